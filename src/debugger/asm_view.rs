@@ -1,22 +1,27 @@
-use std::{collections::HashSet, sync::mpsc::Sender};
+use std::sync::mpsc::Sender;
 
 use crate::gbr::{
     cpu::CpuState,
     game_boy::{DebugEvent, GbrEvent},
+    mbc::MbcState,
 };
 
 use egui::{Label, Sense};
 use egui_extras::{Column, TableBuilder};
 
-use super::debugger::AsmState;
+use super::{breakpoint::Breakpoint, debugger::AsmState, symbols::Symbols};
 
 pub fn show(
     ev_sender: &Sender<GbrEvent>,
     asm: &AsmState,
     cpu: &CpuState,
-    breakpoints: &mut HashSet<u16>,
+    mbc: &MbcState,
+    breakpoints: &[Breakpoint],
+    symbols: &Symbols,
     ui: &mut egui::Ui,
 ) {
+    let resolver = symbols.resolver(mbc);
+
     let text_height = egui::TextStyle::Body.resolve(ui.style()).size;
 
     egui::ScrollArea::horizontal()
@@ -32,17 +37,16 @@ pub fn show(
                 .min_scrolled_height(0.0)
                 .body(|body| {
                     body.rows(text_height, asm.len(), |index, mut row| {
-                        let (pc, instruction) = asm.iter().nth(index).as_ref().unwrap();
+                        let (addr, instruction) = asm.iter().nth(index).as_ref().unwrap();
 
                         row.col(|ui| {
-                            if breakpoints.contains(pc) {
+                            if breakpoints.iter().any(|b| b.addr == *addr) {
                                 if ui
                                     .add(Label::new("*").sense(Sense::click()))
                                     .double_clicked()
                                 {
-                                    breakpoints.remove(pc);
                                     ev_sender
-                                        .send(GbrEvent::Debug(DebugEvent::SetBreakpoint(*pc)))
+                                        .send(GbrEvent::Debug(DebugEvent::ClearBreakpoint(*addr)))
                                         .unwrap();
                                 }
                             } else {
@@ -50,26 +54,39 @@ pub fn show(
                                     .add(Label::new(" ").sense(Sense::click()))
                                     .double_clicked()
                                 {
-                                    breakpoints.insert(*pc);
                                     ev_sender
-                                        .send(GbrEvent::Debug(DebugEvent::ClearBreakpoint(*pc)))
+                                        .send(GbrEvent::Debug(DebugEvent::SetBreakpoint {
+                                            addr: *addr,
+                                            condition: None,
+                                            ignore_count: 0,
+                                        }))
                                         .unwrap();
                                 }
                             }
                         });
 
-                        let cursor = if *pc == cpu.pc { ">" } else { "" };
+                        let cursor = if addr.addr == cpu.pc { ">" } else { "" };
 
                         row.col(|ui| {
                             ui.label(cursor);
                         });
 
                         let instr_label = match instruction {
-                            Some(instr) => format!("{:#06X}: {}", *pc, instr),
-                            None => format!("{:#06X}: Unknonwn", *pc),
+                            Some(instr) => format!("{}", instr.display(addr.addr, &resolver)),
+                            None => "Data".to_string(),
+                        };
+                        let instr_label = match symbols.name(addr) {
+                            Some(name) => format!("{} <{}>: {}", addr, name, instr_label),
+                            None => format!("{}: {}", addr, instr_label),
                         };
                         row.col(|ui| {
-                            ui.label(instr_label);
+                            ui.add(Label::new(instr_label).sense(Sense::click()))
+                                .context_menu(|ui| {
+                                    if ui.button("Run to cursor").clicked() {
+                                        ev_sender.send(GbrEvent::RunTo(*addr)).unwrap();
+                                        ui.close_menu();
+                                    }
+                                });
                         });
                     });
                 });
@@ -0,0 +1,87 @@
+use crate::gbr::memory_map::BankedAddress;
+
+use super::expression::{EvalContext, Expression};
+
+#[derive(Clone)]
+pub struct Breakpoint {
+    pub addr: BankedAddress,
+    pub condition: Option<Expression>,
+    pub ignore_count: u32,
+    pub hit_count: u32,
+}
+
+impl Breakpoint {
+    pub fn new(addr: BankedAddress, condition: Option<Expression>, ignore_count: u32) -> Self {
+        Self {
+            addr,
+            condition,
+            ignore_count,
+            hit_count: 0,
+        }
+    }
+
+    /// Whether the condition holds, breaking on evaluation errors.
+    pub fn condition_holds(&self, ctx: &EvalContext) -> bool {
+        match &self.condition {
+            Some(condition) => condition.is_true(ctx).unwrap_or_else(|e| {
+                log::warn!("Breakpoint condition \"{}\" failed: {}", condition, e);
+                true
+            }),
+            None => true,
+        }
+    }
+
+    /// Count a hit if the condition holds.
+    ///
+    /// Returns whether execution should stop, that is once the first
+    /// `ignore_count` hits are skipped.
+    pub fn hit(&mut self, ctx: &EvalContext) -> bool {
+        if !self.condition_holds(ctx) {
+            return false;
+        }
+
+        self.hit_count += 1;
+        self.hit_count > self.ignore_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        debugger::expression::{EvalContext, Expression},
+        gbr::{bus::Bus, cpu::CpuState, mbc::MbcState, memory_map::BankedAddress},
+    };
+
+    use super::Breakpoint;
+
+    #[test]
+    fn conditional_hit_count() {
+        let bus = Bus::from_rom(&vec![0; 0x8000]).unwrap();
+        let mbc = MbcState::default();
+        let mut cpu = CpuState::default();
+
+        let condition = Expression::parse("B == 0").unwrap();
+        let mut breakpoint = Breakpoint::new(BankedAddress::new(0, 0x0150), Some(condition), 2);
+
+        for b in (0..5).rev() {
+            cpu.bc = b << 8;
+            let ctx = EvalContext {
+                cpu: &cpu,
+                bus: &bus,
+                mbc: &mbc,
+            };
+
+            assert!(!breakpoint.hit(&ctx));
+        }
+        assert_eq!(breakpoint.hit_count, 1);
+
+        let ctx = EvalContext {
+            cpu: &cpu,
+            bus: &bus,
+            mbc: &mbc,
+        };
+        assert!(!breakpoint.hit(&ctx));
+        assert!(breakpoint.hit(&ctx));
+        assert_eq!(breakpoint.hit_count, 3);
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::mpsc::Sender;
+
+use crate::gbr::{
+    game_boy::{DebugEvent, GbrEvent},
+    mbc::MbcState,
+    memory_map::BankedAddress,
+};
+
+use super::{
+    breakpoint::Breakpoint, expression::Expression, symbols::Symbols, watchpoints_view::parse_hex,
+};
+
+// Parse a symbol name, "bank:addr", or a plain address in the currently mapped bank
+fn parse_address(text: &str, mbc: &MbcState, symbols: &Symbols) -> Option<BankedAddress> {
+    if let Some(addr) = symbols.addr(text.trim()) {
+        return Some(addr);
+    }
+
+    match text.split_once(':') {
+        Some((bank, addr)) => Some(BankedAddress::new(parse_hex(bank)?, parse_hex(addr)?)),
+        None => Some(BankedAddress::resolve(parse_hex(text)?, mbc)),
+    }
+}
+
+#[derive(Default)]
+pub struct BreakpointsView {
+    addr: String,
+    condition: String,
+    ignore_count: String,
+}
+
+impl BreakpointsView {
+    fn parse(&self, mbc: &MbcState, symbols: &Symbols) -> Result<DebugEvent, String> {
+        let addr = parse_address(&self.addr, mbc, symbols).ok_or("Invalid address")?;
+
+        let condition = if self.condition.trim().is_empty() {
+            None
+        } else {
+            Expression::parse(&self.condition).map_err(|e| e.to_string())?;
+            Some(self.condition.clone())
+        };
+
+        let ignore_count = if self.ignore_count.trim().is_empty() {
+            0
+        } else {
+            self.ignore_count
+                .trim()
+                .parse()
+                .map_err(|_| "Invalid ignore count")?
+        };
+
+        Ok(DebugEvent::SetBreakpoint {
+            addr,
+            condition,
+            ignore_count,
+        })
+    }
+
+    pub fn show(
+        &mut self,
+        ev_sender: &Sender<GbrEvent>,
+        breakpoints: &[Breakpoint],
+        mbc: &MbcState,
+        symbols: &Symbols,
+        ui: &mut egui::Ui,
+    ) {
+        egui::Grid::new("breakpoint_form").show(ui, |ui| {
+            ui.label("Address (bank:addr or symbol):");
+            ui.text_edit_singleline(&mut self.addr);
+            ui.end_row();
+
+            ui.label("Condition:");
+            ui.text_edit_singleline(&mut self.condition);
+            ui.end_row();
+
+            ui.label("Ignore hits:");
+            ui.text_edit_singleline(&mut self.ignore_count);
+            ui.end_row();
+        });
+
+        match self.parse(mbc, symbols) {
+            Ok(ev) => {
+                if ui.button("Add").clicked() {
+                    ev_sender.send(GbrEvent::Debug(ev)).unwrap();
+                }
+            }
+            Err(e) => {
+                ui.add_enabled(false, egui::Button::new("Add"));
+                ui.label(e);
+            }
+        }
+
+        ui.separator();
+
+        for breakpoint in breakpoints {
+            ui.horizontal(|ui| {
+                if ui.small_button("x").clicked() {
+                    ev_sender
+                        .send(GbrEvent::Debug(DebugEvent::ClearBreakpoint(
+                            breakpoint.addr,
+                        )))
+                        .unwrap();
+                }
+
+                let mut label = format!("{}", breakpoint.addr);
+                if let Some(name) = symbols.name(&breakpoint.addr) {
+                    label += &format!(" <{}>", name);
+                }
+                if let Some(condition) = &breakpoint.condition {
+                    label += &format!(" if {}", condition);
+                }
+                if breakpoint.ignore_count > 0 {
+                    label += &format!(", ignore {}", breakpoint.ignore_count);
+                }
+                label += &format!(", hits: {}", breakpoint.hit_count);
+
+                ui.label(label);
+            });
+        }
+    }
+}
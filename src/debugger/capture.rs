@@ -0,0 +1,340 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    imageops::{self, FilterType},
+    Delay, Frame, ImageResult, RgbaImage,
+};
+
+use crate::gbr::{
+    apu::{StereoSample, SAMPLE_RATE},
+    game_boy::CapturedFrame,
+    ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    speed::FRAME_RATE,
+};
+
+/// Directory screenshots, recordings and exported images are saved to.
+pub const DEFAULT_DIR: &str = "captures";
+// Frames waiting to be written, the emulator waits for a recorder falling behind
+const CAPTURE_QUEUE: usize = 60;
+
+// GIF viewers slow down faster animations, only every other frame is kept
+const GIF_FRAME_STEP: u64 = 2;
+const GIF_QUANTIZATION_SPEED: i32 = 10;
+
+const WAV_HEADER_SIZE: u32 = 44;
+const WAV_CHANNELS: u16 = 2;
+const WAV_SAMPLE_BYTES: u16 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureFormat {
+    Gif,
+    PngFrames,
+}
+
+/// Screen buffer as an image, scaled up by an integer factor.
+pub fn screen_image(buffer: &[u8], scale: u32) -> RgbaImage {
+    let img = RgbaImage::from_raw(SCREEN_WIDTH, SCREEN_HEIGHT, buffer.to_vec())
+        .expect("screen buffer size");
+
+    if scale > 1 {
+        imageops::resize(
+            &img,
+            SCREEN_WIDTH * scale,
+            SCREEN_HEIGHT * scale,
+            FilterType::Nearest,
+        )
+    } else {
+        img
+    }
+}
+
+// First free <prefix>_NNN<extension> path in `dir`
+fn next_path(dir: &Path, prefix: &str, extension: &str) -> PathBuf {
+    (1..)
+        .map(|i| dir.join(format!("{}_{:03}{}", prefix, i, extension)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Save `img` as the next <prefix>_NNN.png in `dir`.
+pub fn save_image(dir: &Path, prefix: &str, img: &RgbaImage) -> ImageResult<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+
+    let path = next_path(dir, prefix, ".png");
+    img.save(&path)?;
+    Ok(path)
+}
+
+// Centiseconds from the first GIF frame to `frame`, GIF delays are in centiseconds
+fn gif_time(frame: u64) -> u64 {
+    (frame as f64 * GIF_FRAME_STEP as f64 * 100.0 / FRAME_RATE).round() as u64
+}
+
+/// 16-bit stereo PCM WAV file, the sizes in its header are written when it
+/// is dropped.
+struct WavWriter {
+    file: BufWriter<File>,
+    samples: u32,
+}
+
+impl WavWriter {
+    fn create(path: &Path) -> io::Result<Self> {
+        let mut wav = Self {
+            file: BufWriter::new(File::create(path)?),
+            samples: 0,
+        };
+        wav.write_header()?;
+
+        Ok(wav)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let block_align = WAV_CHANNELS * WAV_SAMPLE_BYTES;
+        let data_size = self.samples * block_align as u32;
+
+        let file = &mut self.file;
+        file.write_all(b"RIFF")?;
+        file.write_all(&(WAV_HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&WAV_CHANNELS.to_le_bytes())?;
+        file.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        file.write_all(&(SAMPLE_RATE * block_align as u32).to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&(8 * WAV_SAMPLE_BYTES).to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&data_size.to_le_bytes())
+    }
+
+    fn write(&mut self, samples: &[StereoSample]) -> io::Result<()> {
+        for level in samples.iter().flatten() {
+            self.file.write_all(&level.to_le_bytes())?;
+        }
+        self.samples += samples.len() as u32;
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.flush()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::error!("Failed to finish the audio track: {}", e);
+        }
+    }
+}
+
+enum Output {
+    Gif(GifEncoder<BufWriter<File>>),
+    // Directory of frame_NNNNNN.png files
+    PngFrames(PathBuf),
+}
+
+/// Recording of the emulated frames, each one is passed to `write`.
+///
+/// The audio track is saved next to a GIF with the same name, and as
+/// audio.wav in a PNG frames directory.
+pub struct Recorder {
+    output: Output,
+    audio: WavWriter,
+    scale: u32,
+    frames: u64,
+}
+
+impl Recorder {
+    pub fn new(path: &Path, format: CaptureFormat, scale: u32) -> ImageResult<Self> {
+        let (output, audio) = match format {
+            CaptureFormat::Gif => {
+                let file = BufWriter::new(File::create(path)?);
+                let mut encoder = GifEncoder::new_with_speed(file, GIF_QUANTIZATION_SPEED);
+                encoder.set_repeat(Repeat::Infinite)?;
+                (Output::Gif(encoder), path.with_extension("wav"))
+            }
+            CaptureFormat::PngFrames => {
+                std::fs::create_dir_all(path)?;
+                (
+                    Output::PngFrames(path.to_path_buf()),
+                    path.join("audio.wav"),
+                )
+            }
+        };
+
+        Ok(Self {
+            output,
+            audio: WavWriter::create(&audio)?,
+            scale,
+            frames: 0,
+        })
+    }
+
+    pub fn write(&mut self, captured: &CapturedFrame) -> ImageResult<()> {
+        let frame = self.frames;
+        self.frames += 1;
+
+        // Every frame has its audio, even the ones left out of a GIF
+        self.audio.write(&captured.samples)?;
+        let buffer = &captured.screen;
+
+        match &mut self.output {
+            Output::Gif(_) if !frame.is_multiple_of(GIF_FRAME_STEP) => Ok(()),
+            Output::Gif(encoder) => {
+                let index = frame / GIF_FRAME_STEP;
+                // Alternate rounded delays so the total duration stays exact
+                let delay = gif_time(index + 1) - gif_time(index);
+                encoder.encode_frame(Frame::from_parts(
+                    screen_image(buffer, self.scale),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(delay as u32 * 10, 1),
+                ))
+            }
+            Output::PngFrames(dir) => {
+                screen_image(buffer, self.scale).save(dir.join(format!("frame_{:06}.png", frame)))
+            }
+        }
+    }
+}
+
+/// Record the frames sent on the returned channel to a new file in `dir`,
+/// in a thread that finishes the file once the channel is closed.
+pub fn start_recording(
+    dir: &Path,
+    format: CaptureFormat,
+    scale: u32,
+) -> ImageResult<(PathBuf, flume::Sender<CapturedFrame>)> {
+    std::fs::create_dir_all(dir)?;
+
+    let path = match format {
+        CaptureFormat::Gif => next_path(dir, "recording", ".gif"),
+        CaptureFormat::PngFrames => next_path(dir, "recording", ""),
+    };
+    let mut recorder = Recorder::new(&path, format, scale)?;
+    let (sender, receiver) = flume::bounded::<CapturedFrame>(CAPTURE_QUEUE);
+
+    let output = path.clone();
+    std::thread::spawn(move || {
+        for frame in receiver.iter() {
+            // Dropping the receiver stops the emulator from sending frames
+            if let Err(e) = recorder.write(&frame) {
+                log::error!("Recording {} stopped: {}", output.display(), e);
+                return;
+            }
+        }
+
+        log::info!(
+            "Recording of {} frames saved to {}",
+            recorder.frames,
+            output.display()
+        );
+    });
+
+    Ok((path, sender))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use image::{codecs::gif::GifDecoder, AnimationDecoder};
+
+    use crate::gbr::{
+        game_boy::CapturedFrame,
+        ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    };
+
+    use super::{gif_time, screen_image, CaptureFormat, Recorder};
+
+    // Samples output during a frame
+    const FRAME_SAMPLES: usize = 738;
+
+    fn screen(color: u8) -> Vec<u8> {
+        let mut buffer = vec![0xFF; (SCREEN_WIDTH * SCREEN_HEIGHT * 4) as usize];
+        // Top left pixel
+        buffer[..3].fill(color);
+        buffer
+    }
+
+    fn frame(color: u8) -> CapturedFrame {
+        CapturedFrame {
+            screen: screen(color),
+            samples: vec![[color as i16, -(color as i16)]; FRAME_SAMPLES],
+        }
+    }
+
+    #[test]
+    fn upscaling() {
+        let img = screen_image(&screen(0x40), 3);
+
+        assert_eq!(img.dimensions(), (SCREEN_WIDTH * 3, SCREEN_HEIGHT * 3));
+        assert_eq!(img.get_pixel(2, 2).0, [0x40, 0x40, 0x40, 0xFF]);
+        assert_eq!(img.get_pixel(3, 0).0, [0xFF; 4]);
+    }
+
+    #[test]
+    fn gif_delays() {
+        // About 3.35 cs per kept frame, alternating 3 and 4
+        let delays: Vec<u64> = (0..4).map(|i| gif_time(i + 1) - gif_time(i)).collect();
+        assert_eq!(delays, [3, 4, 3, 3]);
+        assert_eq!(gif_time(30), 100);
+    }
+
+    #[test]
+    fn recordings() {
+        let dir = std::env::temp_dir().join(format!("gbr_capture_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let gif = dir.join("recording.gif");
+        let mut recorder = Recorder::new(&gif, CaptureFormat::Gif, 2).unwrap();
+        for i in 0..6 {
+            recorder.write(&frame(i * 0x20)).unwrap();
+        }
+        drop(recorder);
+
+        // Audio of all the frames, GIF ones left out included
+        let wav = std::fs::read(dir.join("recording.wav")).unwrap();
+        let data_size = (6 * FRAME_SAMPLES * 4) as u32;
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(wav[22..24], 2u16.to_le_bytes());
+        assert_eq!(wav[24..28], 44_100u32.to_le_bytes());
+        assert_eq!(wav[40..44], data_size.to_le_bytes());
+        assert_eq!(wav.len(), 44 + data_size as usize);
+        // Last frame, left then right
+        assert_eq!(wav[wav.len() - 4..], [0xA0, 0x00, 0x60, 0xFF]);
+
+        let frames = GifDecoder::new(File::open(&gif).unwrap())
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            frames[0].buffer().dimensions(),
+            (SCREEN_WIDTH * 2, SCREEN_HEIGHT * 2)
+        );
+
+        let png = dir.join("frames");
+        let mut recorder = Recorder::new(&png, CaptureFormat::PngFrames, 1).unwrap();
+        for i in 0..3 {
+            recorder.write(&frame(i)).unwrap();
+        }
+        drop(recorder);
+        assert!(png.join("frame_000002.png").exists());
+        assert!(png.join("audio.wav").exists());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
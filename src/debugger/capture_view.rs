@@ -0,0 +1,108 @@
+use std::{path::PathBuf, sync::mpsc::Sender};
+
+use crate::gbr::{game_boy::GbrEvent, ppu::ScreenBuffer};
+
+use super::{
+    capture::{save_image, start_recording, CaptureFormat, DEFAULT_DIR},
+    lcd_effects::{self, LcdEffects},
+};
+
+pub struct CaptureView {
+    dir: String,
+    format: CaptureFormat,
+    scale: u32,
+    // Last two frames shown on screen
+    screen: Option<ScreenBuffer>,
+    previous: Option<ScreenBuffer>,
+    recording: Option<PathBuf>,
+    message: String,
+}
+
+impl Default for CaptureView {
+    fn default() -> Self {
+        Self {
+            dir: DEFAULT_DIR.to_string(),
+            format: CaptureFormat::Gif,
+            scale: 2,
+            screen: None,
+            previous: None,
+            recording: None,
+            message: String::new(),
+        }
+    }
+}
+
+impl CaptureView {
+    pub fn set_screen(&mut self, screen: ScreenBuffer) {
+        self.previous = self.screen.replace(screen);
+    }
+
+    // Saved as shown on screen, with the LCD effects
+    fn screenshot(&mut self, effects: LcdEffects) {
+        let Some(screen) = &self.screen else {
+            self.message = "No frame rendered yet".to_string();
+            return;
+        };
+
+        let img = lcd_effects::apply(effects, screen, self.previous.as_deref(), self.scale);
+        self.message = match save_image(&PathBuf::from(&self.dir), "screenshot", &img) {
+            Ok(path) => format!("Saved {}", path.display()),
+            Err(e) => e.to_string(),
+        };
+    }
+
+    fn record(&mut self, ev_sender: &Sender<GbrEvent>) {
+        match start_recording(&PathBuf::from(&self.dir), self.format, self.scale) {
+            Ok((path, frames)) => {
+                ev_sender.send(GbrEvent::Capture(Some(frames))).unwrap();
+                self.message = format!("Recording to {}", path.display());
+                self.recording = Some(path);
+            }
+            Err(e) => self.message = e.to_string(),
+        }
+    }
+
+    fn stop(&mut self, ev_sender: &Sender<GbrEvent>) {
+        ev_sender.send(GbrEvent::Capture(None)).unwrap();
+
+        if let Some(path) = self.recording.take() {
+            self.message = format!("Saved {}", path.display());
+        }
+    }
+
+    pub fn show(&mut self, effects: LcdEffects, ev_sender: &Sender<GbrEvent>, ui: &mut egui::Ui) {
+        let recording = self.recording.is_some();
+
+        ui.add_enabled_ui(!recording, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Directory:");
+                ui.text_edit_singleline(&mut self.dir);
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Scale:");
+                ui.add(egui::DragValue::new(&mut self.scale).clamp_range(1..=8));
+
+                ui.label("Format:");
+                ui.radio_value(&mut self.format, CaptureFormat::Gif, "GIF");
+                ui.radio_value(&mut self.format, CaptureFormat::PngFrames, "PNG frames");
+            });
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Screenshot").clicked() {
+                self.screenshot(effects);
+            }
+
+            if recording {
+                if ui.button("Stop recording").clicked() {
+                    self.stop(ev_sender);
+                }
+            } else if ui.button("Record").clicked() {
+                self.record(ev_sender);
+            }
+        });
+
+        ui.label(&self.message);
+    }
+}
@@ -0,0 +1,112 @@
+use serde::Deserialize;
+
+use crate::gbr::ppu::{palette::ColorScheme, rgba::Rgba};
+
+/// User palettes of the config file, each one with four "#RRGGBB" colours from white to black:
+///
+/// ```toml
+/// [[palette]]
+/// name = "Autumn"
+/// bg = ["#FFFFFF", "#F7B58C", "#84739C", "#181010"]
+/// # Optional, OBJ0 defaults to BG and OBJ1 to OBJ0
+/// obj0 = ["#FFFFFF", "#FF8484", "#943A3A", "#000000"]
+/// ```
+#[derive(Deserialize)]
+struct PaletteFile {
+    #[serde(default)]
+    palette: Vec<PaletteEntry>,
+}
+
+#[derive(Deserialize)]
+struct PaletteEntry {
+    name: String,
+    bg: [String; 4],
+    obj0: Option<[String; 4]>,
+    obj1: Option<[String; 4]>,
+}
+
+fn parse_color(text: &str) -> Result<Rgba, String> {
+    text.strip_prefix('#')
+        .filter(|hex| hex.len() == 6)
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .map(Rgba::from_rgb)
+        .ok_or_else(|| format!("invalid colour {}, expected #RRGGBB", text))
+}
+
+fn parse_colors(colors: &[String; 4]) -> Result<[Rgba; 4], String> {
+    let mut rgba = [Rgba::default(); 4];
+    for (rgba, text) in rgba.iter_mut().zip(colors) {
+        *rgba = parse_color(text)?;
+    }
+    Ok(rgba)
+}
+
+pub fn parse(text: &str) -> Result<Vec<(String, ColorScheme)>, String> {
+    let file: PaletteFile = toml::from_str(text).map_err(|e| e.to_string())?;
+
+    file.palette
+        .iter()
+        .map(|entry| {
+            let bg = parse_colors(&entry.bg)?;
+            let obj0 = entry.obj0.as_ref().map(parse_colors).transpose()?;
+            let obj1 = entry.obj1.as_ref().map(parse_colors).transpose()?;
+
+            let obj0 = obj0.unwrap_or(bg);
+            let obj1 = obj1.unwrap_or(obj0);
+
+            let colors = ColorScheme {
+                bg,
+                obj: [obj0, obj1],
+            };
+            Ok((entry.name.clone(), colors))
+        })
+        .collect()
+}
+
+/// Built-in colour schemes.
+pub fn presets() -> Vec<(String, ColorScheme)> {
+    ColorScheme::presets()
+        .into_iter()
+        .map(|(name, colors)| (name.to_string(), colors))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gbr::ppu::rgba::Rgba;
+
+    use super::parse;
+
+    #[test]
+    fn palettes_file() {
+        let schemes = parse(
+            "[[palette]]\n\
+             name = \"Autumn\"\n\
+             bg = [\"#FFFFFF\", \"#F7B58C\", \"#84739C\", \"#181010\"]\n\
+             obj0 = [\"#FFFFFF\", \"#FF8484\", \"#943A3A\", \"#000000\"]\n\
+             [[palette]]\n\
+             name = \"Mono\"\n\
+             bg = [\"#ffffff\", \"#aaaaaa\", \"#555555\", \"#000000\"]\n",
+        )
+        .unwrap();
+
+        assert_eq!(schemes.len(), 2);
+
+        let (name, autumn) = &schemes[0];
+        assert_eq!(name, "Autumn");
+        assert_eq!(autumn.bg[1], Rgba::from_rgb(0xF7B58C));
+        assert_eq!(autumn.obj[0][1], Rgba::from_rgb(0xFF8484));
+        // OBJ1 falls back to OBJ0
+        assert_eq!(autumn.obj[1], autumn.obj[0]);
+
+        let (_, mono) = &schemes[1];
+        assert_eq!(mono.obj, [mono.bg; 2]);
+        assert_eq!(mono.bg[2].rgba, [0x55, 0x55, 0x55, 255]);
+
+        assert!(parse(
+            "[[palette]]\nname = \"Bad\"\nbg = [\"#FFF\", \"#AAA\", \"#555\", \"#000\"]\n"
+        )
+        .is_err());
+        assert!(parse("[[palette]]\nname = \"Short\"\nbg = [\"#FFFFFF\"]\n").is_err());
+    }
+}
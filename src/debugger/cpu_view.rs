@@ -22,5 +22,6 @@ pub fn show(state: &mut CpuState, ui: &mut egui::Ui) {
     ui.horizontal_wrapped(|ui| {
         ui.checkbox(&mut state.ime, "IME");
         ui.checkbox(&mut state.halted, "Halted");
+        ui.checkbox(&mut state.stopped, "Stopped");
     });
 }
@@ -1,21 +1,68 @@
-use std::{collections::HashSet, sync::RwLockWriteGuard};
+use std::{collections::HashMap, sync::RwLockWriteGuard};
 
 use enum_primitive::FromPrimitive;
 use flume::{Receiver, Sender};
 
-use crate::gbr::game_boy::{self, DebugEvent, GameBoy, GbState};
+use crate::gbr::game_boy::{self, DebugEvent, GameBoy, GbState, MemoryBanks};
 use crate::gbr::{
     bus::BusAccess,
     instruction::{opcode::Opcode, Instruction},
+    mbc::MbcState,
+    memory_map::BankedAddress,
+    watchpoint::WatchOwner,
     GbError,
 };
 
-pub type AsmState = Vec<(u16, Option<Instruction>)>;
+use super::{
+    breakpoint::Breakpoint,
+    disassembler::Disassembly,
+    expression::{EvalContext, Expression},
+    history::{History, HistoryState},
+};
+
+pub type AsmState = Vec<(BankedAddress, Option<Instruction>)>;
+
+/// Content of the whole address space, with the cartridge banks it was read from.
+#[derive(Clone)]
+pub struct MemoryState {
+    pub bytes: Vec<u8>,
+    pub rom_bank: u16,
+    pub ram_bank: u16,
+}
+
+impl MemoryState {
+    pub fn banked(&self, addr: u16) -> BankedAddress {
+        let mbc = MbcState {
+            active_rom_bank: self.rom_bank,
+            active_ram_bank: self.ram_bank,
+            ..Default::default()
+        };
+
+        BankedAddress::resolve(addr, &mbc)
+    }
+}
+
+/// Receivers of the states sent by the debugger after each run.
+pub struct DebuggerStates {
+    pub gb_state: Receiver<GbState>,
+    pub asm_state: Receiver<AsmState>,
+    pub breakpoints: Receiver<Vec<Breakpoint>>,
+    pub history: Receiver<HistoryState>,
+    pub memory: Receiver<MemoryState>,
+}
 
 pub struct Debugger {
     gb_state: (Sender<GbState>, Receiver<GbState>),
     asm_state: (Sender<AsmState>, Receiver<AsmState>),
-    breakpoints: HashSet<u16>,
+    breakpoints_state: (Sender<Vec<Breakpoint>>, Receiver<Vec<Breakpoint>>),
+    breakpoints: HashMap<BankedAddress, Breakpoint>,
+    history_state: (Sender<HistoryState>, Receiver<HistoryState>),
+    history: History,
+    memory_state: (Sender<MemoryState>, Receiver<MemoryState>),
+    memory_banks: Option<MemoryBanks>,
+    // Clock cycles of the last memory read, None to read again
+    memory_read_at: Option<u64>,
+    static_asm: Option<Disassembly>,
 }
 
 impl Debugger {
@@ -23,16 +70,108 @@ impl Debugger {
         Debugger {
             gb_state: flume::bounded(1),
             asm_state: flume::bounded(1),
-            breakpoints: HashSet::new(),
+            breakpoints_state: flume::bounded(1),
+            breakpoints: HashMap::new(),
+            history_state: flume::bounded(1),
+            history: History::default(),
+            memory_state: flume::bounded(1),
+            memory_banks: None,
+            memory_read_at: None,
+            static_asm: None,
+        }
+    }
+
+    pub fn states(&self) -> DebuggerStates {
+        DebuggerStates {
+            gb_state: self.gb_state.1.clone(),
+            asm_state: self.asm_state.1.clone(),
+            breakpoints: self.breakpoints_state.1.clone(),
+            history: self.history_state.1.clone(),
+            memory: self.memory_state.1.clone(),
         }
     }
 
-    pub fn gb_state_recv(&self) -> Receiver<GbState> {
-        self.gb_state.1.clone()
+    fn read_memory(gb: &GameBoy, banks: MemoryBanks) -> MemoryState {
+        let mbc = gb.bus().mbc().state();
+        let memory = MemoryState {
+            bytes: vec![],
+            rom_bank: banks.rom.unwrap_or(mbc.active_rom_bank),
+            ram_bank: banks.ram.unwrap_or(mbc.active_ram_bank),
+        };
+
+        let bytes = (0..=u16::MAX)
+            .map(|addr| gb.bus().peek(memory.banked(addr)))
+            .collect();
+
+        MemoryState { bytes, ..memory }
+    }
+
+    // Breakpoint at PC whose condition holds, without counting a hit
+    fn at_breakpoint(breakpoints: &HashMap<BankedAddress, Breakpoint>, gb: &mut GameBoy) -> bool {
+        if gb.take_watchpoint_hit().is_some() {
+            return true;
+        }
+
+        let mbc = gb.bus().mbc().state();
+        let pc = BankedAddress::resolve(gb.cpu().read_pc(), &mbc);
+
+        breakpoints.get(&pc).is_some_and(|breakpoint| {
+            breakpoint.condition_holds(&EvalContext {
+                cpu: &gb.cpu().state(),
+                bus: gb.bus(),
+                mbc: &mbc,
+            })
+        })
+    }
+
+    fn handle_history_event(&mut self, gb: &mut GameBoy, ev: &DebugEvent) -> Result<(), GbError> {
+        match ev {
+            DebugEvent::RecordHistory(true) => self.history.start(gb),
+            DebugEvent::RecordHistory(false) => self.history.stop(gb),
+            DebugEvent::StepBack => {
+                let stepped = self.history.step_back(gb)?;
+                if !stepped {
+                    log::info!("Reached the oldest recorded step");
+                }
+            }
+            DebugEvent::ReverseContinue => {
+                let breakpoints = &self.breakpoints;
+                if !self
+                    .history
+                    .reverse_continue(gb, |gb| Debugger::at_breakpoint(breakpoints, gb))?
+                {
+                    log::info!("No previous break, reached the oldest recorded step");
+                }
+            }
+            DebugEvent::RewindToLastWrite(addr) => {
+                match self.history.rewind_to_last_write(gb, *addr)? {
+                    Some(record) => log::info!("Last write: {}", record),
+                    None => log::info!("No recorded write to {:#06X}", addr),
+                }
+            }
+            DebugEvent::NewBranch => gb.new_branch(),
+            _ => (),
+        }
+
+        Ok(())
     }
 
-    pub fn asm_state_recv(&self) -> Receiver<AsmState> {
-        self.asm_state.1.clone()
+    fn set_breakpoint(
+        &mut self,
+        addr: BankedAddress,
+        condition: &Option<String>,
+        ignore_count: u32,
+    ) {
+        let condition = match condition.as_deref().map(Expression::parse).transpose() {
+            Ok(condition) => condition,
+            Err(e) => {
+                log::error!("Invalid breakpoint condition: {}", e);
+                return;
+            }
+        };
+
+        self.breakpoints
+            .insert(addr, Breakpoint::new(addr, condition, ignore_count));
     }
 
     fn fetch_instruction(pc: u16, bus: &dyn BusAccess) -> Result<Instruction, GbError> {
@@ -56,29 +195,49 @@ impl Debugger {
         Instruction::decode(opcode, byte, word)
     }
 
-    pub fn disassemble(emu: &RwLockWriteGuard<GameBoy>) -> AsmState {
+    /// Disassemble the instructions from the last executed one.
+    ///
+    /// Data found by the static disassembly is shown as a single line, the
+    /// listing resumes at the next known instruction.
+    pub fn disassemble(&mut self, emu: &RwLockWriteGuard<GameBoy>) -> AsmState {
+        let static_asm = self
+            .static_asm
+            .get_or_insert_with(|| Disassembly::new(emu.bus().mbc().rom()));
+
         let mut disassembly = AsmState::new();
         disassembly.reserve(20);
 
+        let mbc = emu.bus().mbc().state();
         let mut pc = emu.cpu().reg_pc_prev;
+        // Whether the previous instruction was found by the static disassembly
+        let mut known = false;
+
+        while disassembly.len() < 20 {
+            let addr = BankedAddress::resolve(pc, &mbc);
+
+            if known && !static_asm.is_code(addr) {
+                disassembly.push((addr, None));
+
+                match static_asm.next_code(addr) {
+                    Some(next) => pc = next.addr,
+                    None => break,
+                }
+                continue;
+            }
+            known = static_asm.is_code(addr);
 
-        loop {
             let instruction = match Debugger::fetch_instruction(pc, emu.bus()) {
                 Ok(instr) => instr,
                 Err(_) => {
-                    disassembly.push((pc, None));
-                    pc += 1;
+                    disassembly.push((addr, None));
+                    pc = pc.wrapping_add(1);
                     continue;
                 }
             };
 
-            let new_pc = pc + instruction.len() as u16;
-            disassembly.push((pc, Some(instruction)));
+            let new_pc = pc.wrapping_add(instruction.len() as u16);
+            disassembly.push((addr, Some(instruction)));
             pc = new_pc;
-
-            if disassembly.len() >= 20 {
-                break;
-            }
         }
 
         disassembly
@@ -86,24 +245,97 @@ impl Debugger {
 }
 
 impl game_boy::Debugger for Debugger {
-    fn handle_event(&mut self, emu: &RwLockWriteGuard<GameBoy>, ev: &game_boy::DebugEvent) {
+    fn handle_event(&mut self, emu: &mut RwLockWriteGuard<GameBoy>, ev: &game_boy::DebugEvent) {
+        self.memory_read_at = None;
+
         match ev {
-            DebugEvent::SetBreakpoint(pc) => {
-                self.breakpoints.insert(*pc);
+            DebugEvent::SetBreakpoint {
+                addr,
+                condition,
+                ignore_count,
+            } => self.set_breakpoint(*addr, condition, *ignore_count),
+            DebugEvent::ClearBreakpoint(addr) => {
+                self.breakpoints.remove(addr);
+            }
+            DebugEvent::SetWatchpoint(watchpoint) => {
+                emu.watchpoints_mut().add(WatchOwner::Debugger, *watchpoint)
             }
-            DebugEvent::ClearBreakpoint(pc) => {
-                self.breakpoints.remove(pc);
+            DebugEvent::ClearWatchpoint(watchpoint) => {
+                emu.watchpoints_mut().remove(WatchOwner::Debugger, watchpoint)
+            }
+            DebugEvent::RecordHistory(_)
+            | DebugEvent::StepBack
+            | DebugEvent::ReverseContinue
+            | DebugEvent::RewindToLastWrite(_)
+            | DebugEvent::NewBranch => {
+                if let Err(e) = self.handle_history_event(emu, ev) {
+                    log::error!("Time travel failed: {}", e);
+                }
+            }
+            DebugEvent::ViewMemory(banks) => self.memory_banks = *banks,
+            DebugEvent::WriteMemory(addr, value) => {
+                if let Err(e) = emu.bus_mut().write_banked(*addr, *value) {
+                    log::error!("Memory write failed: {}", e);
+                }
             }
             DebugEvent::DumpVram => log::info!("\n{}", emu.ppu().vram_dump()),
         }
     }
 
     fn send_state(&mut self, gb: &RwLockWriteGuard<GameBoy>) {
-        self.gb_state.0.send(gb.collect_state()).ok();
-        self.asm_state.0.send(Debugger::disassemble(gb)).ok();
+        self.gb_state.0.try_send(gb.collect_state()).ok();
+        let asm = self.disassemble(gb);
+        self.asm_state.0.try_send(asm).ok();
+
+        let mut breakpoints: Vec<Breakpoint> = self.breakpoints.values().cloned().collect();
+        breakpoints.sort_by_key(|b| b.addr);
+        self.breakpoints_state.0.try_send(breakpoints).ok();
+
+        self.history_state.0.try_send(self.history.state(gb)).ok();
+
+        // The whole address space is read at the state rate while running,
+        // and once per stop
+        let Some(banks) = self.memory_banks else {
+            return;
+        };
+        if self.memory_read_at != Some(gb.cycles())
+            && self
+                .memory_state
+                .0
+                .try_send(Debugger::read_memory(gb, banks))
+                .is_ok()
+        {
+            self.memory_read_at = Some(gb.cycles());
+        }
+    }
+
+    fn record_step(&mut self, gb: &mut GameBoy) {
+        self.history.record(gb);
     }
 
-    fn should_break(&self, gb: &RwLockWriteGuard<GameBoy>) -> bool {
-        self.breakpoints.contains(&gb.cpu().read_pc())
+    fn should_break(&mut self, gb: &mut GameBoy) -> bool {
+        if let Some(hit) = gb.take_watchpoint_hit() {
+            log::info!("Watchpoint hit: {}", hit);
+            return true;
+        }
+
+        if self.breakpoints.is_empty() {
+            return false;
+        }
+
+        let mbc = gb.bus().mbc().state();
+        let pc = BankedAddress::resolve(gb.cpu().read_pc(), &mbc);
+
+        let Some(breakpoint) = self.breakpoints.get_mut(&pc) else {
+            return false;
+        };
+
+        let ctx = EvalContext {
+            cpu: &gb.cpu().state(),
+            bus: gb.bus(),
+            mbc: &mbc,
+        };
+
+        breakpoint.hit(&ctx)
     }
 }
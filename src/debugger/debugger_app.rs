@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::sync::mpsc::Sender;
+use std::path::Path;
 use std::sync::{Arc, RwLock};
 
 use pixels::{Pixels, SurfaceTexture};
@@ -11,49 +11,55 @@ use winit::{
 };
 use winit_input_helper::WinitInputHelper;
 
-use super::{debugger::Debugger, ui::Ui};
-use crate::gbr::game_boy::{self, GbrEvent, GenericInput, InputType};
-use crate::gbr::joypad::{Buttons, Directions};
-use crate::gbr::{game_boy::GameBoy, ppu};
+#[cfg(feature = "gamepad")]
+use super::gamepad::Gamepad;
+use super::{
+    color_schemes, debugger::Debugger, held_inputs::HeldInputs, keymap::Keymap,
+    lcd_renderer::LcdRenderer, symbols::Symbols, ui::Ui,
+};
+use crate::gbr::game_boy::{self, GenericInput, InputType};
+use crate::gbr::gdb::GdbStub;
+use crate::gbr::{game_boy::GameBoy, ppu, ppu::palette::ColorScheme};
 
 #[derive(Clone)]
 pub struct Settings {
-    keymap: HashMap<VirtualKeyCode, GenericInput>,
+    #[cfg(feature = "gamepad")]
+    keymap: Keymap,
+    keys: HashMap<VirtualKeyCode, GenericInput>,
+    color_schemes: Vec<(String, ColorScheme)>,
 }
 
 impl Settings {
-    pub fn new() -> Self {
-        Self {
-            keymap: Settings::default_keymap(),
-        }
-    }
+    /// Load the settings from the TOML config file at `path`, keeping the defaults if missing.
+    pub fn load(path: &Path) -> Self {
+        let text = std::fs::read_to_string(path)
+            .inspect_err(|_| log::info!("No config file {}, default settings", path.display()))
+            .unwrap_or_default();
 
-    fn keymap(&self) -> &HashMap<VirtualKeyCode, GenericInput> {
-        &self.keymap
+        Self::parse(path, &text)
     }
 
-    fn default_keymap() -> HashMap<VirtualKeyCode, GenericInput> {
-        let mut keymap = HashMap::new();
+    fn parse(path: &Path, text: &str) -> Self {
+        let keymap = Keymap::parse(text)
+            .inspect_err(|e| log::error!("{}: {}", path.display(), e))
+            .unwrap_or_default();
 
-        keymap.insert(VirtualKeyCode::A, GenericInput::Button(Buttons::A));
-        keymap.insert(VirtualKeyCode::Z, GenericInput::Button(Buttons::B));
-        keymap.insert(VirtualKeyCode::Space, GenericInput::Button(Buttons::Select));
-        keymap.insert(VirtualKeyCode::Return, GenericInput::Button(Buttons::Start));
-        keymap.insert(
-            VirtualKeyCode::Right,
-            GenericInput::Direction(Directions::Right),
-        );
-        keymap.insert(
-            VirtualKeyCode::Left,
-            GenericInput::Direction(Directions::Left),
-        );
-        keymap.insert(VirtualKeyCode::Up, GenericInput::Direction(Directions::Up));
-        keymap.insert(
-            VirtualKeyCode::Down,
-            GenericInput::Direction(Directions::Down),
-        );
+        let mut color_schemes = color_schemes::presets();
+        match color_schemes::parse(text) {
+            Ok(user) => color_schemes.extend(user),
+            Err(e) => log::error!("{}: {}", path.display(), e),
+        }
+
+        Self {
+            keys: keymap.keyboard(),
+            #[cfg(feature = "gamepad")]
+            keymap,
+            color_schemes,
+        }
+    }
 
-        keymap
+    fn keymap(&self) -> &HashMap<VirtualKeyCode, GenericInput> {
+        &self.keys
     }
 }
 
@@ -62,22 +68,26 @@ pub struct DebuggerApp {
 }
 
 impl DebuggerApp {
-    pub fn new() -> Self {
+    pub fn new(config: &Path) -> Self {
         env_logger::init();
 
         Self {
-            settings: Settings::new(),
+            settings: Settings::load(config),
         }
     }
 
-    pub fn run(&self, gb: Arc<RwLock<GameBoy>>) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn run(
+        &self,
+        gb: Arc<RwLock<GameBoy>>,
+        symbols: Symbols,
+        gdb: Option<GdbStub>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         let debugger = Debugger::new();
 
-        let gb_state = debugger.gb_state_recv();
-        let asm_state = debugger.asm_state_recv();
+        let states = debugger.states();
         let render_slot = gb.read().unwrap().ppu().render_watch();
 
-        let (ev_sender, emu_state_slot) = game_boy::start_gb_thread(gb, debugger);
+        let (ev_sender, emu_state_slot) = game_boy::start_gb_thread(gb, debugger, gdb);
 
         log::debug!("create window");
         let event_loop = EventLoop::new();
@@ -97,16 +107,23 @@ impl DebuggerApp {
         let surface_texture = SurfaceTexture::new(ppu::SCREEN_WIDTH, ppu::SCREEN_HEIGHT, &window);
         let mut pixels = Pixels::new(ppu::SCREEN_WIDTH, ppu::SCREEN_HEIGHT, surface_texture)?;
 
-        pixels.clear_color(pixels::wgpu::Color {
+        let clear_color = pixels::wgpu::Color {
             r: 0.5,
             g: 0.5,
             b: 0.5,
             a: 1.0,
-        });
+        };
+        pixels.clear_color(clear_color);
+
+        let lcd = LcdRenderer::new(
+            pixels.context(),
+            pixels.render_texture_format(),
+            clear_color,
+        );
 
         let mut ui = Ui::new(
-            gb_state,
-            asm_state,
+            states,
+            symbols,
             ev_sender.clone(),
             emu_state_slot,
             &event_loop,
@@ -116,14 +133,24 @@ impl DebuggerApp {
             pixels.device(),
             pixels.render_texture_format(),
         );
+        ui.set_color_schemes(self.settings.color_schemes.clone());
 
         log::debug!("Starting video loop");
 
         let mut input = WinitInputHelper::new();
+        let mut held = HeldInputs::new(ev_sender);
 
         let settings = self.settings.clone();
 
+        #[cfg(feature = "gamepad")]
+        let mut gamepad = Gamepad::new(&settings.keymap);
+
         event_loop.run(move |event, _, control_flow| {
+            #[cfg(feature = "gamepad")]
+            if let Some(gamepad) = gamepad.as_mut() {
+                gamepad.poll(&mut held);
+            }
+
             if input.update(&event) {
                 if input.key_pressed(VirtualKeyCode::Escape)
                     || input.close_requested()
@@ -133,7 +160,7 @@ impl DebuggerApp {
                     return;
                 }
 
-                DebuggerApp::send_key(&input, &ev_sender, &settings);
+                DebuggerApp::send_key(&input, &mut held, &settings);
 
                 if let Some(scale_factor) = input.scale_factor() {
                     if scale_factor > 0.0 {
@@ -156,14 +183,18 @@ impl DebuggerApp {
                 Event::RedrawRequested(_) => {
                     {
                         if let Ok(frame) = render_slot.try_recv() {
+                            lcd.set_previous_frame(&pixels.context().queue, pixels.frame());
                             pixels.frame_mut().copy_from_slice(&frame);
+                            ui.set_screen(frame);
                         }
                     }
 
+                    ui.set_screen_rect(pixels.context().scaling_renderer.clip_rect());
                     ui.prepare(&window);
 
+                    let effects = ui.lcd_effects();
                     let render_result = pixels.render_with(|encoder, render_target, context| {
-                        context.scaling_renderer.render(encoder, render_target);
+                        lcd.render(encoder, render_target, context, effects);
 
                         ui.render(encoder, render_target, context);
 
@@ -182,18 +213,62 @@ impl DebuggerApp {
         });
     }
 
-    fn send_key(input: &WinitInputHelper, ev_sender: &Sender<GbrEvent>, settings: &Settings) {
+    fn send_key(input: &WinitInputHelper, held: &mut HeldInputs, settings: &Settings) {
         for (key, button) in settings.keymap() {
             if input.key_pressed(*key) {
-                ev_sender
-                    .send(GbrEvent::Input(InputType::Pressed(button.clone())))
-                    .ok();
+                held.send(InputType::Pressed(button.clone()));
             }
             if input.key_released(*key) {
-                ev_sender
-                    .send(GbrEvent::Input(InputType::Released(button.clone())))
-                    .ok();
+                held.send(InputType::Released(button.clone()));
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use winit::event::VirtualKeyCode;
+
+    use crate::gbr::{
+        game_boy::GenericInput,
+        joypad::Buttons,
+        ppu::{palette::ColorScheme, rgba::Rgba},
+    };
+
+    use super::Settings;
+
+    #[test]
+    fn config_file() {
+        let settings = Settings::parse(
+            Path::new("gbr.toml"),
+            "[keyboard]\n\
+             A = \"X\"\n\
+             [[palette]]\n\
+             name = \"Mono\"\n\
+             bg = [\"#FFFFFF\", \"#AAAAAA\", \"#555555\", \"#000000\"]\n",
+        );
+
+        assert_eq!(
+            settings.keys.get(&VirtualKeyCode::X),
+            Some(&GenericInput::Button(Buttons::A))
+        );
+
+        let presets = ColorScheme::presets().len();
+        assert_eq!(settings.color_schemes.len(), presets + 1);
+        let (name, mono) = &settings.color_schemes[presets];
+        assert_eq!(name, "Mono");
+        assert_eq!(mono.bg[1], Rgba::from_rgb(0xAAAAAA));
+    }
+
+    #[test]
+    fn missing_file() {
+        let path = std::env::temp_dir().join("gbr_no_such_config.toml");
+        let settings = Settings::load(&path);
+
+        assert_eq!(settings.keys.len(), 8);
+        assert_eq!(settings.color_schemes.len(), ColorScheme::presets().len());
+        assert!(!path.exists());
+    }
+}
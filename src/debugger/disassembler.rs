@@ -0,0 +1,636 @@
+use std::{collections::BTreeMap, fmt::Write, sync::Arc};
+
+use enum_primitive::FromPrimitive;
+
+use crate::gbr::{
+    instruction::opcode::{CbOpcode, Opcode},
+    memory_map::BankedAddress,
+};
+
+const BANK_SIZE: usize = 0x4000;
+
+const ENTRY_POINT: u16 = 0x0100;
+const HEADER_START: u16 = 0x0104;
+const HEADER_END: u16 = 0x014F;
+
+// Writes to this range select the ROM bank mapped at 0x4000
+const BANK_SELECT_START: u16 = 0x2000;
+const BANK_SELECT_END: u16 = 0x3FFF;
+
+// Runs of the same data byte at least this long are emitted as `ds`
+const FILL_MIN_LEN: usize = 16;
+const DATA_BYTES_PER_LINE: usize = 8;
+
+const VECTORS: [(u16, &str); 14] = [
+    (0x0000, "Rst00"),
+    (0x0008, "Rst08"),
+    (0x0010, "Rst10"),
+    (0x0018, "Rst18"),
+    (0x0020, "Rst20"),
+    (0x0028, "Rst28"),
+    (0x0030, "Rst30"),
+    (0x0038, "Rst38"),
+    (0x0040, "VBlankInterrupt"),
+    (0x0048, "LcdStatInterrupt"),
+    (0x0050, "TimerInterrupt"),
+    (0x0058, "SerialInterrupt"),
+    (0x0060, "JoypadInterrupt"),
+    (ENTRY_POINT, "Entry"),
+];
+
+const REGS: [&str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+const ALU_OPS: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+const CB_OPS: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+
+/// RGBDS syntax of an opcode, with the operand as `n8`, `n16`, `a8` (high RAM
+/// address), `a16` (address), `e8` (relative jump) or `s8` (signed offset).
+fn template(opcode: Opcode) -> String {
+    let template = match opcode {
+        Opcode::Nop => "nop",
+        Opcode::LdBCd16 => "ld bc, n16",
+        Opcode::LdBCA => "ld [bc], a",
+        Opcode::IncBC => "inc bc",
+        Opcode::RlcA => "rlca",
+        Opcode::Lda16SP => "ld [a16], sp",
+        Opcode::AddHLBC => "add hl, bc",
+        Opcode::LdABC => "ld a, [bc]",
+        Opcode::DecBC => "dec bc",
+        Opcode::RrcA => "rrca",
+        Opcode::Stop => "stop",
+        Opcode::LdDEd16 => "ld de, n16",
+        Opcode::LdDEA => "ld [de], a",
+        Opcode::IncDE => "inc de",
+        Opcode::RlA => "rla",
+        Opcode::Jr => "jr e8",
+        Opcode::AddHLDE => "add hl, de",
+        Opcode::LdADE => "ld a, [de]",
+        Opcode::DecDE => "dec de",
+        Opcode::RrA => "rra",
+        Opcode::JrNZ => "jr nz, e8",
+        Opcode::LdHLd16 => "ld hl, n16",
+        Opcode::LdHLincA => "ld [hli], a",
+        Opcode::IncHL => "inc hl",
+        Opcode::DaA => "daa",
+        Opcode::JrZ => "jr z, e8",
+        Opcode::AddHLHL => "add hl, hl",
+        Opcode::LdAHLinc => "ld a, [hli]",
+        Opcode::DecHL => "dec hl",
+        Opcode::Cpl => "cpl",
+        Opcode::JrNC => "jr nc, e8",
+        Opcode::LdSPd16 => "ld sp, n16",
+        Opcode::LdHLdecA => "ld [hld], a",
+        Opcode::IncSP => "inc sp",
+        Opcode::Scf => "scf",
+        Opcode::JrC => "jr c, e8",
+        Opcode::AddHLSP => "add hl, sp",
+        Opcode::LdAHLdec => "ld a, [hld]",
+        Opcode::DecSP => "dec sp",
+        Opcode::Ccf => "ccf",
+        Opcode::Halt => "halt",
+        Opcode::RetNZ => "ret nz",
+        Opcode::PopBC => "pop bc",
+        Opcode::JpNZ => "jp nz, a16",
+        Opcode::Jp => "jp a16",
+        Opcode::CallNZ => "call nz, a16",
+        Opcode::PushBC => "push bc",
+        Opcode::RetZ => "ret z",
+        Opcode::Ret => "ret",
+        Opcode::JpZ => "jp z, a16",
+        Opcode::CallZ => "call z, a16",
+        Opcode::Call => "call a16",
+        Opcode::RetNC => "ret nc",
+        Opcode::PopDE => "pop de",
+        Opcode::JpNC => "jp nc, a16",
+        Opcode::CallNC => "call nc, a16",
+        Opcode::PushDE => "push de",
+        Opcode::RetC => "ret c",
+        Opcode::RetI => "reti",
+        Opcode::JpC => "jp c, a16",
+        Opcode::CallC => "call c, a16",
+        Opcode::Ldha8A => "ldh [a8], a",
+        Opcode::PopHL => "pop hl",
+        Opcode::LdioCA => "ldh [c], a",
+        Opcode::PushHL => "push hl",
+        Opcode::AddSPs8 => "add sp, s8",
+        Opcode::JpHL => "jp hl",
+        Opcode::Lda16A => "ld [a16], a",
+        Opcode::LdhAa8 => "ldh a, [a8]",
+        Opcode::PopAF => "pop af",
+        Opcode::LdAioC => "ldh a, [c]",
+        Opcode::Di => "di",
+        Opcode::PushAF => "push af",
+        // Offset written with its sign, "sp+4" or "sp-4"
+        Opcode::LdHLSPs8 => "ld hl, sps8",
+        Opcode::LdSPHL => "ld sp, hl",
+        Opcode::LdAa16 => "ld a, [a16]",
+        Opcode::Ei => "ei",
+        Opcode::Prefix => unreachable!("prefixed opcodes are decoded separately"),
+        _ => {
+            let opcode = opcode as u8;
+            let reg = REGS[(opcode & 0x07) as usize];
+            let reg_hi = REGS[((opcode >> 3) & 0x07) as usize];
+
+            return match opcode {
+                0x00..=0x3F => match opcode & 0x07 {
+                    0x04 => format!("inc {}", reg_hi),
+                    0x05 => format!("dec {}", reg_hi),
+                    _ => format!("ld {}, n8", reg_hi),
+                },
+                0x40..=0x7F => format!("ld {}, {}", reg_hi, reg),
+                0x80..=0xBF => format!("{} a, {}", ALU_OPS[((opcode >> 3) & 0x07) as usize], reg),
+                _ => match opcode & 0x07 {
+                    0x06 => format!("{} a, n8", ALU_OPS[((opcode >> 3) & 0x07) as usize]),
+                    _ => format!("rst ${:02X}", opcode & 0x38),
+                },
+            };
+        }
+    };
+
+    template.to_string()
+}
+
+fn cb_mnemonic(opcode: CbOpcode) -> String {
+    let opcode = opcode as u8;
+    let reg = REGS[(opcode & 0x07) as usize];
+    let bit = (opcode >> 3) & 0x07;
+
+    match opcode >> 6 {
+        0 => format!("{} {}", CB_OPS[bit as usize], reg),
+        1 => format!("bit {}, {}", bit, reg),
+        2 => format!("res {}, {}", bit, reg),
+        _ => format!("set {}, {}", bit, reg),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Flow {
+    Continue,
+    Stop,
+    // Target of jp/jr, and whether execution can fall through
+    Jump(u16, bool),
+    Call(u16),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Decoded {
+    opcode: Opcode,
+    template: String,
+    len: usize,
+    flow: Flow,
+}
+
+fn decode(addr: u16, bytes: &[u8]) -> Option<Decoded> {
+    let opcode = Opcode::from_u8(*bytes.first()?)?;
+
+    let template = if opcode == Opcode::Prefix {
+        cb_mnemonic(CbOpcode::from_u8(*bytes.get(1)?)?)
+    } else {
+        template(opcode)
+    };
+
+    let len = opcode.length() as usize;
+
+    // STOP is followed by a padding byte that RGBDS emits as 0
+    if bytes.len() < len || (opcode == Opcode::Stop && bytes[1] != 0x00) {
+        return None;
+    }
+
+    let word = || u16::from_le_bytes([bytes[1], bytes[2]]);
+    let relative = || addr.wrapping_add(2).wrapping_add(bytes[1] as i8 as u16);
+
+    let flow = match opcode {
+        Opcode::Jr => Flow::Jump(relative(), false),
+        Opcode::JrNZ | Opcode::JrZ | Opcode::JrNC | Opcode::JrC => Flow::Jump(relative(), true),
+        Opcode::Jp => Flow::Jump(word(), false),
+        Opcode::JpNZ | Opcode::JpZ | Opcode::JpNC | Opcode::JpC => Flow::Jump(word(), true),
+        // RST jumps to the vector in its opcode
+        _ if opcode.is_call() && len == 1 => Flow::Call((opcode as u8 & 0x38) as u16),
+        _ if opcode.is_call() => Flow::Call(word()),
+        Opcode::Ret | Opcode::RetI | Opcode::JpHL => Flow::Stop,
+        _ => Flow::Continue,
+    };
+
+    Some(Decoded {
+        opcode,
+        template,
+        len,
+        flow,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ByteKind {
+    Data,
+    Header,
+    Code,
+    Operand,
+}
+
+/// Static disassembly of a whole ROM.
+///
+/// Code is found by following the control flow from the entry point and the
+/// RST and interrupt vectors, the remaining bytes are data.
+pub struct Disassembly {
+    rom: Arc<[u8]>,
+    kinds: Vec<ByteKind>,
+    labels: BTreeMap<BankedAddress, String>,
+    // Jumps and calls from bank 0 into the switchable bank, with the bank
+    // they lead to if it could be resolved
+    switches: BTreeMap<BankedAddress, Option<u16>>,
+}
+
+impl Disassembly {
+    pub fn new(rom: Arc<[u8]>) -> Self {
+        let mut kinds = vec![ByteKind::Data; rom.len()];
+        if rom.len() > HEADER_END as usize {
+            kinds[HEADER_START as usize..=HEADER_END as usize].fill(ByteKind::Header);
+        }
+
+        let mut disassembly = Self {
+            rom,
+            kinds,
+            labels: BTreeMap::new(),
+            switches: BTreeMap::new(),
+        };
+
+        let mut pending = vec![];
+        for (addr, name) in VECTORS {
+            let addr = BankedAddress::new(0, addr);
+            disassembly.labels.insert(addr, name.to_string());
+            pending.push(addr);
+        }
+
+        while let Some(addr) = pending.pop() {
+            disassembly.trace(addr, &mut pending);
+        }
+
+        disassembly
+    }
+
+    fn banks(&self) -> usize {
+        self.rom.len().div_ceil(BANK_SIZE)
+    }
+
+    fn offset(&self, addr: BankedAddress) -> Option<usize> {
+        let offset = match addr.addr {
+            0x0000..=0x3FFF if addr.bank == 0 => addr.addr as usize,
+            0x4000..=0x7FFF if addr.bank > 0 => {
+                addr.bank as usize * BANK_SIZE + addr.addr as usize - BANK_SIZE
+            }
+            _ => return None,
+        };
+
+        (offset < self.rom.len()).then_some(offset)
+    }
+
+    // Bank of a jump target, from bank 0 the one `switched` in if known
+    fn target(
+        &self,
+        from: BankedAddress,
+        target: u16,
+        switched: Option<u16>,
+    ) -> Option<BankedAddress> {
+        let bank = match target {
+            0x0000..=0x3FFF => 0,
+            0x4000..=0x7FFF if from.bank > 0 => from.bank,
+            // Without a mapper bank 1 is always switched in
+            0x4000..=0x7FFF if self.banks() == 2 => 1,
+            0x4000..=0x7FFF => switched?,
+            _ => return None,
+        };
+
+        ((bank as usize) < self.banks()).then_some(BankedAddress::new(bank, target))
+    }
+
+    fn trace(&mut self, mut addr: BankedAddress, pending: &mut Vec<BankedAddress>) {
+        // Value loaded in A and ROM bank switched in by the instructions
+        // traced so far, if known
+        let mut a = None;
+        let mut switched = None;
+
+        while let Some(offset) = self.offset(addr) {
+            if self.kinds[offset] != ByteKind::Data {
+                return;
+            }
+
+            // Instructions do not cross into another bank
+            let bank_end = (offset / BANK_SIZE + 1) * BANK_SIZE;
+            let bytes = &self.rom[offset..bank_end.min(self.rom.len())];
+            let Some(decoded) = decode(addr.addr, bytes) else {
+                return;
+            };
+
+            let operands = offset + 1..offset + decoded.len;
+            if self.kinds[operands.clone()]
+                .iter()
+                .any(|kind| *kind != ByteKind::Data)
+            {
+                return;
+            }
+
+            self.kinds[offset] = ByteKind::Code;
+            self.kinds[operands].fill(ByteKind::Operand);
+
+            let (target, fall_through, prefix) = match decoded.flow {
+                Flow::Continue => (None, true, ""),
+                Flow::Stop => (None, false, ""),
+                Flow::Jump(target, fall_through) => (Some(target), fall_through, "Jump"),
+                Flow::Call(target) => (Some(target), true, "Call"),
+            };
+
+            let arg = bytes.get(1).copied();
+            let word = || u16::from_le_bytes([bytes[1], bytes[2]]);
+            a = match decoded.opcode {
+                Opcode::LdAd8 => arg,
+                Opcode::Lda16A if (BANK_SELECT_START..=BANK_SELECT_END).contains(&word()) => {
+                    // Bank 0 selects bank 1, as on MBC1
+                    switched = a.map(|a| (a as u16 & 0x1F).max(1));
+                    a
+                }
+                Opcode::Lda16A | Opcode::Ldha8A => a,
+                _ => None,
+            };
+
+            if let Some(target) = target {
+                let resolved = self.target(addr, target, switched);
+                if addr.bank == 0 && (0x4000..=0x7FFF).contains(&target) {
+                    self.switches
+                        .insert(addr, resolved.map(|target| target.bank));
+                }
+
+                // Targets in an unknown bank are left as data
+                if let Some(target) = resolved {
+                    let name = format!("{}_{:03X}_{:04X}", prefix, target.bank, target.addr);
+                    self.labels.entry(target).or_insert(name);
+                    pending.push(target);
+                }
+            }
+
+            // The callee may switch banks
+            if matches!(decoded.flow, Flow::Call(_)) {
+                switched = None;
+            }
+
+            if !fall_through {
+                return;
+            }
+
+            addr.addr = addr.addr.wrapping_add(decoded.len as u16);
+        }
+    }
+
+    /// Whether `addr` holds an instruction, rather than data, as far as the
+    /// static analysis can tell.
+    pub fn is_code(&self, addr: BankedAddress) -> bool {
+        self.offset(addr)
+            .is_some_and(|offset| self.kinds[offset] == ByteKind::Code)
+    }
+
+    /// First instruction after `addr` in the same bank, if any.
+    pub fn next_code(&self, addr: BankedAddress) -> Option<BankedAddress> {
+        let offset = self.offset(addr)?;
+        let bank_end = ((offset / BANK_SIZE + 1) * BANK_SIZE).min(self.rom.len());
+
+        let next = (offset + 1..bank_end).find(|i| self.kinds[*i] == ByteKind::Code)?;
+        Some(BankedAddress::new(
+            addr.bank,
+            addr.addr + (next - offset) as u16,
+        ))
+    }
+
+    // Label of an operand address if it is an instruction, or the hex address
+    fn operand(&self, from: BankedAddress, addr: u16) -> String {
+        let switched = self.switches.get(&from).copied().flatten();
+        self.target(from, addr, switched)
+            .filter(|target| self.is_code(*target))
+            .and_then(|target| self.labels.get(&target))
+            .cloned()
+            .unwrap_or_else(|| format!("${:04X}", addr))
+    }
+
+    fn instruction(&self, addr: BankedAddress, bytes: &[u8]) -> String {
+        let decoded = decode(addr.addr, bytes).unwrap();
+
+        if decoded.template.starts_with("rst") {
+            return decoded.template;
+        }
+
+        let word = || u16::from_le_bytes([bytes[1], bytes[2]]);
+        let template = decoded.template;
+
+        if template.contains("n16") {
+            template.replace("n16", &format!("${:04X}", word()))
+        } else if template.contains("a16") {
+            template.replace("a16", &self.operand(addr, word()))
+        } else if template.contains("n8") {
+            template.replace("n8", &format!("${:02X}", bytes[1]))
+        } else if template.contains("a8") {
+            template.replace("a8", &format!("$FF{:02X}", bytes[1]))
+        } else if template.contains("e8") {
+            let target = addr
+                .addr
+                .wrapping_add(2)
+                .wrapping_add(bytes[1] as i8 as u16);
+            template.replace("e8", &self.operand(addr, target))
+        } else if template.contains("s8") {
+            let offset = bytes[1] as i8;
+            let sign = if offset < 0 { '-' } else { '+' };
+            template.replace("s8", &format!("{}{}", sign, offset.unsigned_abs()))
+        } else {
+            template
+        }
+    }
+
+    /// Re-assemblable RGBDS source of the whole ROM.
+    pub fn to_rgbds(&self) -> String {
+        let mut out = String::new();
+
+        for bank in 0..self.banks() {
+            let (start, section) = if bank == 0 {
+                (0x0000, "ROM0[$0000]".to_string())
+            } else {
+                (0x4000, format!("ROMX[$4000], BANK[${:X}]", bank))
+            };
+
+            if bank > 0 {
+                out.push('\n');
+            }
+            writeln!(out, "SECTION \"ROM Bank ${:03X}\", {}", bank, section).unwrap();
+
+            let base = bank * BANK_SIZE;
+            let end = (base + BANK_SIZE).min(self.rom.len());
+            let mut offset = base;
+
+            while offset < end {
+                let addr = BankedAddress::new(bank as u16, (start + offset - base) as u16);
+
+                if let Some(label) = self.labels.get(&addr).filter(|_| self.is_code(addr)) {
+                    writeln!(out, "\n{}:", label).unwrap();
+                }
+
+                if self.kinds[offset] == ByteKind::Code {
+                    let bytes = &self.rom[offset..end];
+                    let decoded = decode(addr.addr, bytes).unwrap();
+
+                    write!(out, "    {}", self.instruction(addr, bytes)).unwrap();
+                    if self.switches.get(&addr) == Some(&None) {
+                        write!(out, " ; unknown bank").unwrap();
+                    }
+                    out.push('\n');
+                    offset += decoded.len;
+                    continue;
+                }
+
+                // Data up to the next instruction
+                let data_end = (offset..end)
+                    .find(|i| self.kinds[*i] == ByteKind::Code)
+                    .unwrap_or(end);
+                self.write_data(&mut out, &self.rom[offset..data_end]);
+                offset = data_end;
+            }
+        }
+
+        out
+    }
+
+    fn write_data(&self, out: &mut String, mut data: &[u8]) {
+        while !data.is_empty() {
+            let fill = data.iter().take_while(|b| **b == data[0]).count();
+            if fill >= FILL_MIN_LEN {
+                writeln!(out, "    ds {}, ${:02X}", fill, data[0]).unwrap();
+                data = &data[fill..];
+                continue;
+            }
+
+            // Stop a line before a fill run
+            let mut len = data.len().min(DATA_BYTES_PER_LINE);
+            for i in 1..len {
+                if data[i..].iter().take_while(|b| **b == data[i]).count() >= FILL_MIN_LEN {
+                    len = i;
+                    break;
+                }
+            }
+
+            let bytes: Vec<String> = data[..len].iter().map(|b| format!("${:02X}", b)).collect();
+            writeln!(out, "    db {}", bytes.join(", ")).unwrap();
+            data = &data[len..];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gbr::memory_map::BankedAddress;
+
+    use super::{decode, Disassembly};
+
+    fn rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0xFF; banks * 0x4000];
+        // RST and interrupt vectors return straight away
+        for vector in (0x00..=0x60).step_by(8) {
+            rom[vector] = 0xC9;
+        }
+        rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+
+        rom
+    }
+
+    #[test]
+    fn rgbds_syntax() {
+        let text = |bytes: &[u8]| decode(0x0150, bytes).unwrap().template;
+
+        assert_eq!(text(&[0x7E]), "ld a, [hl]");
+        assert_eq!(text(&[0xB8]), "cp a, b");
+        assert_eq!(text(&[0xFE, 0x10]), "cp a, n8");
+        assert_eq!(text(&[0x36, 0x10]), "ld [hl], n8");
+        assert_eq!(text(&[0xCB, 0x7C]), "bit 7, h");
+        assert_eq!(text(&[0xCB, 0x37]), "swap a");
+        assert_eq!(text(&[0xEF]), "rst $28");
+        assert_eq!(decode(0x0150, &[0xD3]), None);
+        assert_eq!(decode(0x0150, &[0x10, 0x01]), None);
+    }
+
+    #[test]
+    fn code_and_data() {
+        let mut rom = rom(2);
+        rom[0x0150..0x0160].copy_from_slice(&[
+            0xCD, 0x00, 0x40, // call $4000
+            0x18, 0xFB, // jr $0150
+            0x12, 0x34, // data
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+        rom[0x4000..0x4006].copy_from_slice(&[
+            0xF0, 0x44, // ldh a, [$FF44]
+            0xFE, 0x90, // cp a, $90
+            0x30, 0xFA, // jr nc, $4000
+        ]);
+        rom[0x4006] = 0xC9;
+
+        let disassembly = Disassembly::new(rom.into());
+
+        assert!(disassembly.is_code(BankedAddress::new(0, 0x0153)));
+        assert!(!disassembly.is_code(BankedAddress::new(0, 0x0155)));
+        assert!(!disassembly.is_code(BankedAddress::new(0, 0x0134)));
+        assert!(disassembly.is_code(BankedAddress::new(1, 0x4006)));
+        assert_eq!(
+            disassembly.next_code(BankedAddress::new(0, 0x0001)),
+            Some(BankedAddress::new(0, 0x0008))
+        );
+        assert_eq!(disassembly.next_code(BankedAddress::new(0, 0x0155)), None);
+
+        let asm = disassembly.to_rgbds();
+        for line in [
+            "SECTION \"ROM Bank $000\", ROM0[$0000]",
+            "Entry:\n    nop\n    jp Jump_000_0150",
+            "Jump_000_0150:\n    call Call_001_4000\n    jr Jump_000_0150\n    db $12, $34",
+            "SECTION \"ROM Bank $001\", ROMX[$4000], BANK[$1]",
+            "Call_001_4000:\n    ldh a, [$FF44]\n    cp a, $90\n    jr nc, Call_001_4000\n    ret",
+            "    ds 16377, $FF",
+        ] {
+            assert!(asm.contains(line), "missing {:?} in\n{}", line, asm);
+        }
+    }
+
+    #[test]
+    fn switchable_banks() {
+        let mut rom = rom(4);
+        for bank in 1..4 {
+            let base = bank * 0x4000;
+            rom[base..base + 3].copy_from_slice(&[
+                0x3E, bank as u8, // ld a, bank
+                0xC9,       // ret
+            ]);
+        }
+
+        // The bank switched in is unknown, every bank is left as data
+        let mut unknown = rom.clone();
+        unknown[0x0150..0x0153].copy_from_slice(&[0xC3, 0x00, 0x40]); // jp $4000
+        let disassembly = Disassembly::new(unknown.into());
+        for bank in 1..4 {
+            assert!(!disassembly.is_code(BankedAddress::new(bank, 0x4000)));
+        }
+        let asm = disassembly.to_rgbds();
+        assert!(asm.contains("    jp $4000 ; unknown bank"), "{}", asm);
+
+        rom[0x0150..0x0158].copy_from_slice(&[
+            0x3E, 0x02, // ld a, $02
+            0xEA, 0x00, 0x20, // ld [$2000], a
+            0xC3, 0x00, 0x40, // jp $4000
+        ]);
+        let disassembly = Disassembly::new(rom.into());
+
+        // Only the bank switched in is traced
+        for bank in 1..4 {
+            let code = disassembly.is_code(BankedAddress::new(bank, 0x4002));
+            assert_eq!(code, bank == 2, "bank {}", bank);
+        }
+
+        let asm = disassembly.to_rgbds();
+        for line in [
+            "    jp Jump_002_4000",
+            "Jump_002_4000:\n    ld a, $02\n    ret",
+        ] {
+            assert!(asm.contains(line), "missing {:?} in\n{}", line, asm);
+        }
+    }
+}
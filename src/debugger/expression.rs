@@ -0,0 +1,488 @@
+use std::fmt::Display;
+
+use crate::gbr::{bus::BusAccess, cpu::CpuState, mbc::MbcState, GbError};
+
+// Hardware registers which can be used by name in expressions
+const IO_REGISTERS: [(&str, u16); 21] = [
+    ("P1", 0xFF00),
+    ("SB", 0xFF01),
+    ("SC", 0xFF02),
+    ("DIV", 0xFF04),
+    ("TIMA", 0xFF05),
+    ("TMA", 0xFF06),
+    ("TAC", 0xFF07),
+    ("IF", 0xFF0F),
+    ("LCDC", 0xFF40),
+    ("STAT", 0xFF41),
+    ("SCY", 0xFF42),
+    ("SCX", 0xFF43),
+    ("LY", 0xFF44),
+    ("LYC", 0xFF45),
+    ("DMA", 0xFF46),
+    ("BGP", 0xFF47),
+    ("OBP0", 0xFF48),
+    ("OBP1", 0xFF49),
+    ("WY", 0xFF4A),
+    ("WX", 0xFF4B),
+    ("IE", 0xFFFF),
+];
+
+/// Emulator state an expression is evaluated against.
+pub struct EvalContext<'a> {
+    pub cpu: &'a CpuState,
+    pub bus: &'a dyn BusAccess,
+    pub mbc: &'a MbcState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Variable {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+    RomBank,
+    RamBank,
+    Io(u16),
+}
+
+impl Variable {
+    fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_uppercase();
+
+        let var = match name.as_str() {
+            "A" => Variable::A,
+            "F" => Variable::F,
+            "B" => Variable::B,
+            "C" => Variable::C,
+            "D" => Variable::D,
+            "E" => Variable::E,
+            "H" => Variable::H,
+            "L" => Variable::L,
+            "AF" => Variable::AF,
+            "BC" => Variable::BC,
+            "DE" => Variable::DE,
+            "HL" => Variable::HL,
+            "SP" => Variable::SP,
+            "PC" => Variable::PC,
+            "ROM_BANK" => Variable::RomBank,
+            "RAM_BANK" => Variable::RamBank,
+            _ => {
+                let (_, addr) = IO_REGISTERS.iter().find(|(reg, _)| *reg == name)?;
+                Variable::Io(*addr)
+            }
+        };
+
+        Some(var)
+    }
+
+    fn eval(&self, ctx: &EvalContext) -> Result<u32, GbError> {
+        let cpu = ctx.cpu;
+
+        let value = match self {
+            Variable::A => cpu.af >> 8,
+            Variable::F => cpu.af & 0xFF,
+            Variable::B => cpu.bc >> 8,
+            Variable::C => cpu.bc & 0xFF,
+            Variable::D => cpu.de >> 8,
+            Variable::E => cpu.de & 0xFF,
+            Variable::H => cpu.hl >> 8,
+            Variable::L => cpu.hl & 0xFF,
+            Variable::AF => cpu.af,
+            Variable::BC => cpu.bc,
+            Variable::DE => cpu.de,
+            Variable::HL => cpu.hl,
+            Variable::SP => cpu.sp,
+            Variable::PC => cpu.pc,
+            Variable::RomBank => ctx.mbc.active_rom_bank,
+            Variable::RamBank => ctx.mbc.active_ram_bank,
+            Variable::Io(addr) => ctx.bus.read_byte(*addr)? as u16,
+        };
+
+        Ok(value as u32)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+impl BinaryOp {
+    fn from_token(token: &str) -> Option<Self> {
+        let op = match token {
+            "||" => BinaryOp::Or,
+            "&&" => BinaryOp::And,
+            "==" => BinaryOp::Eq,
+            "!=" => BinaryOp::Ne,
+            "<" => BinaryOp::Lt,
+            "<=" => BinaryOp::Le,
+            ">" => BinaryOp::Gt,
+            ">=" => BinaryOp::Ge,
+            "|" => BinaryOp::BitOr,
+            "^" => BinaryOp::BitXor,
+            "&" => BinaryOp::BitAnd,
+            "+" => BinaryOp::Add,
+            "-" => BinaryOp::Sub,
+            _ => return None,
+        };
+
+        Some(op)
+    }
+
+    // Same precedence as in Rust, higher binds tighter
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq
+            | BinaryOp::Ne
+            | BinaryOp::Lt
+            | BinaryOp::Le
+            | BinaryOp::Gt
+            | BinaryOp::Ge => 3,
+            BinaryOp::BitOr => 4,
+            BinaryOp::BitXor => 5,
+            BinaryOp::BitAnd => 6,
+            BinaryOp::Add | BinaryOp::Sub => 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Literal(u32),
+    Var(Variable),
+    Deref(Box<Node>),
+    Not(Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    fn eval(&self, ctx: &EvalContext) -> Result<u32, GbError> {
+        let value = match self {
+            Node::Literal(value) => *value,
+            Node::Var(var) => var.eval(ctx)?,
+            Node::Deref(addr) => ctx.bus.read_byte(addr.eval(ctx)? as u16)? as u32,
+            Node::Not(node) => (node.eval(ctx)? == 0) as u32,
+            Node::Binary(BinaryOp::Or, lhs, rhs) => {
+                (lhs.eval(ctx)? != 0 || rhs.eval(ctx)? != 0) as u32
+            }
+            Node::Binary(BinaryOp::And, lhs, rhs) => {
+                (lhs.eval(ctx)? != 0 && rhs.eval(ctx)? != 0) as u32
+            }
+            Node::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(ctx)?;
+                let rhs = rhs.eval(ctx)?;
+
+                match op {
+                    BinaryOp::Eq => (lhs == rhs) as u32,
+                    BinaryOp::Ne => (lhs != rhs) as u32,
+                    BinaryOp::Lt => (lhs < rhs) as u32,
+                    BinaryOp::Le => (lhs <= rhs) as u32,
+                    BinaryOp::Gt => (lhs > rhs) as u32,
+                    BinaryOp::Ge => (lhs >= rhs) as u32,
+                    BinaryOp::BitOr => lhs | rhs,
+                    BinaryOp::BitXor => lhs ^ rhs,
+                    BinaryOp::BitAnd => lhs & rhs,
+                    BinaryOp::Add => lhs.wrapping_add(rhs),
+                    BinaryOp::Sub => lhs.wrapping_sub(rhs),
+                    BinaryOp::Or | BinaryOp::And => unreachable!(),
+                }
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(u32),
+    Ident(String),
+    Op(String),
+    Open(char),
+    Close(char),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, GbError> {
+    let mut tokens = vec![];
+    let mut chars = source.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '$' {
+            let mut text = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '$') {
+                    break;
+                }
+                text.push(c);
+                chars.next();
+            }
+
+            let number = if let Some(hex) = text.strip_prefix('$') {
+                u32::from_str_radix(hex, 16)
+            } else if let Some(hex) = text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+                u32::from_str_radix(hex, 16)
+            } else {
+                text.parse()
+            };
+
+            let number =
+                number.map_err(|_| GbError::ExprParsing(format!("invalid number {}", text)))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut ident = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                ident.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Ident(ident));
+        } else if c == '(' || c == '[' {
+            chars.next();
+            tokens.push(Token::Open(c));
+        } else if c == ')' || c == ']' {
+            chars.next();
+            tokens.push(Token::Close(c));
+        } else {
+            chars.next();
+
+            let mut op = c.to_string();
+            if let Some(&next) = chars.peek() {
+                let two = format!("{}{}", c, next);
+                if BinaryOp::from_token(&two).is_some() {
+                    op = two;
+                    chars.next();
+                }
+            }
+
+            if op != "!" && BinaryOp::from_token(&op).is_none() {
+                return Err(GbError::ExprParsing(format!("unexpected character {}", c)));
+            }
+            tokens.push(Token::Op(op));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect_close(&mut self, c: char) -> Result<(), GbError> {
+        match self.next() {
+            Some(Token::Close(close)) if close == c => Ok(()),
+            _ => Err(GbError::ExprParsing(format!("expected {}", c))),
+        }
+    }
+
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Node, GbError> {
+        let mut lhs = self.parse_unary()?;
+
+        while let Some(Token::Op(op)) = self.peek() {
+            let op = match BinaryOp::from_token(op) {
+                Some(op) if op.precedence() >= min_precedence => op,
+                _ => break,
+            };
+            self.next();
+
+            let rhs = self.parse_binary(op.precedence() + 1)?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Node, GbError> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Node::Literal(value)),
+            Some(Token::Ident(name)) => Variable::from_name(&name)
+                .map(Node::Var)
+                .ok_or_else(|| GbError::ExprParsing(format!("unknown variable {}", name))),
+            Some(Token::Op(op)) if op == "!" => Ok(Node::Not(Box::new(self.parse_unary()?))),
+            Some(Token::Open('(')) => {
+                let node = self.parse_binary(0)?;
+                self.expect_close(')')?;
+                Ok(node)
+            }
+            Some(Token::Open('[')) => {
+                let node = self.parse_binary(0)?;
+                self.expect_close(']')?;
+                Ok(Node::Deref(Box::new(node)))
+            }
+            Some(token) => Err(GbError::ExprParsing(format!(
+                "unexpected token {:?}",
+                token
+            ))),
+            None => Err(GbError::ExprParsing("unexpected end of expression".into())),
+        }
+    }
+}
+
+/// Expression over CPU registers, memory and MBC state.
+///
+/// Supports decimal and hex (`0x3F`, `$3F`) numbers, registers (`A`, `HL`, ...),
+/// hardware registers by name (`LY`, `LCDC`, ...), `rom_bank`, `ram_bank`,
+/// memory reads (`[HL]`, `[0xC000 + 1]`), arithmetic, bitwise, comparison and
+/// logic operators. Non zero values are true.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, GbError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+        };
+
+        let root = parser.parse_binary(0)?;
+
+        if let Some(token) = parser.peek() {
+            return Err(GbError::ExprParsing(format!(
+                "unexpected token {:?}",
+                token
+            )));
+        }
+
+        Ok(Self {
+            source: source.trim().to_string(),
+            root,
+        })
+    }
+
+    pub fn eval(&self, ctx: &EvalContext) -> Result<u32, GbError> {
+        self.root.eval(ctx)
+    }
+
+    pub fn is_true(&self, ctx: &EvalContext) -> Result<bool, GbError> {
+        Ok(self.eval(ctx)? != 0)
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gbr::{
+        bus::{Bus, BusAccess},
+        cpu::CpuState,
+        mbc::MbcState,
+    };
+
+    use super::{EvalContext, Expression};
+
+    fn eval(source: &str, cpu: &CpuState, bus: &Bus) -> u32 {
+        let mbc = MbcState {
+            active_rom_bank: 5,
+            ..Default::default()
+        };
+
+        let ctx = EvalContext {
+            cpu,
+            bus,
+            mbc: &mbc,
+        };
+
+        Expression::parse(source).unwrap().eval(&ctx).unwrap()
+    }
+
+    #[test]
+    fn literals_and_precedence() {
+        let cpu = CpuState::default();
+        let bus = Bus::from_rom(&vec![0; 0x8000]).unwrap();
+
+        assert_eq!(eval("0x3F", &cpu, &bus), 0x3F);
+        assert_eq!(eval("$3f", &cpu, &bus), 0x3F);
+        assert_eq!(eval("1 + 2 == 3", &cpu, &bus), 1);
+        assert_eq!(eval("0xF0 | 0x0F & 0x01", &cpu, &bus), 0xF1);
+        assert_eq!(eval("(0xF0 | 0x0F) & 0x01", &cpu, &bus), 0x01);
+        assert_eq!(eval("0 || 1 && 0", &cpu, &bus), 0);
+        assert_eq!(eval("!0 && !(2 < 1)", &cpu, &bus), 1);
+        assert_eq!(eval("0 - 1", &cpu, &bus), u32::MAX);
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let cpu = CpuState {
+            af: 0x3F00,
+            hl: 0xC000,
+            ..Default::default()
+        };
+        let mut bus = Bus::from_rom(&vec![0; 0x8000]).unwrap();
+
+        assert_eq!(eval("A == 0x3F && [HL] != 0", &cpu, &bus), 0);
+
+        bus.write_byte(0xC000, 0x12).unwrap();
+        bus.write_byte(0xC001, 0x34).unwrap();
+
+        assert_eq!(eval("A == 0x3F && [HL] != 0", &cpu, &bus), 1);
+        assert_eq!(eval("[hl + 1]", &cpu, &bus), 0x34);
+        assert_eq!(eval("LY == 0", &cpu, &bus), 1);
+        assert_eq!(eval("lcdc", &cpu, &bus), 0x91);
+        assert_eq!(eval("rom_bank == 5", &cpu, &bus), 1);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Expression::parse("").is_err());
+        assert!(Expression::parse("A ==").is_err());
+        assert!(Expression::parse("(A == 1").is_err());
+        assert!(Expression::parse("[HL)").is_err());
+        assert!(Expression::parse("Q == 1").is_err());
+        assert!(Expression::parse("A = 1").is_err());
+        assert!(Expression::parse("0xZZ").is_err());
+        assert!(Expression::parse("A 1").is_err());
+    }
+
+    #[test]
+    fn display_source() {
+        let expr = Expression::parse("  LY == 144 ").unwrap();
+        assert_eq!(expr.to_string(), "LY == 144");
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use gilrs::{Axis, Button, EventType, Gilrs};
+
+use crate::gbr::{
+    game_boy::{GenericInput, InputType},
+    joypad::Directions,
+};
+
+use super::{held_inputs::HeldInputs, keymap::Keymap};
+
+fn button(name: &str) -> Option<Button> {
+    let button = match name {
+        "South" => Button::South,
+        "East" => Button::East,
+        "North" => Button::North,
+        "West" => Button::West,
+        "C" => Button::C,
+        "Z" => Button::Z,
+        "LeftTrigger" => Button::LeftTrigger,
+        "LeftTrigger2" => Button::LeftTrigger2,
+        "RightTrigger" => Button::RightTrigger,
+        "RightTrigger2" => Button::RightTrigger2,
+        "Select" => Button::Select,
+        "Start" => Button::Start,
+        "Mode" => Button::Mode,
+        "LeftThumb" => Button::LeftThumb,
+        "RightThumb" => Button::RightThumb,
+        "DPadUp" => Button::DPadUp,
+        "DPadDown" => Button::DPadDown,
+        "DPadLeft" => Button::DPadLeft,
+        "DPadRight" => Button::DPadRight,
+        _ => return None,
+    };
+
+    Some(button)
+}
+
+/// Directions pressed by an analog stick, `y` grows upwards.
+fn stick_directions(x: f32, y: f32, threshold: f32) -> Directions {
+    let mut directions = Directions::empty();
+
+    directions.set(Directions::Right, x >= threshold);
+    directions.set(Directions::Left, x <= -threshold);
+    directions.set(Directions::Up, y >= threshold);
+    directions.set(Directions::Down, y <= -threshold);
+
+    directions
+}
+
+/// Game controllers, mapped to the Game Boy keys by the gamepad keymap.
+///
+/// The left stick and D-pads reporting axes press a direction past the stick threshold.
+pub struct Gamepad {
+    gilrs: Gilrs,
+    buttons: HashMap<Button, GenericInput>,
+    stick_threshold: f32,
+    // Last value of the stick and D-pad axes, x then y
+    stick: [f32; 2],
+    dpad: [f32; 2],
+    directions: Directions,
+}
+
+impl Gamepad {
+    pub fn new(keymap: &Keymap) -> Option<Self> {
+        let gilrs = Gilrs::new()
+            .inspect_err(|e| log::error!("Gamepad support disabled: {}", e))
+            .ok()?;
+
+        let mut buttons = HashMap::new();
+        for (input, name) in keymap.gamepad() {
+            match button(name) {
+                Some(button) => {
+                    buttons.insert(button, input);
+                }
+                None => log::warn!("Unknown gamepad button {} in the keymap", name),
+            }
+        }
+
+        Some(Self {
+            gilrs,
+            buttons,
+            stick_threshold: keymap.stick_threshold,
+            stick: [0.0; 2],
+            dpad: [0.0; 2],
+            directions: Directions::empty(),
+        })
+    }
+
+    /// Send the inputs of the gamepad events received since the last call.
+    pub fn poll(&mut self, held: &mut HeldInputs) {
+        while let Some(ev) = self.gilrs.next_event() {
+            let input = match ev.event {
+                EventType::ButtonPressed(button, _) => {
+                    self.buttons.get(&button).cloned().map(InputType::Pressed)
+                }
+                EventType::ButtonReleased(button, _) => {
+                    self.buttons.get(&button).cloned().map(InputType::Released)
+                }
+                EventType::AxisChanged(axis, value, _) => {
+                    match axis {
+                        Axis::LeftStickX => self.stick[0] = value,
+                        Axis::LeftStickY => self.stick[1] = value,
+                        Axis::DPadX => self.dpad[0] = value,
+                        Axis::DPadY => self.dpad[1] = value,
+                        _ => (),
+                    }
+                    self.send_directions(held);
+                    None
+                }
+                EventType::Disconnected => {
+                    self.stick = [0.0; 2];
+                    self.dpad = [0.0; 2];
+                    self.send_directions(held);
+                    None
+                }
+                _ => None,
+            };
+
+            if let Some(input) = input {
+                held.send(input);
+            }
+        }
+    }
+
+    // Press and release the directions changed by the axes
+    fn send_directions(&mut self, held: &mut HeldInputs) {
+        let directions = stick_directions(self.stick[0], self.stick[1], self.stick_threshold)
+            | stick_directions(self.dpad[0], self.dpad[1], 0.5);
+
+        for direction in (directions ^ self.directions).iter() {
+            let input = GenericInput::Direction(direction);
+            let input = if directions.contains(direction) {
+                InputType::Pressed(input)
+            } else {
+                InputType::Released(input)
+            };
+
+            held.send(input);
+        }
+
+        self.directions = directions;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gilrs::Button;
+
+    use crate::gbr::joypad::Directions;
+
+    use super::{button, stick_directions};
+
+    #[test]
+    fn button_names() {
+        assert_eq!(button("South"), Some(Button::South));
+        assert_eq!(button("DPadLeft"), Some(Button::DPadLeft));
+        assert_eq!(button("RightTrigger2"), Some(Button::RightTrigger2));
+        assert_eq!(button("south"), None);
+        assert_eq!(button("Unknown"), None);
+    }
+
+    #[test]
+    fn stick_threshold() {
+        assert_eq!(stick_directions(0.2, -0.3, 0.5), Directions::empty());
+        assert_eq!(stick_directions(0.6, 0.0, 0.5), Directions::Right);
+        assert_eq!(
+            stick_directions(-0.7, -0.5, 0.5),
+            Directions::Left | Directions::Down
+        );
+        assert_eq!(stick_directions(0.0, 1.0, 0.5), Directions::Up);
+    }
+}
@@ -0,0 +1,93 @@
+use std::sync::mpsc::Sender;
+
+use crate::gbr::game_boy::{GbrEvent, GenericInput, InputType};
+
+/// Game Boy keys held by the keyboard and the gamepads.
+///
+/// A key is pressed by the first source holding it and released by the last
+/// one letting it go.
+pub struct HeldInputs {
+    ev_sender: Sender<GbrEvent>,
+    // Keys and the number of sources holding them
+    held: Vec<(GenericInput, u32)>,
+}
+
+impl HeldInputs {
+    pub fn new(ev_sender: Sender<GbrEvent>) -> Self {
+        Self {
+            ev_sender,
+            held: Vec::new(),
+        }
+    }
+
+    /// Count a source pressing or releasing a key, sending the input when the key changes.
+    pub fn send(&mut self, input: InputType) {
+        if let Some(input) = self.update(input) {
+            self.ev_sender.send(GbrEvent::Input(input)).ok();
+        }
+    }
+
+    fn update(&mut self, input: InputType) -> Option<InputType> {
+        match input {
+            InputType::Pressed(key) => match self.held.iter_mut().find(|(held, _)| *held == key) {
+                Some((_, count)) => {
+                    *count += 1;
+                    None
+                }
+                None => {
+                    self.held.push((key.clone(), 1));
+                    Some(InputType::Pressed(key))
+                }
+            },
+            InputType::Released(key) => {
+                let index = self.held.iter().position(|(held, _)| *held == key)?;
+                self.held[index].1 -= 1;
+                if self.held[index].1 > 0 {
+                    return None;
+                }
+
+                self.held.swap_remove(index);
+                Some(InputType::Released(key))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use crate::gbr::{
+        game_boy::{GenericInput, InputType},
+        joypad::{Buttons, Directions},
+    };
+
+    use super::HeldInputs;
+
+    #[test]
+    fn sources() {
+        let (ev_sender, _) = channel();
+        let mut held = HeldInputs::new(ev_sender);
+        let left = GenericInput::Direction(Directions::Left);
+        let a = GenericInput::Button(Buttons::A);
+
+        // Keyboard then stick, the stick release keeps the key held
+        assert_eq!(
+            held.update(InputType::Pressed(left.clone())),
+            Some(InputType::Pressed(left.clone()))
+        );
+        assert_eq!(held.update(InputType::Pressed(left.clone())), None);
+        assert_eq!(
+            held.update(InputType::Pressed(a.clone())),
+            Some(InputType::Pressed(a))
+        );
+        assert_eq!(held.update(InputType::Released(left.clone())), None);
+        assert_eq!(
+            held.update(InputType::Released(left.clone())),
+            Some(InputType::Released(left.clone()))
+        );
+
+        // Not held
+        assert_eq!(held.update(InputType::Released(left)), None);
+    }
+}
@@ -0,0 +1,381 @@
+use std::{collections::VecDeque, fmt::Display};
+
+use crate::gbr::{
+    bus::MemWrite,
+    game_boy::{GameBoy, Snapshot},
+    GbError,
+};
+
+// Steps between snapshots and snapshots kept, about the last 2 seconds of emulation
+const SNAPSHOT_INTERVAL: u64 = 20_000;
+const MAX_SNAPSHOTS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WriteRecord {
+    // Step performing the write, counted from the start of the recording
+    pub step: u64,
+    // Address of the instruction, None when pushed by an interrupt dispatch
+    pub pc: Option<u16>,
+    pub write: MemWrite,
+}
+
+impl Display for WriteRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Step {}: ", self.step)?;
+
+        match self.pc {
+            Some(pc) => write!(f, "PC {:#06X}", pc)?,
+            None => write!(f, "interrupt dispatch")?,
+        }
+
+        write!(
+            f,
+            " wrote {:#04X} to {:#06X}",
+            self.write.value, self.write.addr
+        )?;
+
+        match self.write.old {
+            Some(old) => write!(f, " (was {:#04X})", old),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct HistoryState {
+    pub recording: bool,
+    pub step: u64,
+    pub oldest: u64,
+    // Inputs logged after the current step are being replayed
+    pub replaying: bool,
+    // Address of the last write lookup and the write found
+    pub last_write: Option<(u16, Option<WriteRecord>)>,
+}
+
+/// Recording of the executed steps to go back in time.
+///
+/// Snapshots are taken every `SNAPSHOT_INTERVAL` steps, together with a log of
+/// the memory writes in between. Any previous step is reached by restoring the
+/// closest snapshot and replaying the steps after it.
+#[derive(Default)]
+pub struct History {
+    recording: bool,
+    step: u64,
+    snapshots: VecDeque<(u64, Snapshot)>,
+    writes: VecDeque<WriteRecord>,
+    last_write: Option<(u16, Option<WriteRecord>)>,
+}
+
+impl History {
+    pub fn start(&mut self, gb: &mut GameBoy) {
+        *self = Self {
+            recording: true,
+            ..Default::default()
+        };
+
+        self.snapshots.push_back((0, gb.snapshot()));
+        gb.log_writes(true);
+        gb.log_inputs(true);
+    }
+
+    pub fn stop(&mut self, gb: &mut GameBoy) {
+        *self = Self::default();
+        gb.log_writes(false);
+        gb.log_inputs(false);
+    }
+
+    pub fn state(&self, gb: &GameBoy) -> HistoryState {
+        HistoryState {
+            recording: self.recording,
+            step: self.step,
+            oldest: self.oldest(),
+            replaying: gb.is_replaying(),
+            last_write: self.last_write,
+        }
+    }
+
+    fn oldest(&self) -> u64 {
+        self.snapshots.front().map_or(0, |(step, _)| *step)
+    }
+
+    /// Record the step just executed by `gb`.
+    pub fn record(&mut self, gb: &mut GameBoy) {
+        if !self.recording {
+            return;
+        }
+
+        self.step += 1;
+
+        let cpu = gb.cpu();
+        let pc = cpu.executed_instruction().then_some(cpu.reg_pc_prev);
+
+        for write in gb.take_writes() {
+            self.writes.push_back(WriteRecord {
+                step: self.step,
+                pc,
+                write,
+            });
+        }
+
+        if self.step.is_multiple_of(SNAPSHOT_INTERVAL) {
+            self.snapshots.push_back((self.step, gb.snapshot()));
+
+            if self.snapshots.len() > MAX_SNAPSHOTS {
+                self.snapshots.pop_front();
+
+                // Writes done by the oldest step cannot be rewound anymore
+                let oldest = self.oldest();
+                while self.writes.front().is_some_and(|w| w.step <= oldest) {
+                    self.writes.pop_front();
+                }
+
+                if let Some((_, snapshot)) = self.snapshots.front() {
+                    gb.forget_inputs_before(snapshot);
+                }
+            }
+        }
+    }
+
+    // Go to the state after `target` steps, forgetting the steps after it
+    fn travel(&mut self, gb: &mut GameBoy, target: u64) -> Result<(), GbError> {
+        let index = self
+            .snapshots
+            .iter()
+            .rposition(|(step, _)| *step <= target)
+            .unwrap_or(0);
+        self.snapshots.truncate(index + 1);
+
+        let (step, snapshot) = &self.snapshots[index];
+        gb.restore(snapshot);
+        self.step = *step;
+
+        while self.writes.back().is_some_and(|w| w.step > self.step) {
+            self.writes.pop_back();
+        }
+        gb.take_writes();
+
+        while self.step < target {
+            gb.step()?;
+            self.record(gb);
+        }
+
+        // Hits while replaying were already reported
+        gb.take_watchpoint_hit();
+
+        Ok(())
+    }
+
+    /// Undo the last step. Returns false at the oldest recorded step.
+    pub fn step_back(&mut self, gb: &mut GameBoy) -> Result<bool, GbError> {
+        if !self.recording || self.step <= self.oldest() {
+            return Ok(false);
+        }
+
+        self.travel(gb, self.step - 1)?;
+        Ok(true)
+    }
+
+    /// Go back to the last state before the current one where `should_break`
+    /// holds, or to the oldest recorded step.
+    ///
+    /// Returns whether such a state was found.
+    pub fn reverse_continue(
+        &mut self,
+        gb: &mut GameBoy,
+        mut should_break: impl FnMut(&mut GameBoy) -> bool,
+    ) -> Result<bool, GbError> {
+        if !self.recording {
+            return Ok(false);
+        }
+
+        // Search the intervals between snapshots starting from the latest
+        let mut end = self.step;
+        for index in (0..self.snapshots.len()).rev() {
+            let start = self.snapshots[index].0;
+            if start >= end {
+                continue;
+            }
+
+            gb.restore(&self.snapshots[index].1);
+
+            let mut found = should_break(gb).then_some(start);
+            for step in start + 1..end {
+                gb.step()?;
+                gb.take_writes();
+
+                if should_break(gb) {
+                    found = Some(step);
+                }
+            }
+
+            if let Some(step) = found {
+                self.travel(gb, step)?;
+                return Ok(true);
+            }
+
+            end = start;
+        }
+
+        self.travel(gb, self.oldest())?;
+        Ok(false)
+    }
+
+    /// Go back to just before the last recorded write to `addr`, so that the
+    /// instruction performing it is the next one executed.
+    pub fn rewind_to_last_write(
+        &mut self,
+        gb: &mut GameBoy,
+        addr: u16,
+    ) -> Result<Option<WriteRecord>, GbError> {
+        let record = self
+            .writes
+            .iter()
+            .rev()
+            .find(|w| w.write.addr == addr)
+            .copied();
+        self.last_write = Some((addr, record));
+
+        if let Some(record) = record {
+            self.travel(gb, record.step - 1)?;
+        }
+
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gbr::{
+        bus::BusAccess,
+        game_boy::{GameBoy, GenericInput, InputType},
+        joypad::Buttons,
+    };
+
+    use super::History;
+
+    // Increment the byte at 0xC000 forever
+    fn game_boy() -> GameBoy {
+        GameBoy::with_program(&[
+            0x21, 0x00, 0xC0, // LD HL, 0xC000
+            0x34, // INC (HL)
+            0x18, 0xFD, // JR -3
+        ])
+    }
+
+    // Store the button lines of the joypad register to 0xC000 forever
+    fn joypad_game_boy() -> GameBoy {
+        GameBoy::with_program(&[
+            0x3E, 0x10, // LD A, 0x10
+            0xE0, 0x00, // LDH (0x00), A
+            0xF0, 0x00, // LDH A, (0x00)
+            0xEA, 0x00, 0xC0, // LD (0xC000), A
+            0x18, 0xF9, // JR -7
+        ])
+    }
+
+    fn run(gb: &mut GameBoy, history: &mut History, steps: usize) {
+        for _ in 0..steps {
+            gb.step().unwrap();
+            history.record(gb);
+        }
+    }
+
+    #[test]
+    fn step_back() {
+        let mut gb = game_boy();
+        let mut history = History::default();
+        history.start(&mut gb);
+
+        run(&mut gb, &mut history, 2 * 20_000 + 5);
+        let value = gb.bus().read_byte(0xC000).unwrap();
+        let cycles = gb.cycles();
+
+        run(&mut gb, &mut history, 3);
+        for _ in 0..3 {
+            assert!(history.step_back(&mut gb).unwrap());
+        }
+
+        assert_eq!(history.state(&gb).step, 2 * 20_000 + 5);
+        assert_eq!(gb.bus().read_byte(0xC000).unwrap(), value);
+        assert_eq!(gb.cycles(), cycles);
+
+        // Back to the start of the recording
+        assert!(!history.reverse_continue(&mut gb, |_| false).unwrap());
+        assert!(!history.step_back(&mut gb).unwrap());
+        assert_eq!(history.state(&gb).step, 0);
+        assert_eq!(gb.cpu().read_pc(), 0x0150);
+    }
+
+    #[test]
+    fn reverse_continue() {
+        let mut gb = game_boy();
+        let mut history = History::default();
+        history.start(&mut gb);
+
+        run(&mut gb, &mut history, 30_000);
+
+        let found = history
+            .reverse_continue(&mut gb, |gb| gb.bus().read_byte(0xC000).unwrap() == 0x10)
+            .unwrap();
+
+        // The last state with the value, just before it is incremented again
+        assert!(found);
+        assert_eq!(gb.bus().read_byte(0xC000).unwrap(), 0x10);
+        assert_eq!(gb.cpu().read_pc(), 0x0153);
+    }
+
+    #[test]
+    fn last_write() {
+        let mut gb = game_boy();
+        let mut history = History::default();
+        history.start(&mut gb);
+
+        run(&mut gb, &mut history, 100);
+
+        let record = history
+            .rewind_to_last_write(&mut gb, 0xC000)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(record.pc, Some(0x0153));
+        assert_eq!(record.write.old, Some(record.write.value.wrapping_sub(1)));
+        assert_eq!(gb.cpu().read_pc(), 0x0153);
+        assert_eq!(
+            gb.bus().read_byte(0xC000).unwrap(),
+            record.write.old.unwrap()
+        );
+
+        assert_eq!(history.rewind_to_last_write(&mut gb, 0xC001).unwrap(), None);
+    }
+
+    #[test]
+    fn replay_inputs() {
+        let a = |input: fn(GenericInput) -> InputType| input(GenericInput::Button(Buttons::A));
+
+        let mut gb = joypad_game_boy();
+        let mut history = History::default();
+        history.start(&mut gb);
+
+        // Pressed between the snapshots of steps 20000 and 40000
+        run(&mut gb, &mut history, 25_000);
+        gb.handle_input(a(InputType::Pressed));
+        run(&mut gb, &mut history, 100);
+        assert_eq!(gb.bus().read_byte(0xC000).unwrap(), 0xDE);
+
+        // Back before the press
+        for _ in 0..100 {
+            assert!(history.step_back(&mut gb).unwrap());
+        }
+        assert_eq!(gb.bus().read_byte(0xC000).unwrap(), 0xDF);
+
+        // The press is replayed, live inputs are ignored until then
+        gb.handle_input(a(InputType::Released));
+        run(&mut gb, &mut history, 100);
+        assert_eq!(gb.bus().read_byte(0xC000).unwrap(), 0xDE);
+
+        // Not replaying anymore
+        gb.handle_input(a(InputType::Released));
+        run(&mut gb, &mut history, 100);
+        assert_eq!(gb.bus().read_byte(0xC000).unwrap(), 0xDF);
+    }
+}
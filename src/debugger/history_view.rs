@@ -0,0 +1,89 @@
+use std::sync::mpsc::Sender;
+
+use crate::gbr::game_boy::{DebugEvent, GbrEvent};
+
+use super::{history::HistoryState, watchpoints_view::parse_hex};
+
+#[derive(Default)]
+pub struct HistoryView {
+    addr: String,
+}
+
+impl HistoryView {
+    pub fn show(
+        &mut self,
+        ev_sender: &Sender<GbrEvent>,
+        history: &HistoryState,
+        idle: bool,
+        ui: &mut egui::Ui,
+    ) {
+        let send = |ev| ev_sender.send(GbrEvent::Debug(ev)).unwrap();
+
+        let mut recording = history.recording;
+        if ui.checkbox(&mut recording, "Record").changed() {
+            send(DebugEvent::RecordHistory(recording));
+        }
+
+        if !history.recording {
+            return;
+        }
+
+        ui.label(format!(
+            "Step {}, oldest step {}",
+            history.step, history.oldest
+        ));
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(idle, egui::Button::new("Step back"))
+                .clicked()
+            {
+                send(DebugEvent::StepBack);
+            }
+
+            if ui
+                .add_enabled(idle, egui::Button::new("Reverse continue"))
+                .clicked()
+            {
+                send(DebugEvent::ReverseContinue);
+            }
+        });
+
+        if history.replaying {
+            ui.horizontal(|ui| {
+                ui.label("Replaying the recorded inputs");
+
+                if ui
+                    .add_enabled(idle, egui::Button::new("New branch"))
+                    .clicked()
+                {
+                    send(DebugEvent::NewBranch);
+                }
+            });
+        }
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("Address:");
+            ui.text_edit_singleline(&mut self.addr);
+        });
+
+        let addr = parse_hex(&self.addr);
+        if ui
+            .add_enabled(
+                idle && addr.is_some(),
+                egui::Button::new("Rewind to last write"),
+            )
+            .clicked()
+        {
+            send(DebugEvent::RewindToLastWrite(addr.unwrap()));
+        }
+
+        match history.last_write {
+            Some((_, Some(record))) => ui.label(format!("{}", record)),
+            Some((addr, None)) => ui.label(format!("No recorded write to {:#06X}", addr)),
+            None => ui.label(""),
+        };
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use winit::event::VirtualKeyCode;
+
+use crate::gbr::game_boy::GenericInput;
+
+const DEFAULT_STICK_THRESHOLD: f32 = 0.5;
+
+/// Keyboard and gamepad bindings of the Game Boy keys, read from the config file.
+///
+/// Tables map a key name ("A", "Start", "Left", ...) to a winit key code or
+/// to a gilrs button name ("South", "DPadUp", ...). A missing table keeps the
+/// default bindings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keymap {
+    // Analog stick deflection, from 0 to 1, pressing a direction
+    pub stick_threshold: f32,
+    pub keyboard: BTreeMap<String, VirtualKeyCode>,
+    pub gamepad: BTreeMap<String, String>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let keyboard = [
+            ("A", VirtualKeyCode::A),
+            ("B", VirtualKeyCode::Z),
+            ("Select", VirtualKeyCode::Space),
+            ("Start", VirtualKeyCode::Return),
+            ("Right", VirtualKeyCode::Right),
+            ("Left", VirtualKeyCode::Left),
+            ("Up", VirtualKeyCode::Up),
+            ("Down", VirtualKeyCode::Down),
+        ];
+
+        // Face buttons by position, A is the right one as on the Game Boy
+        let gamepad = [
+            ("A", "East"),
+            ("B", "South"),
+            ("Select", "Select"),
+            ("Start", "Start"),
+            ("Right", "DPadRight"),
+            ("Left", "DPadLeft"),
+            ("Up", "DPadUp"),
+            ("Down", "DPadDown"),
+        ];
+
+        Self {
+            stick_threshold: DEFAULT_STICK_THRESHOLD,
+            keyboard: keyboard
+                .into_iter()
+                .map(|(key, code)| (key.to_string(), code))
+                .collect(),
+            gamepad: gamepad
+                .into_iter()
+                .map(|(key, button)| (key.to_string(), button.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl Keymap {
+    pub fn parse(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    pub fn keyboard(&self) -> HashMap<VirtualKeyCode, GenericInput> {
+        bindings(&self.keyboard)
+            .map(|(input, code)| (*code, input))
+            .collect()
+    }
+
+    /// Game Boy keys and the name of the gamepad button bound to them.
+    #[cfg(feature = "gamepad")]
+    pub fn gamepad(&self) -> Vec<(GenericInput, &str)> {
+        bindings(&self.gamepad)
+            .map(|(input, button)| (input, button.as_str()))
+            .collect()
+    }
+}
+
+fn bindings<T>(map: &BTreeMap<String, T>) -> impl Iterator<Item = (GenericInput, &T)> + '_ {
+    map.iter().filter_map(|(key, binding)| {
+        let input = GenericInput::from_name(key);
+        if input.is_none() {
+            log::warn!("Unknown Game Boy key {} in the keymap", key);
+        }
+        Some((input?, binding))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::event::VirtualKeyCode;
+
+    use crate::gbr::{game_boy::GenericInput, joypad::Buttons};
+
+    use super::Keymap;
+
+    #[test]
+    fn config_file() {
+        let keymap = Keymap::default();
+        let text = toml::to_string(&keymap).unwrap();
+        assert_eq!(Keymap::parse(&text).unwrap(), keymap);
+
+        let keymap = Keymap::parse(
+            "stick_threshold = 0.3\n\
+             [keyboard]\n\
+             A = \"X\"\n\
+             Turbo = \"T\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(keymap.stick_threshold, 0.3);
+        assert_eq!(
+            keymap.keyboard().into_iter().collect::<Vec<_>>(),
+            vec![(VirtualKeyCode::X, GenericInput::Button(Buttons::A))]
+        );
+        // Not in the file, default bindings
+        assert_eq!(keymap.gamepad, Keymap::default().gamepad);
+
+        assert!(Keymap::parse("[keyboard]\nA = \"NoSuchKey\"\n").is_err());
+    }
+}
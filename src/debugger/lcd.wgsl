@@ -0,0 +1,82 @@
+// LCD post-processing of the pixels texture, drawn in the viewport of the scaled screen.
+// The CPU version in lcd_effects.rs follows the same steps.
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+}
+
+struct Locals {
+    // Top left corner of the scaled screen and its scale, in target pixels
+    origin: vec2<f32>,
+    scale: f32,
+    flags: u32,
+}
+
+@group(0) @binding(0) var frame: texture_2d<f32>;
+@group(0) @binding(1) var previous: texture_2d<f32>;
+@group(0) @binding(2) var nearest: sampler;
+@group(0) @binding(3) var<uniform> locals: Locals;
+
+const GHOSTING: u32 = 1u;
+const GRID: u32 = 2u;
+const COLOR_CORRECTION: u32 = 4u;
+
+const GHOSTING_WEIGHT: f32 = 0.5;
+const GRID_DIM: f32 = 0.75;
+const GRID_MIN_SCALE: f32 = 3.0;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // One triangle covering the viewport
+    let position = vec2<f32>(select(-1.0, 3.0, index == 1u), select(-1.0, 3.0, index == 2u));
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.tex_coord = fma(position, vec2<f32>(0.5, -0.5), vec2<f32>(0.5, 0.5));
+    return out;
+}
+
+// The effects work on the sRGB values, as the screen buffer does
+fn to_srgb(color: vec3<f32>) -> vec3<f32> {
+    return select(
+        1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055,
+        color * 12.92,
+        color <= vec3<f32>(0.0031308),
+    );
+}
+
+fn to_linear(color: vec3<f32>) -> vec3<f32> {
+    return select(
+        pow((color + 0.055) / 1.055, vec3<f32>(2.4)),
+        color / 12.92,
+        color <= vec3<f32>(0.04045),
+    );
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = to_srgb(textureSample(frame, nearest, in.tex_coord).rgb);
+    let old = to_srgb(textureSample(previous, nearest, in.tex_coord).rgb);
+
+    if (locals.flags & GHOSTING) != 0u {
+        color = mix(color, old, GHOSTING_WEIGHT);
+    }
+
+    if (locals.flags & COLOR_CORRECTION) != 0u {
+        color = vec3<f32>(
+            26.0 * color.r + 4.0 * color.g + 2.0 * color.b,
+            24.0 * color.g + 8.0 * color.b,
+            6.0 * color.r + 4.0 * color.g + 22.0 * color.b,
+        ) / 32.0;
+    }
+
+    if (locals.flags & GRID) != 0u && locals.scale >= GRID_MIN_SCALE {
+        let cell = floor(in.position.xy - locals.origin) % locals.scale;
+        if any(cell >= vec2<f32>(locals.scale - 1.0)) {
+            color *= GRID_DIM;
+        }
+    }
+
+    return vec4<f32>(to_linear(color), 1.0);
+}
@@ -0,0 +1,149 @@
+use image::RgbaImage;
+
+use super::capture::screen_image;
+
+// Keep in sync with lcd.wgsl
+const GHOSTING_WEIGHT: f32 = 0.5;
+const GRID_DIM: f32 = 0.75;
+const GRID_MIN_SCALE: u32 = 3;
+
+/// LCD post-processing drawn by the shader in lcd.wgsl.
+///
+/// `apply` does the same on the CPU, for screenshots and tests.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LcdEffects {
+    // Blend each frame with the previous one, like the slow DMG LCD response
+    pub ghosting: bool,
+    // Darken the border of each LCD pixel once scaled up enough
+    pub grid: bool,
+    // Colours as shown by the CGB LCD
+    pub color_correction: bool,
+}
+
+impl LcdEffects {
+    pub fn any(&self) -> bool {
+        self.ghosting || self.grid || self.color_correction
+    }
+
+    /// Effects enabled in the shader.
+    pub fn flags(&self) -> u32 {
+        self.ghosting as u32 | (self.grid as u32) << 1 | (self.color_correction as u32) << 2
+    }
+}
+
+fn correct_color([r, g, b]: [f32; 3]) -> [f32; 3] {
+    [
+        (26.0 * r + 4.0 * g + 2.0 * b) / 32.0,
+        (24.0 * g + 8.0 * b) / 32.0,
+        (6.0 * r + 4.0 * g + 22.0 * b) / 32.0,
+    ]
+}
+
+/// Screen buffer with the effects applied, scaled up by `scale`.
+///
+/// `previous` is the frame shown before `screen`, for ghosting.
+pub fn apply(effects: LcdEffects, screen: &[u8], previous: Option<&[u8]>, scale: u32) -> RgbaImage {
+    let mut buffer = screen.to_vec();
+
+    for (i, pixel) in buffer.chunks_exact_mut(4).enumerate() {
+        let mut rgb = [0.0; 3];
+        for (c, value) in rgb.iter_mut().enumerate() {
+            *value = pixel[c] as f32 / 255.0;
+        }
+
+        if let (true, Some(previous)) = (effects.ghosting, previous) {
+            for (c, value) in rgb.iter_mut().enumerate() {
+                let old = previous[i * 4 + c] as f32 / 255.0;
+                *value += (old - *value) * GHOSTING_WEIGHT;
+            }
+        }
+
+        if effects.color_correction {
+            rgb = correct_color(rgb);
+        }
+
+        for (c, value) in rgb.iter().enumerate() {
+            pixel[c] = (value * 255.0).round() as u8;
+        }
+    }
+
+    let mut img = screen_image(&buffer, scale);
+
+    if effects.grid && scale >= GRID_MIN_SCALE {
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            if x % scale == scale - 1 || y % scale == scale - 1 {
+                for value in &mut pixel.0[..3] {
+                    *value = (*value as f32 * GRID_DIM).round() as u8;
+                }
+            }
+        }
+    }
+
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gbr::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    use super::{apply, LcdEffects};
+
+    fn screen(rgb: [u8; 3]) -> Vec<u8> {
+        [rgb[0], rgb[1], rgb[2], 0xFF].repeat((SCREEN_WIDTH * SCREEN_HEIGHT) as usize)
+    }
+
+    #[test]
+    fn ghosting() {
+        let effects = LcdEffects {
+            ghosting: true,
+            ..Default::default()
+        };
+
+        // Sprites flickering every other frame show half transparent
+        let img = apply(effects, &screen([0; 3]), Some(&screen([0xFF; 3])), 1);
+        assert_eq!(img.get_pixel(0, 0).0, [0x80, 0x80, 0x80, 0xFF]);
+        let img = apply(effects, &screen([0xFF; 3]), Some(&screen([0; 3])), 1);
+        assert_eq!(img.get_pixel(0, 0).0, [0x80, 0x80, 0x80, 0xFF]);
+
+        // Nothing to blend with on the first frame
+        let img = apply(effects, &screen([0x40; 3]), None, 1);
+        assert_eq!(img.get_pixel(0, 0).0, [0x40, 0x40, 0x40, 0xFF]);
+    }
+
+    #[test]
+    fn pixel_grid() {
+        let effects = LcdEffects {
+            grid: true,
+            ..Default::default()
+        };
+
+        let img = apply(effects, &screen([0xFF; 3]), None, 4);
+        assert_eq!(img.dimensions(), (SCREEN_WIDTH * 4, SCREEN_HEIGHT * 4));
+        assert_eq!(img.get_pixel(2, 2).0, [0xFF; 4]);
+        assert_eq!(img.get_pixel(3, 2).0, [0xBF, 0xBF, 0xBF, 0xFF]);
+        assert_eq!(img.get_pixel(5, 7).0, [0xBF, 0xBF, 0xBF, 0xFF]);
+
+        // Too small to show a grid
+        let img = apply(effects, &screen([0xFF; 3]), None, 2);
+        assert!(img.pixels().all(|pixel| pixel.0 == [0xFF; 4]));
+    }
+
+    #[test]
+    fn color_correction() {
+        let effects = LcdEffects {
+            color_correction: true,
+            ..Default::default()
+        };
+
+        // Greys are kept, saturated colours are washed out
+        let img = apply(effects, &screen([0xFF; 3]), None, 1);
+        assert_eq!(img.get_pixel(0, 0).0, [0xFF; 4]);
+        let img = apply(effects, &screen([0xFF, 0, 0]), None, 1);
+        assert_eq!(img.get_pixel(0, 0).0, [0xCF, 0, 0x30, 0xFF]);
+        let img = apply(effects, &screen([0, 0, 0xFF]), None, 1);
+        assert_eq!(img.get_pixel(0, 0).0, [0x10, 0x40, 0xAF, 0xFF]);
+
+        assert_eq!(effects.flags(), 0b100);
+        assert!(!LcdEffects::default().any());
+    }
+}
@@ -0,0 +1,212 @@
+use pixels::{wgpu, PixelsContext};
+
+use crate::gbr::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use super::lcd_effects::LcdEffects;
+
+const LOCALS_SIZE: u64 = 16;
+
+/// Draws the pixels texture with the LCD effects, in place of the scaling renderer.
+pub struct LcdRenderer {
+    // Frame shown before the current one, for ghosting
+    previous: wgpu::Texture,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    clear_color: wgpu::Color,
+}
+
+impl LcdRenderer {
+    pub fn new(
+        context: &PixelsContext,
+        render_format: wgpu::TextureFormat,
+        clear_color: wgpu::Color,
+    ) -> Self {
+        let device = &context.device;
+        let module = device.create_shader_module(wgpu::include_wgsl!("lcd.wgsl"));
+
+        let previous = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("lcd_previous_frame"),
+            size: context.texture_extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: context.texture_format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("lcd_sampler"),
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("lcd_locals"),
+            size: LOCALS_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lcd_bind_group_layout"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(LOCALS_SIZE),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let frame_view = context.texture.create_view(&Default::default());
+        let previous_view = previous.create_view(&Default::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lcd_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&frame_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&previous_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("lcd_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("lcd_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: render_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        Self {
+            previous,
+            uniform_buffer,
+            bind_group,
+            pipeline,
+            clear_color,
+        }
+    }
+
+    /// Keep `frame` for ghosting, before the pixels texture is replaced by the next one.
+    pub fn set_previous_frame(&self, queue: &wgpu::Queue, frame: &[u8]) {
+        queue.write_texture(
+            self.previous.as_image_copy(),
+            frame,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(SCREEN_WIDTH * 4),
+                rows_per_image: Some(SCREEN_HEIGHT),
+            },
+            self.previous.size(),
+        );
+    }
+
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        render_target: &wgpu::TextureView,
+        context: &PixelsContext,
+        effects: LcdEffects,
+    ) {
+        if !effects.any() {
+            context.scaling_renderer.render(encoder, render_target);
+            return;
+        }
+
+        // Same placement as the scaling renderer, with an integer scale
+        let (x, y, width, height) = context.scaling_renderer.clip_rect();
+        let scale = width as f32 / SCREEN_WIDTH as f32;
+
+        let mut locals = Vec::with_capacity(LOCALS_SIZE as usize);
+        locals.extend_from_slice(&(x as f32).to_ne_bytes());
+        locals.extend_from_slice(&(y as f32).to_ne_bytes());
+        locals.extend_from_slice(&scale.to_ne_bytes());
+        locals.extend_from_slice(&effects.flags().to_ne_bytes());
+        context.queue.write_buffer(&self.uniform_buffer, 0, &locals);
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("lcd_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: render_target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.clear_color),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+        rpass.set_scissor_rect(x, y, width, height);
+        rpass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,307 @@
+use std::sync::mpsc::Sender;
+
+use egui::{Color32, Label, RichText, Sense, TextEdit};
+
+use crate::gbr::{
+    game_boy::{DebugEvent, GbrEvent, MemoryBanks},
+    mbc::MbcState,
+    memory_map::map_address,
+};
+
+use super::{debugger::MemoryState, watchpoints_view::parse_hex};
+
+const BYTES_PER_ROW: usize = 16;
+
+// Memory snapshots a changed byte stays highlighted for, half a second while
+// running as they are sent at the display rate
+const HIGHLIGHT_UPDATES: u8 = 30;
+
+// Parse hex bytes separated by spaces, "??" matching any byte, e.g. "3E ?? E0 40"
+fn parse_pattern(text: &str) -> Option<Vec<Option<u8>>> {
+    let mut pattern = vec![];
+
+    for token in text.split_whitespace() {
+        if token.len() % 2 != 0 || !token.is_ascii() {
+            return None;
+        }
+
+        for i in (0..token.len()).step_by(2) {
+            let byte = &token[i..i + 2];
+            pattern.push(match byte {
+                "??" => None,
+                _ => Some(u8::from_str_radix(byte, 16).ok()?),
+            });
+        }
+    }
+
+    (!pattern.is_empty()).then_some(pattern)
+}
+
+// First match of `pattern` starting at `from`, wrapping around the end
+fn find(bytes: &[u8], pattern: &[Option<u8>], from: usize) -> Option<usize> {
+    let matches = |start: &usize| {
+        pattern
+            .iter()
+            .enumerate()
+            .all(|(i, p)| p.is_none_or(|p| bytes.get(start + i) == Some(&p)))
+    };
+
+    (from..bytes.len()).chain(0..from).find(matches)
+}
+
+#[derive(Default)]
+pub struct MemoryView {
+    memory: Option<MemoryState>,
+    // Remaining updates each byte is highlighted for
+    changed: Vec<u8>,
+    banks: MemoryBanks,
+    subscribed: bool,
+    cursor: Option<u16>,
+    scroll_to: Option<u16>,
+    editing: Option<(u16, String)>,
+    goto: String,
+    search: String,
+    message: String,
+}
+
+impl MemoryView {
+    pub fn update(&mut self, memory: MemoryState) {
+        match &self.memory {
+            // Highlighting a bank switch would mark all of it
+            Some(previous)
+                if previous.rom_bank == memory.rom_bank && previous.ram_bank == memory.ram_bank =>
+            {
+                for (i, changed) in self.changed.iter_mut().enumerate() {
+                    if previous.bytes[i] != memory.bytes[i] {
+                        *changed = HIGHLIGHT_UPDATES;
+                    } else {
+                        *changed = changed.saturating_sub(1);
+                    }
+                }
+            }
+            _ => self.changed = vec![0; memory.bytes.len()],
+        }
+
+        self.memory = Some(memory);
+    }
+
+    /// Stop receiving the memory content once the view is closed.
+    pub fn close(&mut self, ev_sender: &Sender<GbrEvent>) {
+        if self.subscribed {
+            ev_sender
+                .send(GbrEvent::Debug(DebugEvent::ViewMemory(None)))
+                .unwrap();
+            self.subscribed = false;
+        }
+    }
+
+    fn select(&mut self, addr: u16) {
+        self.cursor = Some(addr);
+        self.scroll_to = Some(addr);
+    }
+
+    fn bank_selectors(&mut self, mbc: &MbcState, ui: &mut egui::Ui) -> bool {
+        let before = self.banks;
+        let bank_text = |bank: Option<u16>| match bank {
+            Some(bank) => format!("{:02X}", bank),
+            None => "Mapped".to_string(),
+        };
+
+        ui.horizontal(|ui| {
+            ui.label("ROM bank:");
+            egui::ComboBox::from_id_source("memory_rom_bank")
+                .selected_text(bank_text(self.banks.rom))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.banks.rom, None, "Mapped");
+                    for bank in 1..mbc.rom_banks_count {
+                        ui.selectable_value(&mut self.banks.rom, Some(bank), bank_text(Some(bank)));
+                    }
+                });
+
+            ui.label("RAM bank:");
+            egui::ComboBox::from_id_source("memory_ram_bank")
+                .selected_text(bank_text(self.banks.ram))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.banks.ram, None, "Mapped");
+                    for bank in 0..mbc.ram_banks_count {
+                        ui.selectable_value(&mut self.banks.ram, Some(bank), bank_text(Some(bank)));
+                    }
+                });
+        });
+
+        self.banks != before
+    }
+
+    fn search_bar(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Go to:");
+            let goto = ui.add(TextEdit::singleline(&mut self.goto).desired_width(50.0));
+            if goto.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                match parse_hex(&self.goto) {
+                    Some(addr) => self.select(addr),
+                    None => self.message = "Invalid address".to_string(),
+                }
+            }
+
+            ui.label("Find bytes:");
+            ui.add(TextEdit::singleline(&mut self.search).desired_width(120.0));
+            if ui.button("Next").clicked() {
+                let from = self.cursor.map_or(0, |addr| addr as usize + 1);
+
+                let found = match (parse_pattern(&self.search), &self.memory) {
+                    (None, _) => Err("Invalid pattern, e.g. 3E ?? E0"),
+                    (Some(_), None) => Err("No memory content"),
+                    (Some(pattern), Some(memory)) => {
+                        find(&memory.bytes, &pattern, from).ok_or("Not found")
+                    }
+                };
+
+                match found {
+                    Ok(addr) => {
+                        self.select(addr as u16);
+                        self.message.clear();
+                    }
+                    Err(e) => self.message = e.to_string(),
+                }
+            }
+        });
+    }
+
+    // Text edit replacing the byte being edited, the value is written on Enter
+    fn edit_byte(&mut self, addr: u16, ev_sender: &Sender<GbrEvent>, ui: &mut egui::Ui) {
+        let Some((_, text)) = self.editing.as_mut() else {
+            return;
+        };
+
+        let edit = ui.add(TextEdit::singleline(text).desired_width(16.0));
+        edit.request_focus();
+
+        if edit.lost_focus() {
+            if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                let value = u8::from_str_radix(text.trim(), 16);
+
+                match (value, &self.memory) {
+                    (Ok(value), Some(memory)) => ev_sender
+                        .send(GbrEvent::Debug(DebugEvent::WriteMemory(
+                            memory.banked(addr),
+                            value,
+                        )))
+                        .unwrap(),
+                    _ => self.message = "Invalid byte".to_string(),
+                }
+            }
+
+            self.editing = None;
+        }
+    }
+
+    pub fn show(&mut self, ev_sender: &Sender<GbrEvent>, mbc: &MbcState, ui: &mut egui::Ui) {
+        if self.bank_selectors(mbc, ui) || !self.subscribed {
+            ev_sender
+                .send(GbrEvent::Debug(DebugEvent::ViewMemory(Some(self.banks))))
+                .unwrap();
+            self.subscribed = true;
+        }
+
+        self.search_bar(ui);
+
+        match (self.cursor, &self.memory) {
+            (Some(addr), Some(memory)) => ui.label(format!(
+                "{} {:?}, double click to edit. {}",
+                memory.banked(addr),
+                map_address(addr),
+                self.message
+            )),
+            _ => ui.label(&self.message),
+        };
+
+        ui.separator();
+
+        let Some(memory) = self.memory.clone() else {
+            ui.label("Memory is read while the emulator is paused");
+            return;
+        };
+
+        let text_style = egui::TextStyle::Monospace;
+        let row_height = ui.text_style_height(&text_style);
+        let rows = memory.bytes.len() / BYTES_PER_ROW;
+
+        let mut scroll = egui::ScrollArea::vertical().auto_shrink([false, false]);
+        if let Some(addr) = self.scroll_to.take() {
+            let row = addr as usize / BYTES_PER_ROW;
+            scroll = scroll
+                .vertical_scroll_offset(row as f32 * (row_height + ui.spacing().item_spacing.y));
+        }
+
+        scroll.show_rows(ui, row_height, rows, |ui, range| {
+            ui.style_mut().override_text_style = Some(text_style);
+
+            for row in range {
+                let start = row * BYTES_PER_ROW;
+                let row_addr = start as u16;
+
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}", memory.banked(row_addr)));
+
+                    for i in 0..BYTES_PER_ROW {
+                        let addr = row_addr + i as u16;
+
+                        if self.editing.as_ref().is_some_and(|(a, _)| *a == addr) {
+                            self.edit_byte(addr, ev_sender, ui);
+                            continue;
+                        }
+
+                        let mut text = RichText::new(format!("{:02X}", memory.bytes[start + i]));
+                        if self.changed[start + i] > 0 {
+                            text = text.color(Color32::YELLOW);
+                        }
+                        if self.cursor == Some(addr) {
+                            text = text.background_color(ui.visuals().selection.bg_fill);
+                        }
+
+                        let byte = ui.add(Label::new(text).sense(Sense::click()));
+                        if byte.double_clicked() {
+                            self.editing = Some((addr, format!("{:02X}", memory.bytes[start + i])));
+                        } else if byte.clicked() {
+                            self.cursor = Some(addr);
+                        }
+                    }
+
+                    let ascii: String = memory.bytes[start..start + BYTES_PER_ROW]
+                        .iter()
+                        .map(|b| {
+                            if b.is_ascii_graphic() {
+                                *b as char
+                            } else {
+                                '.'
+                            }
+                        })
+                        .collect();
+                    ui.label(ascii);
+
+                    ui.weak(format!("{:?}", map_address(row_addr)));
+                });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find, parse_pattern};
+
+    #[test]
+    fn pattern_search() {
+        let pattern = parse_pattern("3E ?? e040").unwrap();
+        assert_eq!(pattern, vec![Some(0x3E), None, Some(0xE0), Some(0x40)]);
+        assert_eq!(parse_pattern("3E 4"), None);
+        assert_eq!(parse_pattern(""), None);
+
+        let bytes = [0x3E, 0x01, 0xE0, 0x40, 0x00, 0x3E, 0x02, 0xE0, 0x40];
+        assert_eq!(find(&bytes, &pattern, 0), Some(0));
+        assert_eq!(find(&bytes, &pattern, 1), Some(5));
+        // Wraps around the end
+        assert_eq!(find(&bytes, &pattern, 6), Some(0));
+        assert_eq!(find(&bytes, &[Some(0xFF)], 0), None);
+    }
+}
@@ -1,13 +1,31 @@
 pub mod debugger;
 pub mod debugger_app;
+pub mod disassembler;
+pub mod symbols;
 
 mod asm_view;
+mod breakpoint;
+mod breakpoints_view;
+mod capture;
+mod capture_view;
+mod color_schemes;
 mod cpu_view;
+mod expression;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+mod held_inputs;
+mod history;
+mod history_view;
 mod interrupts_view;
 mod joypad_view;
+mod keymap;
+mod lcd_effects;
+mod lcd_renderer;
 mod mbc_view;
+mod memory_view;
 mod oam_view;
 mod palette_view;
 mod tilemap_view;
 mod tiles_view;
 mod ui;
+mod watchpoints_view;
@@ -1,36 +1,317 @@
-use crate::gbr::oam::ObjAttribute;
-
+use egui::{Color32, ColorImage, Id, LayerId, Order, Rect, Stroke, TextureOptions, Vec2};
 use egui_extras::{Column, TableBuilder};
+use image::RgbaImage;
+
+use crate::gbr::{
+    oam::ObjAttribute,
+    ppu::{palette::Palette, tile::TileData, PpuState, SCREEN_HEIGHT, SCREEN_WIDTH, TILE_WIDTH},
+};
+
+const OBJ_COUNT: usize = 40;
+const OBJS_PER_LINE: usize = 10;
+const MAX_HEIGHT: u32 = 16;
+const ZOOM: f32 = 2.0;
+
+const SELECTED_COLOR: Color32 = Color32::from_rgb(255, 64, 64);
+
+fn obj_height(ppu: &PpuState) -> i16 {
+    if ppu.lcd_control.obj_size_sel {
+        16
+    } else {
+        8
+    }
+}
+
+/// Objects drawn side by side in a 320x16 image, colour 0 transparent.
+fn objs_image(
+    oam: &[ObjAttribute],
+    tiles: &TileData,
+    palettes: &[Palette],
+    height: i16,
+) -> RgbaImage {
+    let mut img = RgbaImage::new(OBJ_COUNT as u32 * TILE_WIDTH, MAX_HEIGHT);
+
+    for (i, obj) in oam.iter().enumerate() {
+        // 8x16 objects ignore the tile index low bit
+        let first_tile = if height == 16 {
+            obj.tile_index() & !1
+        } else {
+            obj.tile_index()
+        } as usize;
+        let palette = &palettes[obj.palette_id() as usize];
+
+        for r in 0..height as usize {
+            let src_r = if obj.flip_y() {
+                height as usize - 1 - r
+            } else {
+                r
+            };
+            let tile = &tiles.list()[first_tile + src_r / 8];
+            let line = tile.line(src_r % 8, false, obj.flip_x());
+
+            for (c, color_id) in line.iter().enumerate() {
+                let rgba = match color_id {
+                    0 => [0; 4],
+                    _ => palette.rgba(*color_id).rgba,
+                };
+                img.put_pixel(
+                    i as u32 * TILE_WIDTH + c as u32,
+                    r as u32,
+                    image::Rgba(rgba),
+                );
+            }
+        }
+    }
+
+    img
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct ObjStatus {
+    off_screen: bool,
+    // Lines the object is not drawn on, past the first 10 objects of the line
+    dropped_lines: u8,
+}
+
+fn objs_status(oam: &[ObjAttribute], height: i16) -> Vec<ObjStatus> {
+    let mut status: Vec<ObjStatus> = oam
+        .iter()
+        .map(|obj| ObjStatus {
+            off_screen: obj.top() <= -height
+                || obj.top() >= SCREEN_HEIGHT as i16
+                || obj.left() <= -(TILE_WIDTH as i16)
+                || obj.left() >= SCREEN_WIDTH as i16,
+            dropped_lines: 0,
+        })
+        .collect();
+
+    // Objects are selected in OAM order, whatever their x
+    for ly in 0..SCREEN_HEIGHT as i16 {
+        let on_line = oam
+            .iter()
+            .enumerate()
+            .filter(|(_, obj)| obj.top() <= ly && ly < obj.top() + height);
+
+        for (index, _) in on_line.skip(OBJS_PER_LINE) {
+            status[index].dropped_lines += 1;
+        }
+    }
+
+    status
+}
+
+#[derive(Default)]
+pub struct OamView {
+    texture: Option<egui::TextureHandle>,
+    selected: Option<usize>,
+}
+
+impl OamView {
+    // Rectangle of the selected object, in LCD pixels
+    fn selected_rect(&self, ppu: &PpuState, oam: &[ObjAttribute]) -> Option<Rect> {
+        let obj = oam.get(self.selected?)?;
+
+        Some(Rect::from_min_size(
+            [obj.left() as f32, obj.top() as f32].into(),
+            Vec2::new(TILE_WIDTH as f32, obj_height(ppu) as f32),
+        ))
+    }
+
+    /// Outline the selected object over the game screen, drawn at `screen` in points.
+    pub fn highlight_selected(
+        &self,
+        ppu: &PpuState,
+        oam: &[ObjAttribute],
+        screen: Rect,
+        ctx: &egui::Context,
+    ) {
+        let Some(rect) = self.selected_rect(ppu, oam) else {
+            return;
+        };
+
+        let scale = screen.width() / SCREEN_WIDTH as f32;
+        let rect =
+            Rect::from_min_size(screen.min + rect.min.to_vec2() * scale, rect.size() * scale);
+
+        ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("oam_highlight")))
+            .with_clip_rect(screen)
+            .rect_stroke(rect, 0.0, Stroke::new(2.0, SELECTED_COLOR));
+    }
+
+    pub fn show(&mut self, ppu: &PpuState, oam: &[ObjAttribute], ui: &mut egui::Ui) {
+        if oam.is_empty() || ppu.tiles.list().is_empty() {
+            return;
+        }
+
+        let height = obj_height(ppu);
+        let status = objs_status(oam, height);
 
-pub fn show(oam: &[ObjAttribute], ui: &mut egui::Ui) {
-    let text_height = egui::TextStyle::Body.resolve(ui.style()).size;
-
-    egui::ScrollArea::horizontal()
-        .auto_shrink([true, false])
-        .show(ui, |ui| {
-            TableBuilder::new(ui)
-                .striped(true)
-                .resizable(false)
-                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
-                .column(Column::exact(10.0))
-                .column(Column::exact(10.0))
-                .column(Column::remainder())
-                .min_scrolled_height(0.0)
-                .body(|body| {
-                    body.rows(text_height, oam.len(), |index, mut row| {
-                        let obj = &oam[index];
-
-                        row.col(|ui| {
-                            ui.label(format!("{}", index));
-                        });
-
-                        row.col(|ui| {
-                            ui.label(format!("{}", obj.tile_index()));
-                        });
-                        row.col(|ui| {
-                            ui.label(format!("{}, {}", obj.top(), obj.left()));
-                        });
+        let img = objs_image(oam, &ppu.tiles, &ppu.obj_palettes, height);
+        let size = [img.width() as _, img.height() as _];
+        let image = ColorImage::from_rgba_unmultiplied(size, img.as_raw());
+
+        let texture = self.texture.get_or_insert_with(|| {
+            ui.ctx()
+                .load_texture("oam_view", image.clone(), TextureOptions::NEAREST)
+        });
+        texture.set(image, TextureOptions::NEAREST);
+        let texture_id = texture.id();
+
+        let text_height = egui::TextStyle::Body.resolve(ui.style()).size;
+        let row_height = text_height.max(height as f32 * ZOOM);
+        let thumbnail = Vec2::new(TILE_WIDTH as f32, height as f32) * ZOOM;
+
+        ui.horizontal(|ui| {
+            ui.label(format!("Objects 8x{}", height));
+            if self.selected.is_some() && ui.button("Clear selection").clicked() {
+                self.selected = None;
+            }
+        });
+
+        TableBuilder::new(ui)
+            .striped(true)
+            .resizable(false)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .column(Column::exact(24.0))
+            .column(Column::exact(thumbnail.x))
+            .column(Column::auto())
+            .column(Column::remainder())
+            .min_scrolled_height(0.0)
+            .body(|body| {
+                body.rows(row_height, oam.len(), |index, mut row| {
+                    let obj = &oam[index];
+
+                    row.col(|ui| {
+                        let selected = self.selected == Some(index);
+                        if ui.selectable_label(selected, index.to_string()).clicked() {
+                            self.selected = (!selected).then_some(index);
+                        }
+                    });
+
+                    row.col(|ui| {
+                        let u = index as f32 / OBJ_COUNT as f32;
+                        let uv = Rect::from_min_max(
+                            [u, 0.0].into(),
+                            [
+                                u + 1.0 / OBJ_COUNT as f32,
+                                height as f32 / MAX_HEIGHT as f32,
+                            ]
+                            .into(),
+                        );
+                        ui.add(egui::Image::new(texture_id, thumbnail).uv(uv));
+                    });
+
+                    row.col(|ui| {
+                        ui.label(format!(
+                            "({}, {}) tile {:02X} pal {}{}{}{}",
+                            obj.left(),
+                            obj.top(),
+                            obj.tile_index(),
+                            obj.palette_id(),
+                            if obj.flip_x() { " X" } else { "" },
+                            if obj.flip_y() { " Y" } else { "" },
+                            if obj.bg_win_prio() { " behind BG" } else { "" },
+                        ));
+                    });
+
+                    row.col(|ui| {
+                        let status = status[index];
+                        if status.off_screen {
+                            ui.weak("off-screen");
+                        }
+                        if status.dropped_lines > 0 {
+                            ui.colored_label(
+                                SELECTED_COLOR,
+                                format!("dropped on {} lines", status.dropped_lines),
+                            );
+                        }
                     });
                 });
-        });
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gbr::{bus::BusAccess, game_boy::GameBoy, ppu::palette::Palette};
+
+    use super::{objs_image, objs_status, ObjStatus};
+
+    fn write_obj(gb: &mut GameBoy, index: u16, y: u8, x: u8, tile: u8, flags: u8) {
+        let bus = gb.bus_mut();
+        let addr = 0xFE00 + index * 4;
+        bus.write_byte(addr, y).unwrap();
+        bus.write_byte(addr + 1, x).unwrap();
+        bus.write_byte(addr + 2, tile).unwrap();
+        bus.write_byte(addr + 3, flags).unwrap();
+    }
+
+    #[test]
+    fn thumbnails() {
+        let mut gb = GameBoy::with_program(&[]);
+        // Tile 2 first line colour 3 on the left half, tile 3 last line colour 1
+        let bus = gb.bus_mut();
+        bus.write_byte(0x8020, 0xF0).unwrap();
+        bus.write_byte(0x8021, 0xF0).unwrap();
+        bus.write_byte(0x803E, 0xFF).unwrap();
+
+        // Object 1 flipped horizontally with OBJ1, object 2 flipped vertically
+        write_obj(&mut gb, 0, 16, 8, 2, 0);
+        write_obj(&mut gb, 1, 16, 8, 2, 0b0011_0000);
+        write_obj(&mut gb, 2, 16, 8, 3, 0b0100_0000);
+
+        let state = gb.collect_state();
+        let palettes = [Palette::from(0xE4), Palette::from(0x1B)];
+
+        let img = objs_image(&state.oam, &state.ppu.tiles, &palettes, 8);
+        assert_eq!(img.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(img.get_pixel(7, 0).0, [0; 4]);
+        // OBJ1 maps colour 3 to white
+        assert_eq!(img.get_pixel(15, 0).0, [255, 255, 255, 255]);
+        assert_eq!(img.get_pixel(8, 0).0, [0; 4]);
+        // Flipped vertically, the last line of tile 3 comes first
+        assert_eq!(img.get_pixel(16, 0).0, [168, 168, 168, 255]);
+        assert_eq!(img.get_pixel(16, 7).0, [0; 4]);
+
+        // 8x16 objects use tiles 2 and 3, flipped as a whole
+        let img = objs_image(&state.oam, &state.ppu.tiles, &palettes, 16);
+        assert_eq!(img.get_pixel(0, 15).0, [168, 168, 168, 255]);
+        assert_eq!(img.get_pixel(16, 0).0, [168, 168, 168, 255]);
+        assert_eq!(img.get_pixel(16, 15).0, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn line_limit() {
+        let mut gb = GameBoy::with_program(&[]);
+
+        // 12 objects on lines 16 to 23, the last two 4 lines lower
+        for index in 0..12 {
+            let y = if index < 10 { 32 } else { 36 };
+            write_obj(&mut gb, index, y, 8 + index as u8 * 8, 0, 0);
+        }
+        // Off screen on the right, still counted for the line limit
+        write_obj(&mut gb, 12, 32, 200, 0, 0);
+
+        let state = gb.collect_state();
+        let status = objs_status(&state.oam, 8);
+
+        assert_eq!(status[0], ObjStatus::default());
+        assert_eq!(status[9], ObjStatus::default());
+        assert_eq!(
+            status[10],
+            ObjStatus {
+                off_screen: false,
+                dropped_lines: 4
+            }
+        );
+        assert_eq!(
+            status[12],
+            ObjStatus {
+                off_screen: true,
+                dropped_lines: 8
+            }
+        );
+        assert!(status[13].off_screen);
+        assert_eq!(status[13].dropped_lines, 0);
+    }
 }
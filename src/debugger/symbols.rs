@@ -0,0 +1,163 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::gbr::{instruction::SymbolResolver, mbc::MbcState, memory_map::BankedAddress};
+
+/// Labels read from a RGBDS or wla-dx symbol file, one "bank:addr label" per line.
+#[derive(Default, Clone)]
+pub struct Symbols {
+    names: HashMap<BankedAddress, String>,
+    addrs: HashMap<String, BankedAddress>,
+}
+
+impl Symbols {
+    pub fn parse(text: &str) -> Self {
+        let mut symbols = Self::default();
+        // wla-dx files have sections, only [labels] holds addresses and names
+        let mut in_labels = true;
+
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap().trim();
+
+            if line.starts_with('[') {
+                in_labels = line == "[labels]";
+                continue;
+            }
+
+            if !in_labels {
+                continue;
+            }
+
+            let Some((addr, name)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let Some((bank, addr)) = addr.split_once(':') else {
+                continue;
+            };
+            let (Ok(bank), Ok(addr)) =
+                (u16::from_str_radix(bank, 16), u16::from_str_radix(addr, 16))
+            else {
+                continue;
+            };
+
+            symbols.add(BankedAddress::new(bank, addr), name.trim());
+        }
+
+        symbols
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    fn add(&mut self, addr: BankedAddress, name: &str) {
+        // The first label of an address names it, usually the global one
+        self.names.entry(addr).or_insert_with(|| name.to_string());
+        self.addrs.insert(name.to_string(), addr);
+    }
+
+    pub fn len(&self) -> usize {
+        self.addrs.len()
+    }
+
+    pub fn name(&self, addr: &BankedAddress) -> Option<&str> {
+        self.names.get(addr).map(String::as_str)
+    }
+
+    pub fn addr(&self, name: &str) -> Option<BankedAddress> {
+        self.addrs.get(name).copied()
+    }
+
+    /// Resolve operand addresses in the banks currently mapped by `mbc`.
+    pub fn resolver<'a>(&'a self, mbc: &'a MbcState) -> MappedSymbols<'a> {
+        MappedSymbols { symbols: self, mbc }
+    }
+}
+
+pub struct MappedSymbols<'a> {
+    symbols: &'a Symbols,
+    mbc: &'a MbcState,
+}
+
+impl SymbolResolver for MappedSymbols<'_> {
+    fn symbol(&self, addr: u16) -> Option<&str> {
+        self.symbols.name(&BankedAddress::resolve(addr, self.mbc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gbr::{
+        instruction::{opcode::Opcode, Instruction},
+        mbc::MbcState,
+        memory_map::BankedAddress,
+    };
+
+    use super::Symbols;
+
+    #[test]
+    fn rgbds() {
+        let symbols = Symbols::parse(
+            "; File generated by rgblink\n\
+             00:0150 Main\n\
+             00:0150 Main.loop\n\
+             02:4A3C UpdateSprites\n\
+             00:C000 wPlayerX\n",
+        );
+
+        assert_eq!(symbols.len(), 4);
+        assert_eq!(symbols.name(&BankedAddress::new(0, 0x0150)), Some("Main"));
+        assert_eq!(
+            symbols.addr("UpdateSprites"),
+            Some(BankedAddress::new(2, 0x4A3C))
+        );
+        assert_eq!(
+            symbols.addr("Main.loop"),
+            Some(BankedAddress::new(0, 0x0150))
+        );
+    }
+
+    #[test]
+    fn wla_dx() {
+        let symbols = Symbols::parse(
+            "[labels]\n\
+             0000:0150 Main\n\
+             [definitions]\n\
+             00000010 SPEED\n\
+             [addr-to-line mapping]\n\
+             00:0150 0000:0000001a\n",
+        );
+
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols.addr("Main"), Some(BankedAddress::new(0, 0x0150)));
+    }
+
+    #[test]
+    fn operands() {
+        let symbols = Symbols::parse("02:4A3C UpdateSprites\n00:C000 wPlayerX\n");
+        let mbc = MbcState {
+            active_rom_bank: 2,
+            ..Default::default()
+        };
+
+        let call = Instruction::decode(Opcode::Call, None, Some(0x4A3C)).unwrap();
+        let resolver = symbols.resolver(&mbc);
+        assert_eq!(
+            format!("{}", call.display(0x0150, &resolver)),
+            "(0xCD) Call , UpdateSprites"
+        );
+
+        let store = Instruction::decode(Opcode::Lda16A, None, Some(0xC000)).unwrap();
+        assert_eq!(
+            format!("{}", store.display(0x0150, &resolver)),
+            "(0xEA) Store wPlayerX A"
+        );
+
+        // Another bank is mapped
+        let mbc = MbcState::default();
+        let resolver = symbols.resolver(&mbc);
+        assert_eq!(
+            format!("{}", call.display(0x0150, &resolver)),
+            "(0xCD) Call , 0x4A3C"
+        );
+    }
+}
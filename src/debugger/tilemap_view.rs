@@ -1,47 +1,108 @@
-use egui::{ColorImage, TextureOptions};
-use image::{GenericImage, RgbaImage};
+use egui::{Color32, ColorImage, Rect, Stroke, TextureOptions, Vec2};
+use image::RgbaImage;
 
-use crate::gbr::ppu::{
-    palette::{GrayShade, Palette},
-    tile::{TileData, TileMap},
-    TILE_HEIGHT, TILE_WIDTH,
+use crate::gbr::{
+    memory_map::VRAM_START,
+    ppu::{
+        lcd_control_register::LcdControlRegister, palette::Palette, tile::TileData, PpuState,
+        SCREEN_HEIGHT, SCREEN_WIDTH, TILE_DATA_SIZE, TILE_HEIGHT, TILE_WIDTH,
+    },
 };
 
 const TILE_PER_ROW: usize = 32;
 const ROWS: usize = 32;
+const MAP_SIZE: u32 = 256;
+const ZOOM: f32 = 2.0;
 
-fn create_image(tilemap: &TileMap, tiles: &TileData, bg_tile_area: bool) -> ColorImage {
-    let pal = Palette::new(
-        GrayShade::White,
-        GrayShade::LightGray,
-        GrayShade::DarkGray,
-        GrayShade::Black,
-    );
+const TILEMAP_ADDR: [u16; 2] = [0x9800, 0x9C00];
+// The window is drawn from screen x WX - 7
+const WIN_X_OFFSET: i16 = 7;
 
-    let w = TILE_PER_ROW * TILE_WIDTH as usize;
-    let h = ROWS * TILE_HEIGHT as usize;
+const VIEWPORT_COLOR: Color32 = Color32::from_rgb(255, 64, 64);
+const WINDOW_COLOR: Color32 = Color32::from_rgb(64, 160, 255);
 
-    let mut img = RgbaImage::new(w as u32, h as u32);
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TilemapLayer {
+    Bg,
+    Window,
+}
+
+// Tilemap the LCDC selects for `layer`, 0 at $9800 and 1 at $9C00
+fn selected_map(layer: TilemapLayer, lcd_ctrl: &LcdControlRegister) -> usize {
+    match layer {
+        TilemapLayer::Bg => lcd_ctrl.bg_tile_map_area_sel as usize,
+        TilemapLayer::Window => lcd_ctrl.window_tile_area_sel as usize,
+    }
+}
+
+fn map_image(ppu: &PpuState, map: usize, palette: &Palette) -> RgbaImage {
+    let mut img = RgbaImage::new(MAP_SIZE, MAP_SIZE);
+
+    for r in 0..MAP_SIZE {
+        let line = ppu.tilemaps[map].line(
+            r as usize,
+            &ppu.tiles,
+            ppu.lcd_control.bg_and_window_tile_area_sel,
+        );
+
+        for (c, color_id) in line.iter().enumerate() {
+            img.put_pixel(c as u32, r, image::Rgba(palette.rgba(*color_id).rgba));
+        }
+    }
+
+    img
+}
+
+// Ranges covered by `size` pixels from `start`, split where they wrap around the map
+fn wrapped_ranges(start: u8, size: u32) -> Vec<(u32, u32)> {
+    let start = start as u32;
 
-    for r in 0..h {
-        let line = tilemap.line(r, tiles, bg_tile_area);
+    if start + size <= MAP_SIZE {
+        vec![(start, size)]
+    } else {
+        vec![(start, MAP_SIZE - start), (0, start + size - MAP_SIZE)]
+    }
+}
+
+/// Rectangles of the map shown on screen by SCX and SCY, as x, y, width and height.
+fn viewport_rects(scx: u8, scy: u8) -> Vec<(u32, u32, u32, u32)> {
+    let mut rects = vec![];
 
-        for c in 0..line.len() {
-            img.put_pixel(c as u32, r as u32, image::Rgba(pal.rgba(line[c]).rgba));
+    for (y, h) in wrapped_ranges(scy, SCREEN_HEIGHT) {
+        for (x, w) in wrapped_ranges(scx, SCREEN_WIDTH) {
+            rects.push((x, y, w, h));
         }
     }
 
-    let resized = image::imageops::resize(
-        &img,
-        img.width() * 2,
-        img.height() * 2,
-        image::imageops::FilterType::Nearest,
-    );
+    rects
+}
+
+/// Rectangle of the window map shown on screen by WX and WY, None if off screen.
+fn window_rect(wx: u8, wy: u8) -> Option<(u32, u32, u32, u32)> {
+    let screen_x = wx as i16 - WIN_X_OFFSET;
+    if screen_x >= SCREEN_WIDTH as i16 || wy as u32 >= SCREEN_HEIGHT {
+        return None;
+    }
+
+    // Left of the screen, the first columns are hidden
+    let map_x = (-screen_x).max(0) as u32;
+    let width = SCREEN_WIDTH - screen_x.max(0) as u32;
+
+    Some((map_x, 0, width, SCREEN_HEIGHT - wy as u32))
+}
+
+fn entry_info(ppu: &PpuState, map: usize, row: usize, col: usize) -> String {
+    let tile_area = ppu.lcd_control.bg_and_window_tile_area_sel;
+    let value = ppu.tilemaps[map].at(row, col);
+    let index = TileData::tile_index_from_bg_map(value as usize, tile_area);
 
-    let size = [resized.width() as _, resized.height() as _];
-    let pixels = resized.as_flat_samples();
+    let entry_addr = TILEMAP_ADDR[map] as usize + row * TILE_PER_ROW + col;
+    let tile_addr = VRAM_START as usize + index * TILE_DATA_SIZE;
 
-    ColorImage::from_rgba_unmultiplied(size, pixels.as_slice())
+    format!(
+        "Entry ({}, {}), ${:04X} = ${:02X}\nTile {}, ${:04X}",
+        col, row, entry_addr, value, index, tile_addr
+    )
 }
 
 #[derive(Default)]
@@ -50,29 +111,151 @@ pub struct TilemapView {
 }
 
 impl TilemapView {
-    pub fn show(
-        &mut self,
-        tilemap: &TileMap,
-        tiles: &TileData,
-        bg_tile_area: bool,
-        ui: &mut egui::Ui,
-    ) {
-        match self.texture.as_mut() {
-            None => {
-                self.texture = Some(ui.ctx().load_texture(
-                    "tiles_view",
-                    create_image(tilemap, tiles, bg_tile_area),
-                    egui::TextureOptions::default(),
-                ));
+    pub fn show(&mut self, layer: TilemapLayer, ppu: &PpuState, ui: &mut egui::Ui) {
+        let map = selected_map(layer, &ppu.lcd_control);
+        ui.label(format!("Map at ${:04X}", TILEMAP_ADDR[map]));
+
+        let img = map_image(ppu, map, &ppu.bg_palette);
+        let size = [img.width() as _, img.height() as _];
+        let image = ColorImage::from_rgba_unmultiplied(size, img.as_raw());
+
+        let texture = self.texture.get_or_insert_with(|| {
+            ui.ctx()
+                .load_texture("tilemap_view", image.clone(), TextureOptions::NEAREST)
+        });
+        texture.set(image, TextureOptions::NEAREST);
+
+        let response = ui.image(texture.id(), texture.size_vec2() * ZOOM);
+        let origin = response.rect.min;
+
+        let to_rect = |(x, y, w, h): (u32, u32, u32, u32)| {
+            Rect::from_min_size(
+                origin + Vec2::new(x as f32, y as f32) * ZOOM,
+                Vec2::new(w as f32, h as f32) * ZOOM,
+            )
+        };
+        let painter = ui.painter_at(response.rect);
+
+        match layer {
+            TilemapLayer::Bg => {
+                for rect in viewport_rects(ppu.viewport.x, ppu.viewport.y) {
+                    painter.rect_stroke(to_rect(rect), 0.0, Stroke::new(1.0, VIEWPORT_COLOR));
+                }
+
+                // Where the window starts covering the background
+                if ppu.lcd_control.window_enable
+                    && window_rect(ppu.win_pos.x, ppu.win_pos.y).is_some()
+                {
+                    let x = ppu
+                        .viewport
+                        .x
+                        .wrapping_add(ppu.win_pos.x)
+                        .wrapping_sub(WIN_X_OFFSET as u8);
+                    let y = ppu.viewport.y.wrapping_add(ppu.win_pos.y);
+                    let center = origin + Vec2::new(x as f32, y as f32) * ZOOM;
+                    painter.circle_stroke(center, 3.0, Stroke::new(1.0, WINDOW_COLOR));
+                }
+            }
+            TilemapLayer::Window => {
+                if let Some(rect) = window_rect(ppu.win_pos.x, ppu.win_pos.y) {
+                    painter.rect_stroke(to_rect(rect), 0.0, Stroke::new(1.0, WINDOW_COLOR));
+                }
+            }
+        }
+
+        if let Some(pos) = response.hover_pos() {
+            let tile_size = TILE_WIDTH as f32 * ZOOM;
+            let entry = (pos - origin) / Vec2::new(tile_size, TILE_HEIGHT as f32 * ZOOM);
+            let (col, row) = (entry.x as usize, entry.y as usize);
+
+            if col < TILE_PER_ROW && row < ROWS {
+                let min = origin + Vec2::new(col as f32, row as f32) * tile_size;
+                painter.rect_stroke(
+                    Rect::from_min_size(min, Vec2::splat(tile_size)),
+                    0.0,
+                    Stroke::new(1.0, Color32::WHITE),
+                );
+                response.on_hover_text_at_pointer(entry_info(ppu, map, row, col));
             }
-            Some(tex_ref) => tex_ref.set(
-                create_image(tilemap, tiles, bg_tile_area),
-                TextureOptions::default(),
-            ),
         }
 
-        let tex_ref = self.texture.as_ref().unwrap();
+        ui.horizontal(|ui| {
+            if layer == TilemapLayer::Bg {
+                ui.colored_label(
+                    VIEWPORT_COLOR,
+                    format!("SCX {} SCY {}", ppu.viewport.x, ppu.viewport.y),
+                );
+            }
+            ui.colored_label(
+                WINDOW_COLOR,
+                format!("WX {} WY {}", ppu.win_pos.x, ppu.win_pos.y),
+            );
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gbr::{
+        bus::BusAccess, game_boy::GameBoy, ppu::lcd_control_register::LcdControlRegister,
+    };
+
+    use super::{entry_info, selected_map, viewport_rects, window_rect, TilemapLayer};
+
+    #[test]
+    fn lcdc_map_select() {
+        let lcd_ctrl = LcdControlRegister::from(0b0100_0000);
+        assert_eq!(selected_map(TilemapLayer::Bg, &lcd_ctrl), 0);
+        assert_eq!(selected_map(TilemapLayer::Window, &lcd_ctrl), 1);
+
+        let lcd_ctrl = LcdControlRegister::from(0b0000_1000);
+        assert_eq!(selected_map(TilemapLayer::Bg, &lcd_ctrl), 1);
+        assert_eq!(selected_map(TilemapLayer::Window, &lcd_ctrl), 0);
+    }
+
+    #[test]
+    fn viewport_wrap_around() {
+        assert_eq!(viewport_rects(16, 8), [(16, 8, 160, 144)]);
+        assert_eq!(viewport_rects(96, 0), [(96, 0, 160, 144)]);
+
+        assert_eq!(
+            viewport_rects(200, 150),
+            [
+                (200, 150, 56, 106),
+                (0, 150, 104, 106),
+                (200, 0, 56, 38),
+                (0, 0, 104, 38)
+            ]
+        );
+    }
+
+    #[test]
+    fn window_origin() {
+        assert_eq!(window_rect(7, 0), Some((0, 0, 160, 144)));
+        assert_eq!(window_rect(87, 100), Some((0, 0, 80, 44)));
+        // Left of the screen edge
+        assert_eq!(window_rect(3, 0), Some((4, 0, 160, 144)));
+        assert_eq!(window_rect(167, 0), None);
+        assert_eq!(window_rect(7, 144), None);
+    }
+
+    #[test]
+    fn map_entry() {
+        let mut gb = GameBoy::with_program(&[]);
+        let bus = gb.bus_mut();
+
+        // Signed tile indices, map at $9C00
+        bus.write_byte(0xFF40, 0b1000_1000).unwrap();
+        bus.write_byte(0x9C00 + 2 * 32 + 3, 0x80).unwrap();
 
-        ui.image(tex_ref, tex_ref.size_vec2());
+        let state = gb.collect_state();
+        assert_eq!(
+            entry_info(&state.ppu, 1, 2, 3),
+            "Entry (3, 2), $9C43 = $80\nTile 128, $8800"
+        );
+        assert_eq!(
+            entry_info(&state.ppu, 1, 0, 0),
+            "Entry (0, 0), $9C00 = $00\nTile 256, $9000"
+        );
     }
 }
@@ -1,75 +1,273 @@
-use egui::{ColorImage, TextureOptions};
-use image::{GenericImage, RgbaImage};
+use std::path::Path;
 
-use crate::gbr::ppu::{palette::Palette, tile::TileData, TILE_HEIGHT, TILE_WIDTH};
+use egui::{Color32, ColorImage, Rect, Stroke, TextureOptions, Vec2};
+use image::RgbaImage;
+
+use crate::gbr::{
+    memory_map::VRAM_START,
+    oam::ObjAttribute,
+    ppu::{
+        palette::Palette, tile::TileData, PpuState, SCREEN_HEIGHT, SCREEN_WIDTH, TILE_DATA_SIZE,
+        TILE_HEIGHT, TILE_WIDTH,
+    },
+};
+
+use super::capture::{save_image, DEFAULT_DIR};
 
 const TILE_PER_ROW: usize = 16;
+const ZOOM: f32 = 2.0;
+const HIGHLIGHT_COLOR: Color32 = Color32::from_rgb(255, 64, 64);
+// Identity palette, shades 0 to 3
+const DEFAULT_CUSTOM_PALETTE: u8 = 0xE4;
 
-// TODO: replace usage if image library with handmade
-// tilemap to frame generator
-fn create_image(tiles: &TileData, palette: &Palette) -> ColorImage {
-    let tiles = tiles.list();
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PaletteChoice {
+    Bg,
+    Obj0,
+    Obj1,
+    Custom,
+}
 
-    let rows_count = tiles.len() / TILE_PER_ROW as usize;
-    let w = TILE_PER_ROW * TILE_WIDTH as usize;
-    let h = rows_count * TILE_HEIGHT as usize;
+fn sheet_image(tiles: &TileData, palette: &Palette) -> RgbaImage {
+    let tiles = tiles.list();
+    let rows = tiles.len() / TILE_PER_ROW;
 
-    let mut img = RgbaImage::new(w as u32, h as u32);
+    let mut img = RgbaImage::new(TILE_PER_ROW as u32 * TILE_WIDTH, rows as u32 * TILE_HEIGHT);
 
     for (i, tile) in tiles.iter().enumerate() {
-        let x = (i as u32) % TILE_PER_ROW as u32 * TILE_WIDTH;
-        let y = (i as u32) / TILE_PER_ROW as u32 * TILE_HEIGHT;
+        let x = (i % TILE_PER_ROW) as u32 * TILE_WIDTH;
+        let y = (i / TILE_PER_ROW) as u32 * TILE_HEIGHT;
+
+        for (r, line) in tile.pixels.iter().enumerate() {
+            for (c, color_id) in line.iter().enumerate() {
+                img.put_pixel(
+                    x + c as u32,
+                    y + r as u32,
+                    image::Rgba(palette.rgba(*color_id).rgba),
+                );
+            }
+        }
+    }
 
-        let mut tile_region = img.sub_image(x, y, TILE_WIDTH, TILE_HEIGHT);
+    img
+}
+
+// Tiles drawn by the selected BG and window maps and by the objects on screen
+fn referenced_tiles(ppu: &PpuState, oam: &[ObjAttribute]) -> Vec<bool> {
+    let lcd_ctrl = &ppu.lcd_control;
+    let mut referenced = vec![false; ppu.tiles.list().len()];
+
+    let mut maps = vec![lcd_ctrl.bg_tile_map_area_sel as usize];
+    if lcd_ctrl.window_enable {
+        maps.push(lcd_ctrl.window_tile_area_sel as usize);
+    }
 
-        for r in 0..TILE_HEIGHT as usize {
-            for c in 0..TILE_WIDTH as usize {
-                tile_region.put_pixel(
-                    c as u32,
-                    r as u32,
-                    image::Rgba(palette.rgba(tile.pixels[r][c]).rgba),
+    for map in maps {
+        for r in 0..32 {
+            for c in 0..32 {
+                let index = TileData::tile_index_from_bg_map(
+                    ppu.tilemaps[map].at(r, c) as usize,
+                    lcd_ctrl.bg_and_window_tile_area_sel,
                 );
+                referenced[index] = true;
             }
         }
     }
 
-    let resized = image::imageops::resize(
-        &img,
-        img.width() * 2,
-        img.height() * 2,
-        image::imageops::FilterType::Nearest,
-    );
+    let height = if lcd_ctrl.obj_size_sel { 16 } else { 8 };
+    for obj in oam {
+        let on_screen = obj.top() > -height
+            && obj.top() < SCREEN_HEIGHT as i16
+            && obj.left() > -(TILE_WIDTH as i16)
+            && obj.left() < SCREEN_WIDTH as i16;
 
-    let size = [resized.width() as _, resized.height() as _];
-    let pixels = resized.as_flat_samples();
+        if on_screen {
+            let index = obj.tile_index() as usize;
+            if lcd_ctrl.obj_size_sel {
+                // 8x16 objects ignore the tile index low bit
+                referenced[index & !1] = true;
+                referenced[index | 1] = true;
+            } else {
+                referenced[index] = true;
+            }
+        }
+    }
+
+    referenced
+}
+
+fn tile_info(tiles: &TileData, index: usize) -> String {
+    let addr = VRAM_START as usize + index * TILE_DATA_SIZE;
+    let data = tiles.list()[index].data();
+
+    let bytes: Vec<String> = data
+        .chunks(8)
+        .map(|half| {
+            half.iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
 
-    ColorImage::from_rgba_unmultiplied(size, pixels.as_slice())
+    format!("Tile {}, ${:04X}\n{}", index, addr, bytes.join("\n"))
 }
 
-#[derive(Default)]
 pub struct TilesView {
     texture: Option<egui::TextureHandle>,
+    palette: PaletteChoice,
+    custom_palette: u8,
+    highlight: bool,
+    message: String,
+}
+
+impl Default for TilesView {
+    fn default() -> Self {
+        Self {
+            texture: None,
+            palette: PaletteChoice::Bg,
+            custom_palette: DEFAULT_CUSTOM_PALETTE,
+            highlight: false,
+            message: String::new(),
+        }
+    }
 }
 
 impl TilesView {
-    pub fn show(&mut self, tiles: &TileData, palette: &Palette, ui: &mut egui::Ui) {
-        if tiles.list().len() > TILE_PER_ROW {
-            match self.texture.as_mut() {
-                None => {
-                    self.texture = Some(ui.ctx().load_texture(
-                        "tiles_view",
-                        create_image(tiles, palette),
-                        egui::TextureOptions::default(),
-                    ));
-                }
-                Some(tex_ref) => {
-                    tex_ref.set(create_image(tiles, palette), TextureOptions::default())
-                }
+    pub fn show(&mut self, ppu: &PpuState, oam: &[ObjAttribute], ui: &mut egui::Ui) {
+        if ppu.tiles.list().is_empty() {
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.palette, PaletteChoice::Bg, "BG");
+            ui.radio_value(&mut self.palette, PaletteChoice::Obj0, "OBJ0");
+            ui.radio_value(&mut self.palette, PaletteChoice::Obj1, "OBJ1");
+            ui.radio_value(&mut self.palette, PaletteChoice::Custom, "Custom");
+            if self.palette == PaletteChoice::Custom {
+                ui.add(egui::DragValue::new(&mut self.custom_palette).hexadecimal(2, false, true));
             }
+        });
 
-            let tex_ref = self.texture.as_ref().unwrap();
+        let palette = match self.palette {
+            PaletteChoice::Bg => ppu.bg_palette,
+            PaletteChoice::Obj0 => ppu.obj_palettes[0],
+            PaletteChoice::Obj1 => ppu.obj_palettes[1],
+            PaletteChoice::Custom => Palette::from(self.custom_palette),
+        };
+        let img = sheet_image(&ppu.tiles, &palette);
 
-            ui.image(tex_ref, tex_ref.size_vec2());
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.highlight, "Highlight used");
+
+            if ui.button("Export PNG").clicked() {
+                self.message = match save_image(Path::new(DEFAULT_DIR), "tiles", &img) {
+                    Ok(path) => format!("Saved {}", path.display()),
+                    Err(e) => e.to_string(),
+                };
+            }
+        });
+
+        let size = [img.width() as _, img.height() as _];
+        let image = ColorImage::from_rgba_unmultiplied(size, img.as_raw());
+
+        let texture = self.texture.get_or_insert_with(|| {
+            ui.ctx()
+                .load_texture("tiles_view", image.clone(), TextureOptions::NEAREST)
+        });
+        texture.set(image, TextureOptions::NEAREST);
+
+        let response = ui.image(texture.id(), texture.size_vec2() * ZOOM);
+
+        let tile_size = TILE_WIDTH as f32 * ZOOM;
+        let tile_rect = |index: usize| {
+            let offset = Vec2::new(
+                (index % TILE_PER_ROW) as f32 * tile_size,
+                (index / TILE_PER_ROW) as f32 * tile_size,
+            );
+            Rect::from_min_size(response.rect.min + offset, Vec2::splat(tile_size))
+        };
+
+        if self.highlight {
+            let referenced = referenced_tiles(ppu, oam);
+            for index in (0..referenced.len()).filter(|i| referenced[*i]) {
+                ui.painter()
+                    .rect_stroke(tile_rect(index), 0.0, Stroke::new(1.0, HIGHLIGHT_COLOR));
+            }
+        }
+
+        if let Some(pos) = response.hover_pos() {
+            let tile = (pos - response.rect.min) / tile_size;
+            let index = tile.y as usize * TILE_PER_ROW + tile.x as usize;
+
+            if index < ppu.tiles.list().len() {
+                ui.painter()
+                    .rect_stroke(tile_rect(index), 0.0, Stroke::new(1.0, Color32::WHITE));
+                response.on_hover_text_at_pointer(tile_info(&ppu.tiles, index));
+            }
+        }
+
+        if !self.message.is_empty() {
+            ui.label(&self.message);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::gbr::{
+        bus::BusAccess,
+        game_boy::GameBoy,
+        ppu::{palette::Palette, tile::TileData},
+    };
+
+    use super::{referenced_tiles, sheet_image, tile_info};
+
+    #[test]
+    fn tile_sheet() {
+        let mut tiles = TileData::new();
+        // Tile 17, first line all shade 3
+        tiles.write_line(17 * 16, 0xFF, 0xFF);
+
+        let img = sheet_image(&tiles, &Palette::from(0xE4));
+        assert_eq!(img.dimensions(), (128, 192));
+        assert_eq!(img.get_pixel(8, 8).0, [0, 0, 0, 255]);
+        assert_eq!(img.get_pixel(8, 9).0, [255, 255, 255, 255]);
+
+        assert_eq!(
+            tile_info(&tiles, 17),
+            "Tile 17, $8110\nFF FF 00 00 00 00 00 00\n00 00 00 00 00 00 00 00"
+        );
+    }
+
+    #[test]
+    fn used_tiles() {
+        let mut gb = GameBoy::with_program(&[]);
+        let bus = gb.bus_mut();
+
+        // BG map at $9800 with unsigned tile indices, all tile 0 but one
+        bus.write_byte(0xFF40, 0b1001_0011).unwrap();
+        bus.write_byte(0x9800, 5).unwrap();
+        // One object on screen with tile 7, another one hidden at y 0
+        bus.write_byte(0xFE00, 40).unwrap();
+        bus.write_byte(0xFE01, 40).unwrap();
+        bus.write_byte(0xFE02, 7).unwrap();
+        bus.write_byte(0xFE04, 0).unwrap();
+        bus.write_byte(0xFE06, 9).unwrap();
+
+        let state = gb.collect_state();
+        let used = referenced_tiles(&state.ppu, &state.oam);
+        let used: Vec<usize> = (0..used.len()).filter(|i| used[*i]).collect();
+        assert_eq!(used, [0, 5, 7]);
+
+        let bus = gb.bus_mut();
+
+        // Signed indices with 8x16 objects
+        bus.write_byte(0xFF40, 0b1000_0111).unwrap();
+        let state = gb.collect_state();
+        let used = referenced_tiles(&state.ppu, &state.oam);
+        let used: Vec<usize> = (0..used.len()).filter(|i| used[*i]).collect();
+        // Objects at y 0 are hidden, even the never written ones
+        assert_eq!(used, [6, 7, 256, 261]);
+    }
+}
@@ -1,8 +1,7 @@
-use std::collections::HashSet;
 use std::sync::mpsc::Sender;
 
 use egui::ClippedPrimitive;
-use egui::{Context, TexturesDelta, TopBottomPanel};
+use egui::{Context, Key, Rect, TexturesDelta, TopBottomPanel};
 use egui_wgpu::renderer::ScreenDescriptor;
 use egui_wgpu::Renderer;
 use flume::Receiver;
@@ -12,51 +11,109 @@ use winit::event_loop::EventLoopWindowTarget;
 use winit::{event::WindowEvent, window::Window};
 
 use crate::gbr::game_boy::{DebugEvent, EmuState, GbState, GbrEvent};
-
-use super::debugger::AsmState;
+use crate::gbr::ppu::ScreenBuffer;
+use crate::gbr::{ppu::palette::ColorScheme, speed::Speed};
+
+use super::breakpoint::Breakpoint;
+use super::breakpoints_view::BreakpointsView;
+use super::capture_view::CaptureView;
+use super::debugger::{AsmState, DebuggerStates, MemoryState};
+use super::history::HistoryState;
+use super::history_view::HistoryView;
+use super::lcd_effects::LcdEffects;
+use super::memory_view::MemoryView;
 use super::palette_view::PaletteView;
-use super::tilemap_view::TilemapView;
+use super::symbols::Symbols;
+use super::tilemap_view::{TilemapLayer, TilemapView};
 use super::tiles_view::TilesView;
-use super::{asm_view, cpu_view, mbc_view, oam_view};
+use super::watchpoints_view::WatchpointsView;
+use super::oam_view::OamView;
+use super::{asm_view, cpu_view, mbc_view};
 use super::{interrupts_view, joypad_view};
 
 struct UiState {
     show_bg_tilemap: bool,
     show_win_tilemap: bool,
+    show_watchpoints: bool,
+    show_breakpoints: bool,
+    show_history: bool,
+    show_memory: bool,
+    show_capture: bool,
     gb_state_next: Receiver<GbState>,
     gb_state: GbState,
     asm_state_next: Receiver<AsmState>,
     asm_state: AsmState,
+    breakpoints_next: Receiver<Vec<Breakpoint>>,
+    breakpoints: Vec<Breakpoint>,
+    history_next: Receiver<HistoryState>,
+    history: HistoryState,
+    memory_next: Receiver<MemoryState>,
+    symbols: Symbols,
     ev_sender: Sender<GbrEvent>,
     tiles_view: TilesView,
+    oam_view: OamView,
+    // BG and window
     tilemap_views: [TilemapView; 2],
     palette_view: PaletteView,
     emu_state: EmuState,
     emu_state_slot: Receiver<EmuState>,
-    breakpoints: HashSet<u16>,
+    breakpoints_view: BreakpointsView,
+    watchpoints_view: WatchpointsView,
+    history_view: HistoryView,
+    memory_view: MemoryView,
+    capture_view: CaptureView,
+    run_frames: u32,
+    speed: Speed,
+    color_schemes: Vec<(String, ColorScheme)>,
+    color_scheme: usize,
+    lcd_effects: LcdEffects,
+    // Where the game screen is drawn, in points
+    screen_rect: Rect,
 }
 
 impl UiState {
     fn new(
-        gb_state: Receiver<GbState>,
-        asm_state: Receiver<AsmState>,
+        states: DebuggerStates,
+        symbols: Symbols,
         ev_sender: Sender<GbrEvent>,
         emu_state_slot: Receiver<EmuState>,
     ) -> Self {
         Self {
             show_bg_tilemap: false,
             show_win_tilemap: false,
-            gb_state_next: gb_state,
+            show_watchpoints: false,
+            show_breakpoints: false,
+            show_history: false,
+            show_memory: false,
+            show_capture: false,
+            gb_state_next: states.gb_state,
             gb_state: GbState::default(),
-            asm_state_next: asm_state,
+            asm_state_next: states.asm_state,
             asm_state: AsmState::default(),
+            breakpoints_next: states.breakpoints,
+            breakpoints: vec![],
+            history_next: states.history,
+            history: HistoryState::default(),
+            memory_next: states.memory,
+            symbols,
             ev_sender,
             tiles_view: TilesView::default(),
+            oam_view: OamView::default(),
             tilemap_views: Default::default(),
             palette_view: PaletteView::new(),
             emu_state: EmuState::Idle,
             emu_state_slot,
-            breakpoints: HashSet::new(),
+            breakpoints_view: BreakpointsView::default(),
+            watchpoints_view: WatchpointsView::default(),
+            history_view: HistoryView::default(),
+            memory_view: MemoryView::default(),
+            capture_view: CaptureView::default(),
+            run_frames: 1,
+            speed: Speed::default(),
+            color_schemes: vec![],
+            color_scheme: 0,
+            lcd_effects: LcdEffects::default(),
+            screen_rect: Rect::NOTHING,
         }
     }
 
@@ -72,10 +129,62 @@ impl UiState {
         if let Ok(state) = self.asm_state_next.try_recv() {
             self.asm_state = state.clone();
         }
+
+        if let Ok(breakpoints) = self.breakpoints_next.try_recv() {
+            self.breakpoints = breakpoints;
+        }
+
+        if let Ok(history) = self.history_next.try_recv() {
+            self.history = history;
+        }
+
+        if let Ok(memory) = self.memory_next.try_recv() {
+            self.memory_view.update(memory);
+        }
+    }
+
+    // F5 continue/pause, F10 step over, F11 step, Shift+F11 step out, F8 run frames,
+    // hold Tab to fast forward
+    fn handle_shortcuts(&self, ctx: &Context) {
+        // Tab moves the focus between text fields
+        let typing = ctx.wants_keyboard_input();
+
+        let ev = ctx.input(|i| {
+            let idle = matches!(self.emu_state, EmuState::Idle);
+
+            if i.key_pressed(Key::Tab) && !typing {
+                Some(GbrEvent::FastForward(true))
+            } else if i.key_released(Key::Tab) {
+                Some(GbrEvent::FastForward(false))
+            } else if i.key_pressed(Key::F5) {
+                Some(if idle {
+                    GbrEvent::Start
+                } else {
+                    GbrEvent::Pause
+                })
+            } else if !idle {
+                None
+            } else if i.key_pressed(Key::F10) {
+                Some(GbrEvent::StepOver)
+            } else if i.key_pressed(Key::F11) && i.modifiers.shift {
+                Some(GbrEvent::StepOut)
+            } else if i.key_pressed(Key::F11) {
+                Some(GbrEvent::Step)
+            } else if i.key_pressed(Key::F8) {
+                Some(GbrEvent::RunFrames(self.run_frames))
+            } else {
+                None
+            }
+        });
+
+        if let Some(ev) = ev {
+            self.ev_sender.send(ev).unwrap();
+        }
     }
 
     pub fn update(&mut self, ctx: &Context) {
         self.update_debug_data();
+        self.handle_shortcuts(ctx);
 
         TopBottomPanel::top("menubar_container").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
@@ -89,6 +198,37 @@ impl UiState {
                         self.show_win_tilemap = true;
                         ui.close_menu();
                     }
+
+                    if ui.button("Breakpoints...").clicked() {
+                        self.show_breakpoints = true;
+                        ui.close_menu();
+                    }
+
+                    if ui.button("Watchpoints...").clicked() {
+                        self.show_watchpoints = true;
+                        ui.close_menu();
+                    }
+
+                    if ui.button("History...").clicked() {
+                        self.show_history = true;
+                        ui.close_menu();
+                    }
+
+                    if ui.button("Memory...").clicked() {
+                        self.show_memory = true;
+                        ui.close_menu();
+                    }
+
+                    if ui.button("Capture...").clicked() {
+                        self.show_capture = true;
+                        ui.close_menu();
+                    }
+                });
+
+                ui.menu_button("Display", |ui| {
+                    ui.checkbox(&mut self.lcd_effects.ghosting, "LCD ghosting");
+                    ui.checkbox(&mut self.lcd_effects.grid, "Pixel grid");
+                    ui.checkbox(&mut self.lcd_effects.color_correction, "CGB colour correction");
                 });
             });
         });
@@ -97,13 +237,7 @@ impl UiState {
             egui::Window::new("BG tilemap")
                 .open(&mut self.show_bg_tilemap)
                 .show(ctx, |ui| {
-                    let view_idx = self.gb_state.ppu.lcd_control.bg_tile_map_area_sel as usize;
-                    self.tilemap_views[view_idx].show(
-                        &self.gb_state.ppu.tilemaps[0],
-                        &self.gb_state.ppu.tiles,
-                        self.gb_state.ppu.lcd_control.bg_and_window_tile_area_sel,
-                        ui,
-                    );
+                    self.tilemap_views[0].show(TilemapLayer::Bg, &self.gb_state.ppu, ui);
                 });
         }
 
@@ -111,16 +245,65 @@ impl UiState {
             egui::Window::new("Win tilemap")
                 .open(&mut self.show_win_tilemap)
                 .show(ctx, |ui| {
-                    let view_idx = self.gb_state.ppu.lcd_control.window_tile_area_sel as usize;
-                    self.tilemap_views[view_idx].show(
-                        &self.gb_state.ppu.tilemaps[1],
-                        &self.gb_state.ppu.tiles,
-                        self.gb_state.ppu.lcd_control.bg_and_window_tile_area_sel,
+                    self.tilemap_views[1].show(TilemapLayer::Window, &self.gb_state.ppu, ui);
+                });
+        }
+
+        if self.show_breakpoints {
+            egui::Window::new("Breakpoints")
+                .open(&mut self.show_breakpoints)
+                .show(ctx, |ui| {
+                    self.breakpoints_view.show(
+                        &self.ev_sender,
+                        &self.breakpoints,
+                        &self.gb_state.mbc,
+                        &self.symbols,
                         ui,
                     );
                 });
         }
 
+        if self.show_watchpoints {
+            egui::Window::new("Watchpoints")
+                .open(&mut self.show_watchpoints)
+                .show(ctx, |ui| {
+                    self.watchpoints_view
+                        .show(&self.ev_sender, &self.gb_state.watchpoint_hit, ui);
+                });
+        }
+
+        if self.show_history {
+            let idle = matches!(self.emu_state, EmuState::Idle);
+
+            egui::Window::new("History")
+                .open(&mut self.show_history)
+                .show(ctx, |ui| {
+                    self.history_view
+                        .show(&self.ev_sender, &self.history, idle, ui);
+                });
+        }
+
+        if self.show_memory {
+            egui::Window::new("Memory")
+                .open(&mut self.show_memory)
+                .default_height(400.0)
+                .show(ctx, |ui| {
+                    self.memory_view
+                        .show(&self.ev_sender, &self.gb_state.mbc, ui);
+                });
+        } else {
+            self.memory_view.close(&self.ev_sender);
+        }
+
+        if self.show_capture {
+            egui::Window::new("Capture")
+                .open(&mut self.show_capture)
+                .show(ctx, |ui| {
+                    self.capture_view
+                        .show(self.lcd_effects, &self.ev_sender, ui);
+                });
+        }
+
         egui::TopBottomPanel::top("toolbar")
             .max_height(60.0)
             .show(ctx, |ui| {
@@ -143,9 +326,57 @@ impl UiState {
                             if ui.button("Step").clicked() {
                                 self.ev_sender.send(GbrEvent::Step).unwrap();
                             }
+
+                            if ui.button("Step over").clicked() {
+                                self.ev_sender.send(GbrEvent::StepOver).unwrap();
+                            }
+
+                            if ui.button("Step out").clicked() {
+                                self.ev_sender.send(GbrEvent::StepOut).unwrap();
+                            }
+
+                            if ui.button("Run frames").clicked() {
+                                self.ev_sender
+                                    .send(GbrEvent::RunFrames(self.run_frames))
+                                    .unwrap();
+                            }
+                            ui.add(
+                                egui::DragValue::new(&mut self.run_frames).clamp_range(1..=3600),
+                            );
                         }
                     }
 
+                    ui.label("Speed:");
+                    let speed = self.speed;
+                    egui::ComboBox::from_id_source("speed")
+                        .selected_text(speed.to_string())
+                        .show_ui(ui, |ui| {
+                            for option in Speed::ALL {
+                                ui.selectable_value(&mut self.speed, option, option.to_string());
+                            }
+                        });
+                    if self.speed != speed {
+                        self.ev_sender.send(GbrEvent::SetSpeed(self.speed)).unwrap();
+                    }
+
+                    ui.label("Colors:");
+                    let color_scheme = self.color_scheme;
+                    egui::ComboBox::from_id_source("color_scheme")
+                        .selected_text(
+                            self.color_schemes
+                                .get(color_scheme)
+                                .map_or("", |(name, _)| name.as_str()),
+                        )
+                        .show_ui(ui, |ui| {
+                            for (index, (name, _)) in self.color_schemes.iter().enumerate() {
+                                ui.selectable_value(&mut self.color_scheme, index, name);
+                            }
+                        });
+                    if self.color_scheme != color_scheme {
+                        let (_, colors) = self.color_schemes[self.color_scheme];
+                        self.ev_sender.send(GbrEvent::SetColorScheme(colors)).unwrap();
+                    }
+
                     if ui.button("Dump VRAM").clicked() {
                         self.ev_sender
                             .send(GbrEvent::Debug(DebugEvent::DumpVram))
@@ -170,7 +401,9 @@ impl UiState {
                         &self.ev_sender,
                         &self.asm_state,
                         &self.gb_state.cpu,
-                        &mut self.breakpoints,
+                        &self.gb_state.mbc,
+                        &self.breakpoints,
+                        &self.symbols,
                         ui,
                     );
                 });
@@ -181,11 +414,8 @@ impl UiState {
             .show(ctx, |ui| {
                 ui.vertical(|ui| {
                     ui.heading("Tiles");
-                    self.tiles_view.show(
-                        &self.gb_state.ppu.tiles,
-                        &self.gb_state.ppu.bg_palette,
-                        ui,
-                    );
+                    self.tiles_view
+                        .show(&self.gb_state.ppu, &self.gb_state.oam, ui);
                     ui.separator();
                     ui.heading("LCD Ctrl");
                     ui.label(format!("{}", self.gb_state.ppu.lcd_control));
@@ -220,9 +450,17 @@ impl UiState {
 
                     ui.separator();
 
-                    oam_view::show(&self.gb_state.oam, ui);
+                    self.oam_view
+                        .show(&self.gb_state.ppu, &self.gb_state.oam, ui);
                 });
             });
+
+        self.oam_view.highlight_selected(
+            &self.gb_state.ppu,
+            &self.gb_state.oam,
+            self.screen_rect,
+            ctx,
+        );
     }
 }
 
@@ -238,8 +476,8 @@ pub struct Ui {
 
 impl Ui {
     pub fn new<T>(
-        gb_state: Receiver<GbState>,
-        asm_state: Receiver<AsmState>,
+        states: DebuggerStates,
+        symbols: Symbols,
         ev_sender: Sender<GbrEvent>,
         emu_state_slot: Receiver<EmuState>,
         event_loop: &EventLoopWindowTarget<T>,
@@ -268,7 +506,7 @@ impl Ui {
             renderer,
             paint_jobs: vec![],
             textures: TexturesDelta::default(),
-            state: UiState::new(gb_state, asm_state, ev_sender, emu_state_slot),
+            state: UiState::new(states, symbols, ev_sender, emu_state_slot),
         }
     }
 
@@ -282,6 +520,30 @@ impl Ui {
         }
     }
 
+    /// Colour schemes to choose from, the first one being in use.
+    pub fn set_color_schemes(&mut self, color_schemes: Vec<(String, ColorScheme)>) {
+        self.state.color_schemes = color_schemes;
+        self.state.color_scheme = 0;
+    }
+
+    pub fn lcd_effects(&self) -> LcdEffects {
+        self.state.lcd_effects
+    }
+
+    /// Keep the frame shown on screen for screenshots.
+    pub fn set_screen(&mut self, screen: ScreenBuffer) {
+        self.state.capture_view.set_screen(screen);
+    }
+
+    /// Game screen placement in physical pixels, as given by the scaling renderer.
+    pub fn set_screen_rect(&mut self, (x, y, width, height): (u32, u32, u32, u32)) {
+        let ppp = self.screen_descriptor.pixels_per_point;
+        self.state.screen_rect = Rect::from_min_size(
+            [x as f32 / ppp, y as f32 / ppp].into(),
+            [width as f32 / ppp, height as f32 / ppp].into(),
+        );
+    }
+
     pub fn handle_event(&mut self, event: &WindowEvent) {
         let _ = self.egui_state.on_event(&self.ctx, event);
     }
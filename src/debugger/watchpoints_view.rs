@@ -0,0 +1,123 @@
+use std::sync::mpsc::Sender;
+
+use crate::gbr::{
+    game_boy::{DebugEvent, GbrEvent},
+    watchpoint::{WatchKind, Watchpoint, WatchpointHit},
+};
+
+pub fn parse_hex(text: &str) -> Option<u16> {
+    let text = text.trim();
+    let text = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+
+    u16::from_str_radix(text, 16).ok()
+}
+
+#[derive(Default)]
+pub struct WatchpointsView {
+    start: String,
+    end: String,
+    value: String,
+    kind: WatchKind,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl WatchpointsView {
+    fn parse(&self) -> Option<Watchpoint> {
+        let start = parse_hex(&self.start)?;
+
+        let end = if self.end.trim().is_empty() {
+            start
+        } else {
+            parse_hex(&self.end)?
+        };
+
+        let value = if self.value.trim().is_empty() {
+            None
+        } else {
+            Some(u8::try_from(parse_hex(&self.value)?).ok()?)
+        };
+
+        if end < start {
+            return None;
+        }
+
+        Some(Watchpoint {
+            start,
+            end,
+            kind: self.kind,
+            value,
+        })
+    }
+
+    pub fn show(
+        &mut self,
+        ev_sender: &Sender<GbrEvent>,
+        last_hit: &Option<WatchpointHit>,
+        ui: &mut egui::Ui,
+    ) {
+        egui::Grid::new("watchpoint_form").show(ui, |ui| {
+            ui.label("Start:");
+            ui.text_edit_singleline(&mut self.start);
+            ui.end_row();
+
+            ui.label("End:");
+            ui.text_edit_singleline(&mut self.end);
+            ui.end_row();
+
+            ui.label("Value:");
+            ui.text_edit_singleline(&mut self.value);
+            ui.end_row();
+        });
+
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.kind, WatchKind::Read, "Read");
+            ui.radio_value(&mut self.kind, WatchKind::Write, "Write");
+            ui.radio_value(&mut self.kind, WatchKind::Access, "Access");
+        });
+
+        let watchpoint = self.parse();
+
+        if ui
+            .add_enabled(watchpoint.is_some(), egui::Button::new("Add"))
+            .clicked()
+        {
+            let watchpoint = watchpoint.unwrap();
+
+            if !self.watchpoints.contains(&watchpoint) {
+                self.watchpoints.push(watchpoint);
+                ev_sender
+                    .send(GbrEvent::Debug(DebugEvent::SetWatchpoint(watchpoint)))
+                    .unwrap();
+            }
+        }
+
+        ui.separator();
+
+        let mut removed = None;
+        for (index, watchpoint) in self.watchpoints.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.small_button("x").clicked() {
+                    removed = Some(index);
+                }
+                ui.label(format!("{}", watchpoint));
+            });
+        }
+
+        if let Some(index) = removed {
+            let watchpoint = self.watchpoints.remove(index);
+            ev_sender
+                .send(GbrEvent::Debug(DebugEvent::ClearWatchpoint(watchpoint)))
+                .unwrap();
+        }
+
+        ui.separator();
+
+        match last_hit {
+            Some(hit) => ui.label(format!("Last hit: {}", hit)),
+            None => ui.label("Last hit: none"),
+        };
+    }
+}
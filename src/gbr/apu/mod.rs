@@ -35,6 +35,14 @@ const CH3_WAVE_PATTERN_RAM_END: u16 = 0xFF3F;
 const CH3_WAVE_PATTERN_RAM_SIZE: usize =
     (CH3_WAVE_PATTERN_RAM_END - CH3_WAVE_PATTERN_RAM_BEGIN) as usize + 1;
 
+const CPU_FREQ: u64 = 4_194_304; // Hz
+/// Audio samples output per second.
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// Left and right output levels.
+pub type StereoSample = [i16; 2];
+
+#[derive(Clone)]
 pub struct APU {
     sound_enable: u8,
     sound_output_terminal_selection: u8,
@@ -43,6 +51,11 @@ pub struct APU {
     ch2: Channel2,
     ch3: Channel3,
     ch4: Channel4,
+
+    // CPU cycles times the sample rate since the last sample
+    sample_clock: u64,
+    // Samples output since the last take
+    samples: Vec<StereoSample>,
 }
 
 impl APU {
@@ -55,14 +68,31 @@ impl APU {
             ch2: Default::default(),
             ch3: Default::default(),
             ch4: Default::default(),
+            sample_clock: 0,
+            samples: vec![],
         }
     }
 
-    pub fn step(&mut self, _cpu_cycles: u8) -> Result<(), GbError> {
-        // TODO
+    pub fn step(&mut self, cpu_cycles: u8) -> Result<(), GbError> {
+        self.sample_clock += cpu_cycles as u64 * SAMPLE_RATE as u64;
+        while self.sample_clock >= CPU_FREQ {
+            self.sample_clock -= CPU_FREQ;
+            self.samples.push(self.output());
+        }
+
         Ok(())
     }
 
+    // The channels are not synthesized yet, the output stays silent
+    fn output(&self) -> StereoSample {
+        [0, 0]
+    }
+
+    /// Take the samples output since the last call.
+    pub fn take_samples(&mut self) -> Vec<StereoSample> {
+        std::mem::take(&mut self.samples)
+    }
+
     pub fn read_reg(&self, addr: u16) -> Result<u8, GbError> {
         match addr {
             CH1_WAVE_AND_TIMER_REG_ADDR => Ok(self.ch1.read_duty_cycle()),
@@ -1,6 +1,6 @@
 use super::CH3_WAVE_PATTERN_RAM_SIZE;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 enum SweepDirection {
     #[default]
     Increase,
@@ -39,7 +39,7 @@ impl From<DutyCycle> for u8 {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 struct ChannelControl {
     trigger: bool,
     sound_length_enable: bool,
@@ -52,7 +52,7 @@ impl ChannelControl {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Sweep {
     pace: u8,
     direction: SweepDirection,
@@ -71,7 +71,7 @@ impl Sweep {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Envelope {
     volume: u8,
     direction: SweepDirection,
@@ -90,7 +90,7 @@ impl Envelope {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Pulse {
     duty_cycle: DutyCycle,
     length_timer: u8,
@@ -122,7 +122,7 @@ impl Pulse {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub enum OutputLevel {
     #[default]
     Mute,
@@ -154,14 +154,14 @@ impl From<OutputLevel> for u8 {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub enum LfsrWidth {
     #[default]
     FifteenBits,
     SevenBits,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Channel1 {
     sweep: Sweep,
     pulse: Pulse,
@@ -195,7 +195,7 @@ impl Channel1 {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Channel2 {
     pulse: Pulse,
     ctrl: ChannelControl,
@@ -224,7 +224,7 @@ impl Channel2 {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Channel3 {
     enable: bool,
     length_timer: u8,
@@ -259,7 +259,7 @@ impl Channel3 {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Channel4 {
     length_timer: u8,
     envelope: Envelope,
@@ -3,8 +3,19 @@ use std::{fs, path::PathBuf};
 use byteorder::{ByteOrder, LittleEndian};
 
 use super::{
-    apu::APU, dma::DMA, interrupts::InterruptHandler, joypad::Joypad, mbc::MBC, memory_map::*,
-    oam::ObjAttributeMemory, ppu::PPU, serial::Serial, timer::Timer, GbError,
+    apu::APU,
+    dma::DMA,
+    interrupts::{InterruptHandler, InterruptType},
+    joypad::Joypad,
+    link::SerialPeer,
+    mbc::MBC,
+    memory_map::*,
+    oam::ObjAttributeMemory,
+    ppu::PPU,
+    serial::Serial,
+    timer::Timer,
+    watchpoint::Watchpoints,
+    GbError,
 };
 
 #[cfg(test)]
@@ -18,6 +29,32 @@ pub trait BusAccess {
 
     fn ir_handler(&self) -> &InterruptHandler;
     fn ir_handler_mut(&mut self) -> &mut InterruptHandler;
+
+    fn joypad(&self) -> &Joypad;
+}
+
+/// CPU write with the value it replaced, if the address was readable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemWrite {
+    pub addr: u16,
+    pub old: Option<u8>,
+    pub value: u8,
+}
+
+/// Bus state, without the boot ROM, watchpoints and link cable.
+pub struct BusSnapshot {
+    boot_rom_lock: bool,
+    hram: Box<[u8]>,
+    wram: Box<[u8]>,
+    oam: ObjAttributeMemory,
+    ppu: PPU,
+    apu: APU,
+    ir_handler: InterruptHandler,
+    timer: Timer,
+    mbc: MBC,
+    dma: DMA,
+    serial: Serial,
+    joypad: Joypad,
 }
 
 pub struct Bus {
@@ -34,6 +71,8 @@ pub struct Bus {
     dma: DMA,
     serial: Serial,
     joypad: Joypad,
+    watchpoints: Watchpoints,
+    write_log: Option<Vec<MemWrite>>,
 }
 
 impl Bus {
@@ -50,9 +89,24 @@ impl Bus {
             None => MBC::default(),
         };
 
+        Bus::with_mbc(boot_rom.into_boxed_slice(), mbc)
+    }
+
+    /// Create a bus for the given cartridge ROM without a boot ROM.
+    ///
+    /// Registers are initialized to the values left by the DMG boot ROM.
+    #[cfg(test)]
+    pub fn from_rom(rom: &[u8]) -> Result<Self, GbError> {
+        let mut bus = Bus::with_mbc(Box::default(), MBC::from_rom(rom)?);
+        bus.init_post_boot_registers()?;
+
+        Ok(bus)
+    }
+
+    fn with_mbc(boot_rom: Box<[u8]>, mbc: MBC) -> Self {
         Bus {
-            boot_rom_lock: true,
-            boot_rom: boot_rom.into_boxed_slice(),
+            boot_rom_lock: !boot_rom.is_empty(),
+            boot_rom,
             hram: vec![0; HRAM_SIZE].into_boxed_slice(),
             wram: vec![0; WRAM_SIZE].into_boxed_slice(),
             oam: ObjAttributeMemory::new(),
@@ -64,22 +118,44 @@ impl Bus {
             dma: DMA::new(),
             serial: Serial::default(),
             joypad: Joypad::default(),
+            watchpoints: Watchpoints::default(),
+            write_log: None,
         }
     }
 
     pub fn step(&mut self, cycles: u8) -> Result<bool, GbError> {
-        self.dma
-            .step(&self.wram, &self.ppu, &self.mbc, &mut self.oam, cycles)?;
+        self.dma.step(
+            &self.wram,
+            &self.ppu,
+            &self.mbc,
+            &mut self.oam,
+            &self.watchpoints,
+            cycles,
+        )?;
         self.timer.step(cycles, &mut self.ir_handler);
         self.serial.step(cycles, &mut self.ir_handler);
+        if self.joypad.take_interrupt() {
+            self.ir_handler.set(InterruptType::Joypad);
+        }
         self.apu.step(cycles)?;
         self.ppu
             .step(&mut self.ir_handler, &self.oam, cycles as u16)
     }
 
+    fn init_post_boot_registers(&mut self) -> Result<(), GbError> {
+        self.write_byte(LCD_CONTROL_REGISTER, 0x91)?;
+        self.write_byte(BG_PALETTE_REGISTER, 0xFC)?;
+
+        Ok(())
+    }
+
+    pub fn has_boot_rom(&self) -> bool {
+        !self.boot_rom.is_empty()
+    }
+
     pub fn reset(&mut self) {
         self.ppu.reset();
-        self.boot_rom_lock = true;
+        self.boot_rom_lock = self.has_boot_rom();
         self.hram.fill(0);
         self.wram.fill(0);
         self.oam = ObjAttributeMemory::new();
@@ -87,9 +163,13 @@ impl Bus {
         self.ir_handler = InterruptHandler::default();
         self.timer = Timer::default();
         self.dma = DMA::new();
-        self.serial = Serial::default();
+        self.serial.reset();
         self.joypad = Joypad::default();
 
+        if !self.has_boot_rom() {
+            self.init_post_boot_registers().ok();
+        }
+
         // TODO reset MBC
     }
 
@@ -97,21 +177,127 @@ impl Bus {
         &self.ppu
     }
 
+    pub fn ppu_mut(&mut self) -> &mut PPU {
+        &mut self.ppu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut APU {
+        &mut self.apu
+    }
+
     pub fn mbc(&self) -> &MBC {
         &self.mbc
     }
 
-    pub fn oam(&self) -> &ObjAttributeMemory {
-        &self.oam
+    /// Read `addr` for the debugger, from the given bank in the cartridge
+    /// areas, without side effects or logs.
+    ///
+    /// OAM and VRAM are read whatever the PPU mode, unreadable addresses read as 0xFF.
+    pub fn peek(&self, addr: BankedAddress) -> u8 {
+        let value = match map_address(addr.addr) {
+            // Bank 0 may be overlaid by the boot ROM
+            MappedAddress::CartRom if addr.addr <= CART_ROM_BANK0_END => self.read_byte(addr.addr),
+            MappedAddress::CartRom | MappedAddress::CartRam => self.mbc.read_banked(addr),
+            MappedAddress::VideoRam => Ok(self.ppu.peek(addr.addr)),
+            MappedAddress::ObjectAttributeTable => Ok(self.oam.peek(addr.addr)),
+            MappedAddress::NotUsable
+            | MappedAddress::BootRomLockRegister
+            | MappedAddress::InvalidAddress => Ok(0xFF),
+            _ => self.read_byte(addr.addr),
+        };
+
+        value.unwrap_or(0xFF)
     }
 
-    pub fn joypad(&self) -> &Joypad {
-        &self.joypad
+    /// Write `addr` without going through the MBC registers, into the given
+    /// bank of cartridge RAM. Cartridge ROM is read only.
+    pub fn write_banked(&mut self, addr: BankedAddress, value: u8) -> Result<(), GbError> {
+        match map_address(addr.addr) {
+            MappedAddress::CartRom | MappedAddress::CartRam => {
+                self.mbc.write_ram_banked(addr, value)
+            }
+            _ => self.write_byte(addr.addr, value),
+        }
+    }
+
+    pub fn oam(&self) -> &ObjAttributeMemory {
+        &self.oam
     }
 
     pub fn joypad_mut(&mut self) -> &mut Joypad {
         &mut self.joypad
     }
+
+    pub fn watchpoints(&self) -> &Watchpoints {
+        &self.watchpoints
+    }
+
+    pub fn serial_mut(&mut self) -> &mut Serial {
+        &mut self.serial
+    }
+
+    pub fn watchpoints_mut(&mut self) -> &mut Watchpoints {
+        &mut self.watchpoints
+    }
+
+    pub fn set_serial_peer(&mut self, peer: Option<Box<dyn SerialPeer>>) {
+        self.serial.set_peer(peer);
+    }
+
+    pub fn snapshot(&self) -> BusSnapshot {
+        BusSnapshot {
+            boot_rom_lock: self.boot_rom_lock,
+            hram: self.hram.clone(),
+            wram: self.wram.clone(),
+            oam: self.oam.clone(),
+            ppu: self.ppu.clone(),
+            apu: self.apu.clone(),
+            ir_handler: self.ir_handler.clone(),
+            timer: self.timer.clone(),
+            mbc: self.mbc.clone(),
+            dma: self.dma.clone(),
+            serial: self.serial.snapshot(),
+            joypad: self.joypad.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &BusSnapshot) {
+        self.boot_rom_lock = snapshot.boot_rom_lock;
+        self.hram.clone_from(&snapshot.hram);
+        self.wram.clone_from(&snapshot.wram);
+        self.oam = snapshot.oam.clone();
+        self.ppu.restore(&snapshot.ppu);
+        self.apu = snapshot.apu.clone();
+        self.ir_handler = snapshot.ir_handler.clone();
+        self.timer = snapshot.timer.clone();
+        self.mbc = snapshot.mbc.clone();
+        self.dma = snapshot.dma.clone();
+        self.serial.restore(&snapshot.serial);
+        self.joypad = snapshot.joypad.clone();
+    }
+
+    /// Start or stop logging CPU writes, see `WatchedBus`.
+    pub fn log_writes(&mut self, enabled: bool) {
+        self.write_log = enabled.then(Vec::new);
+    }
+
+    pub fn logs_writes(&self) -> bool {
+        self.write_log.is_some()
+    }
+
+    pub fn log_write(&mut self, write: MemWrite) {
+        if let Some(log) = self.write_log.as_mut() {
+            log.push(write);
+        }
+    }
+
+    /// Take the writes logged since the last call.
+    pub fn take_writes(&mut self) -> Vec<MemWrite> {
+        self.write_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
 }
 
 impl BusAccess for Bus {
@@ -251,4 +437,8 @@ impl BusAccess for Bus {
     fn ir_handler_mut(&mut self) -> &mut InterruptHandler {
         &mut self.ir_handler
     }
+
+    fn joypad(&self) -> &Joypad {
+        &self.joypad
+    }
 }
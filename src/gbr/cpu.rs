@@ -20,17 +20,25 @@ const TIMER_IR_ADDRESS: u16 = 0x0050;
 const SERIAL_IR_ADDRESS: u16 = 0x0058;
 const JOYPAD_IR_ADDRESS: u16 = 0x0060;
 
-#[derive(Default)]
-struct Delay<Type: Copy + Default, const CYCLES: usize = 1> {
+const INTERRUPT_DISPATCH_CYCLES: u8 = 5;
+
+const DIVIDER_REG_ADDR: u16 = 0xFF04;
+
+#[derive(Default, Clone)]
+struct Delay<Type: Copy + Default + PartialEq, const CYCLES: usize = 1> {
     v: Type,
     next: Type,
     delay: usize,
 }
 
-impl<Type: Copy + Default, const CYCLES: usize> Delay<Type, CYCLES> {
+impl<Type: Copy + Default + PartialEq, const CYCLES: usize> Delay<Type, CYCLES> {
     pub fn set(&mut self, v: Type) {
-        self.next = v;
-        self.delay = 0;
+        // Setting the same pending value again must not restart the delay,
+        // e.g. EI; EI enables IME after the second EI like a single one would.
+        if self.next != v {
+            self.next = v;
+            self.delay = 0;
+        }
     }
 
     pub fn get(&self) -> Type {
@@ -67,6 +75,7 @@ pub struct CpuState {
 
     pub ime: bool,
     pub halted: bool,
+    pub stopped: bool,
 }
 
 impl fmt::Display for CpuState {
@@ -77,7 +86,7 @@ impl fmt::Display for CpuState {
             AF {:#06X}, BC {:#06X}, DE {:#06X}, HL {:#06X}, PC {:#06X}, SP {:#06X}\n
             Flags:\n
             Z {}, C {}, BCD-N {}, BCD-H {}\n
-            Halted: {}",
+            Halted: {}, Stopped: {}",
             self.af,
             self.bc,
             self.de,
@@ -88,12 +97,13 @@ impl fmt::Display for CpuState {
             self.carry,
             self.bcd_n,
             self.bcd_h,
-            self.halted
+            self.halted,
+            self.stopped
         )
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct CPU {
     // 8bit general purpose registers
     reg_a: u8,
@@ -114,6 +124,9 @@ pub struct CPU {
     low_power_mode: bool,
     ime: Delay<bool>,
     halted: bool,
+    halt_bug: bool,
+    // Whether the last step fetched and executed an instruction
+    executed: bool,
 }
 
 impl CPU {
@@ -121,6 +134,26 @@ impl CPU {
         Self::default()
     }
 
+    /// CPU with the register values left by the DMG boot ROM.
+    pub fn new_post_boot() -> Self {
+        let mut cpu = Self::default();
+        cpu.write_af(0x01B0);
+        cpu.write_bc(0x0013);
+        cpu.write_de(0x00D8);
+        cpu.write_hl(0x014D);
+        cpu.reg_sp = 0xFFFE;
+        cpu.reg_pc = 0x0100;
+        cpu.reg_pc_prev = 0x0100;
+
+        cpu
+    }
+
+    /// Whether the last step executed an instruction, rather than idling in
+    /// HALT/STOP or dispatching an interrupt.
+    pub fn executed_instruction(&self) -> bool {
+        self.executed
+    }
+
     pub fn read_af(&self) -> u16 {
         (self.reg_a as u16) << 8 | (self.reg_f & 0xF0) as u16
     }
@@ -205,6 +238,10 @@ impl CPU {
         self.reg_pc
     }
 
+    pub fn write_pc(&mut self, value: u16) {
+        self.reg_pc = value;
+    }
+
     pub fn read_sp(&self) -> u16 {
         self.reg_sp
     }
@@ -435,11 +472,14 @@ impl CPU {
         }
     }
 
-    fn goto_interrupt(&mut self, bus: &mut dyn BusAccess, ir_addr: u16) -> Result<(), GbError> {
-        self.ime.set_now(false);
-        self.push_stack(bus, self.reg_pc)?;
-        self.reg_pc = ir_addr;
-        Ok(())
+    fn interrupt_address(ir: InterruptType) -> u16 {
+        match ir {
+            InterruptType::VBlank => VBLANK_IR_ADDRESS,
+            InterruptType::LcdStat => LCD_STAT_IR_ADDRESS,
+            InterruptType::Timer => TIMER_IR_ADDRESS,
+            InterruptType::Serial => SERIAL_IR_ADDRESS,
+            InterruptType::Joypad => JOYPAD_IR_ADDRESS,
+        }
     }
 
     fn is_halted(&mut self, bus: &mut dyn BusAccess) -> Result<bool, GbError> {
@@ -450,46 +490,70 @@ impl CPU {
         Ok(self.halted)
     }
 
+    /// Interrupt dispatch, takes INTERRUPT_DISPATCH_CYCLES M-cycles:
+    /// two wait cycles, push of PC high byte, push of PC low byte and jump.
+    ///
+    /// The interrupt to service is resolved only after the high byte of PC
+    /// has been pushed. If that push overwrites IE (SP = 0x0000) and no
+    /// enabled interrupt is left pending, the dispatch is cancelled and
+    /// execution continues at 0x0000.
     fn check_interrupts(&mut self, bus: &mut dyn BusAccess) -> Result<bool, GbError> {
-        if self.ime.get() {
-            let ir_handler = bus.ir_handler_mut();
+        if !self.ime.get() || !bus.ir_handler().any_pending_interrupt() {
+            return Ok(false);
+        }
 
-            if ir_handler.test(InterruptType::VBlank) {
-                ir_handler.clear(InterruptType::VBlank);
-                self.goto_interrupt(bus, VBLANK_IR_ADDRESS)?;
-                return Ok(true);
-            }
+        self.ime.set_now(false);
 
-            if ir_handler.test(InterruptType::LcdStat) {
-                ir_handler.clear(InterruptType::LcdStat);
-                self.goto_interrupt(bus, LCD_STAT_IR_ADDRESS)?;
-                return Ok(true);
-            }
+        // An interrupt serviced right after a bugged HALT returns to the HALT itself
+        if self.halt_bug {
+            self.halt_bug = false;
+            self.reg_pc = self.reg_pc.wrapping_sub(1);
+        }
 
-            if ir_handler.test(InterruptType::Timer) {
-                ir_handler.clear(InterruptType::Timer);
-                self.goto_interrupt(bus, TIMER_IR_ADDRESS)?;
-                return Ok(true);
-            }
+        self.reg_sp = self.reg_sp.wrapping_sub(1);
+        bus.write_byte(self.reg_sp, (self.reg_pc >> 8) as u8)?;
 
-            if ir_handler.test(InterruptType::Serial) {
-                ir_handler.clear(InterruptType::Serial);
-                self.goto_interrupt(bus, SERIAL_IR_ADDRESS)?;
-                return Ok(true);
-            }
+        let ir = bus.ir_handler().highest_priority_pending();
+
+        self.reg_sp = self.reg_sp.wrapping_sub(1);
+        bus.write_byte(self.reg_sp, self.reg_pc as u8)?;
 
-            if ir_handler.test(InterruptType::Joypad) {
-                ir_handler.clear(InterruptType::Joypad);
-                self.goto_interrupt(bus, JOYPAD_IR_ADDRESS)?;
-                return Ok(true);
+        self.reg_pc = match ir {
+            Some(ir) => {
+                bus.ir_handler_mut().clear(ir);
+                CPU::interrupt_address(ir)
             }
+            None => 0x0000,
+        };
+
+        Ok(true)
+    }
+
+    fn halt(&mut self, bus: &mut dyn BusAccess) {
+        // HALT bug: with IME disabled and an interrupt already pending the CPU
+        // does not halt, but fails to increment PC after the next opcode fetch.
+        if !self.ime.get() && bus.ir_handler().any_pending_interrupt() {
+            self.halt_bug = true;
+        } else {
+            self.halted = true;
         }
+    }
 
-        Ok(false)
+    fn stop(&mut self, bus: &mut dyn BusAccess) -> Result<(), GbError> {
+        // Entering STOP resets the divider, any value written to DIV clears it
+        bus.write_byte(DIVIDER_REG_ADDR, 0)?;
+        self.low_power_mode = true;
+        Ok(())
     }
 
-    fn halt(&mut self, _bus: &mut dyn BusAccess) {
-        self.halted = true;
+    fn is_stopped(&mut self, bus: &dyn BusAccess) -> bool {
+        // STOP mode is left as soon as one of the selected P1 input lines goes low.
+        // The lines are not a CPU read of P1, watchpoints must not see them.
+        if self.low_power_mode && bus.joypad().read() & 0x0F != 0x0F {
+            self.low_power_mode = false;
+        }
+
+        self.low_power_mode
     }
 
     fn fetch_instruction(&mut self, bus: &dyn BusAccess) -> Result<Instruction, GbError> {
@@ -498,42 +562,53 @@ impl CPU {
         let opcode =
             Opcode::from_u8(opcode_data).ok_or(GbError::UnknownInstruction(opcode_data))?;
 
+        // When the HALT bug triggers the opcode byte is read twice
+        let pc_increment = if self.halt_bug { 0 } else { 1 };
+        self.halt_bug = false;
+
         let byte = if opcode.length() == 2 {
-            Some(bus.read_byte(self.reg_pc + 1)?)
+            Some(bus.read_byte(self.reg_pc + pc_increment)?)
         } else {
             None
         };
 
         let word = if opcode.length() == 3 {
-            Some(bus.read_word(self.reg_pc + 1)?)
+            Some(bus.read_word(self.reg_pc + pc_increment)?)
         } else {
             None
         };
 
         self.reg_pc_prev = self.reg_pc;
-        self.reg_pc += opcode.length() as u16;
+        self.reg_pc += opcode.length() as u16 - 1 + pc_increment;
 
         Instruction::decode(opcode, byte, word)
     }
 
     pub fn step(&mut self, bus: &mut dyn BusAccess) -> Result<u8, GbError> {
+        // IME enabled by EI becomes effective after the instruction following EI
+        self.ime.tick();
+        self.executed = false;
+
+        if self.is_stopped(bus) {
+            return Ok(1);
+        }
+
         if self.is_halted(bus)? {
             return Ok(1);
         }
 
         if self.check_interrupts(bus)? {
-            return Ok(5);
+            return Ok(INTERRUPT_DISPATCH_CYCLES);
         }
 
-        self.ime.tick();
-
         let instr = self.fetch_instruction(bus)?;
+        self.executed = true;
 
         let mut jumped = false;
 
         match instr.instr_type() {
             InstructionType::Nop => (),
-            InstructionType::Stop => self.low_power_mode = true,
+            InstructionType::Stop => self.stop(bus)?,
             InstructionType::Halt => self.halt(bus),
             InstructionType::FlipCarry => {
                 self.set_flags(self.get_zero_flag(), false, false, !self.get_carry_flag())
@@ -598,6 +673,7 @@ impl CPU {
             bcd_n: self.get_bcd_n_flag(),
             ime: self.ime.get(),
             halted: self.halted,
+            stopped: self.low_power_mode,
         }
     }
 }
@@ -607,13 +683,67 @@ mod tests {
     use mockall::predicate::eq;
 
     use crate::gbr::{
-        bus::MockBusAccess,
+        bus::{BusAccess, MockBusAccess},
         instruction::{opcode::Opcode, GenericRegType::*, Instruction, SingleRegType::*, Source},
-        interrupts::InterruptHandler,
+        interrupts::{InterruptHandler, InterruptType},
+        joypad::{Buttons, Joypad},
+        GbError,
     };
 
     use super::CPU;
 
+    // Flat memory bus, used where IE/IF must be reachable through memory writes
+    struct TestBus {
+        memory: Vec<u8>,
+        ir_handler: InterruptHandler,
+        joypad: Joypad,
+    }
+
+    impl TestBus {
+        fn new() -> Self {
+            Self {
+                memory: vec![0; 0x10000],
+                ir_handler: InterruptHandler::default(),
+                joypad: Joypad::default(),
+            }
+        }
+    }
+
+    impl BusAccess for TestBus {
+        fn read_byte(&self, addr: u16) -> Result<u8, GbError> {
+            match addr {
+                0xFF0F => Ok(self.ir_handler.read_if()),
+                0xFFFF => Ok(self.ir_handler.read_ie()),
+                _ => Ok(self.memory[addr as usize]),
+            }
+        }
+
+        fn write_byte(&mut self, addr: u16, value: u8) -> Result<(), GbError> {
+            match addr {
+                0xFF0F => self.ir_handler.write_if(value),
+                0xFFFF => self.ir_handler.write_ie(value),
+                _ => self.memory[addr as usize] = value,
+            }
+            Ok(())
+        }
+
+        fn read_word(&self, addr: u16) -> Result<u16, GbError> {
+            Ok(self.read_byte(addr)? as u16 | (self.read_byte(addr + 1)? as u16) << 8)
+        }
+
+        fn ir_handler(&self) -> &InterruptHandler {
+            &self.ir_handler
+        }
+
+        fn ir_handler_mut(&mut self) -> &mut InterruptHandler {
+            &mut self.ir_handler
+        }
+
+        fn joypad(&self) -> &Joypad {
+            &self.joypad
+        }
+    }
+
     struct CpuTester {
         cpu: CPU,
         bus: MockBusAccess,
@@ -621,18 +751,46 @@ mod tests {
 
     impl CpuTester {
         fn new() -> Self {
+            Self::with_ir_handler(InterruptHandler::default())
+        }
+
+        fn with_ir_handler(ir_handler: InterruptHandler) -> Self {
+            Self::with_state(ir_handler, Joypad::default())
+        }
+
+        fn with_state(ir_handler: InterruptHandler, joypad: Joypad) -> Self {
             let mut bus = MockBusAccess::new();
 
-            bus.expect_ir_handler()
-                .return_const(InterruptHandler::default());
+            bus.expect_ir_handler().return_const(ir_handler.clone());
+
+            bus.expect_ir_handler_mut().return_var(ir_handler);
 
-            bus.expect_ir_handler_mut()
-                .return_var(InterruptHandler::default());
+            bus.expect_joypad().return_const(joypad);
 
             Self {
                 cpu: CPU::new(),
-                bus: MockBusAccess::new(),
+                bus,
+            }
+        }
+
+        fn with_pending_interrupt() -> Self {
+            let mut ir_handler = InterruptHandler::default();
+            ir_handler.write_ie(0b00000001);
+            ir_handler.write_if(0b00000001);
+
+            Self::with_ir_handler(ir_handler)
+        }
+
+        fn load_program(&mut self, addr: u16, program: &[u8]) {
+            for (offset, byte) in program.iter().enumerate() {
+                let byte = *byte;
+                self.bus
+                    .expect_read_byte()
+                    .with(eq(addr + offset as u16))
+                    .returning(move |_| Ok(byte));
             }
+
+            self.cpu.reg_pc = addr;
         }
 
         fn exec(&mut self, opcode: Opcode, byte: Option<u8>, word: Option<u16>) -> u8 {
@@ -721,4 +879,260 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn halt_without_pending_interrupt() {
+        let mut tester = CpuTester::new();
+        tester.load_program(0x0100, &[Opcode::Halt as u8, Opcode::Nop as u8]);
+
+        tester.cpu.step(&mut tester.bus).unwrap();
+
+        assert!(tester.cpu.halted);
+        assert_eq!(tester.cpu.read_pc(), 0x0101);
+
+        let cycles = tester.cpu.step(&mut tester.bus).unwrap();
+
+        assert_eq!(cycles, 1);
+        assert!(tester.cpu.halted);
+        assert_eq!(tester.cpu.read_pc(), 0x0101);
+    }
+
+    #[test]
+    fn halt_bug() {
+        let mut tester = CpuTester::with_pending_interrupt();
+        tester.load_program(0x0100, &[Opcode::Halt as u8, Opcode::LdAd8 as u8, 0x14]);
+
+        tester.cpu.step(&mut tester.bus).unwrap();
+
+        assert!(!tester.cpu.halted);
+        assert_eq!(tester.cpu.read_pc(), 0x0101);
+
+        // The opcode byte is read twice: it is used as operand as well
+        tester.cpu.step(&mut tester.bus).unwrap();
+
+        assert_eq!(tester.cpu.read_single_reg(&A), Opcode::LdAd8 as u8);
+        assert_eq!(tester.cpu.read_pc(), 0x0102);
+    }
+
+    #[test]
+    fn ei_halt_returns_to_halt() {
+        let mut tester = CpuTester::with_pending_interrupt();
+        tester.load_program(0x0100, &[Opcode::Ei as u8, Opcode::Halt as u8]);
+        tester.cpu.write_sp(0xFFFE);
+
+        tester
+            .bus
+            .expect_write_byte()
+            .with(eq(0xFFFD), eq(0x01))
+            .times(1)
+            .returning(|_, _| Ok(()));
+        tester
+            .bus
+            .expect_write_byte()
+            .with(eq(0xFFFC), eq(0x01))
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        tester.cpu.step(&mut tester.bus).unwrap();
+        tester.cpu.step(&mut tester.bus).unwrap();
+        tester.cpu.step(&mut tester.bus).unwrap();
+
+        // The interrupt handler returns to the HALT instruction
+        assert_eq!(tester.cpu.read_pc(), 0x0040);
+        assert!(!tester.cpu.halt_bug);
+    }
+
+    #[test]
+    fn stop_resets_divider() {
+        let mut tester = CpuTester::new();
+        tester.load_program(0x0100, &[Opcode::Stop as u8, 0x00]);
+
+        tester
+            .bus
+            .expect_write_byte()
+            .with(eq(0xFF04), eq(0))
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        tester.cpu.step(&mut tester.bus).unwrap();
+
+        assert!(tester.cpu.low_power_mode);
+        assert_eq!(tester.cpu.read_pc(), 0x0102);
+    }
+
+    #[test]
+    fn stop_wakes_up_on_joypad() {
+        let mut tester = CpuTester::new();
+        tester.load_program(0x0100, &[Opcode::Nop as u8]);
+        tester.cpu.low_power_mode = true;
+
+        // P1 is not read through the bus, watchpoints would see it
+        tester.bus.expect_read_byte().with(eq(0xFF00)).never();
+
+        let cycles = tester.cpu.step(&mut tester.bus).unwrap();
+
+        assert_eq!(cycles, 1);
+        assert!(tester.cpu.low_power_mode);
+        assert_eq!(tester.cpu.read_pc(), 0x0100);
+
+        let mut joypad = Joypad::default();
+        joypad.write(0x10);
+        joypad.press_button(Buttons::A);
+
+        let cpu = tester.cpu;
+        let mut tester = CpuTester::with_state(InterruptHandler::default(), joypad);
+        tester.load_program(0x0100, &[Opcode::Nop as u8]);
+        tester.cpu = cpu;
+
+        tester.cpu.step(&mut tester.bus).unwrap();
+
+        assert!(!tester.cpu.low_power_mode);
+        assert_eq!(tester.cpu.read_pc(), 0x0101);
+    }
+
+    #[test]
+    fn stop_ignores_joypad_interrupt_flag() {
+        let mut ir_handler = InterruptHandler::default();
+        ir_handler.set(InterruptType::Joypad);
+
+        let mut tester = CpuTester::with_ir_handler(ir_handler);
+        tester.load_program(0x0100, &[Opcode::Nop as u8]);
+        tester.cpu.low_power_mode = true;
+
+        // Left set from an earlier press, no input line is low
+        tester.cpu.step(&mut tester.bus).unwrap();
+
+        assert!(tester.cpu.low_power_mode);
+        assert_eq!(tester.cpu.read_pc(), 0x0100);
+    }
+
+    #[test]
+    fn ei_enables_ime_after_next_instruction() {
+        let mut tester = CpuTester::new();
+        tester.load_program(
+            0x0100,
+            &[Opcode::Ei as u8, Opcode::Nop as u8, Opcode::Nop as u8],
+        );
+
+        tester.cpu.step(&mut tester.bus).unwrap();
+        assert!(!tester.cpu.state().ime);
+
+        tester.cpu.step(&mut tester.bus).unwrap();
+        assert!(!tester.cpu.state().ime);
+
+        tester.cpu.step(&mut tester.bus).unwrap();
+        assert!(tester.cpu.state().ime);
+    }
+
+    #[test]
+    fn ei_di_does_not_enable_ime() {
+        let mut tester = CpuTester::with_pending_interrupt();
+        tester.load_program(
+            0x0100,
+            &[Opcode::Ei as u8, Opcode::Di as u8, Opcode::Nop as u8],
+        );
+
+        for _ in 0..3 {
+            tester.cpu.step(&mut tester.bus).unwrap();
+        }
+
+        assert!(!tester.cpu.state().ime);
+        assert_eq!(tester.cpu.read_pc(), 0x0103);
+    }
+
+    #[test]
+    fn ei_ei_does_not_restart_delay() {
+        let mut tester = CpuTester::new();
+        tester.load_program(
+            0x0100,
+            &[Opcode::Ei as u8, Opcode::Ei as u8, Opcode::Nop as u8],
+        );
+
+        tester.cpu.step(&mut tester.bus).unwrap();
+        tester.cpu.step(&mut tester.bus).unwrap();
+        tester.cpu.step(&mut tester.bus).unwrap();
+
+        assert!(tester.cpu.state().ime);
+    }
+
+    #[test]
+    fn reti_enables_ime_immediately() {
+        let mut tester = CpuTester::new();
+        tester.load_program(0x0100, &[Opcode::RetI as u8]);
+        tester.load_program(0xFFFC, &[0x34, 0x12]);
+        tester.cpu.reg_pc = 0x0100;
+        tester.cpu.write_sp(0xFFFC);
+
+        tester.cpu.step(&mut tester.bus).unwrap();
+
+        assert!(tester.cpu.state().ime);
+        assert_eq!(tester.cpu.read_pc(), 0x1234);
+        assert_eq!(tester.cpu.read_sp(), 0xFFFE);
+    }
+
+    #[test]
+    fn interrupt_dispatch() {
+        let mut bus = TestBus::new();
+        bus.ir_handler.write_ie(0b00000101);
+        bus.ir_handler.write_if(0b00000101);
+
+        let mut cpu = CPU::new();
+        cpu.reg_pc = 0x1234;
+        cpu.write_sp(0xD000);
+        cpu.ime.set_now(true);
+
+        let cycles = cpu.step(&mut bus).unwrap();
+
+        assert_eq!(cycles, 5);
+        assert_eq!(cpu.read_pc(), 0x0040);
+        assert_eq!(cpu.read_sp(), 0xCFFE);
+        assert_eq!(bus.read_word(0xCFFE).unwrap(), 0x1234);
+        assert!(!cpu.state().ime);
+
+        // Lower priority interrupt is still pending
+        assert_eq!(
+            bus.ir_handler.highest_priority_pending(),
+            Some(InterruptType::Timer)
+        );
+    }
+
+    #[test]
+    fn interrupt_dispatch_cancelled_by_ie_push() {
+        let mut bus = TestBus::new();
+        bus.ir_handler.write_ie(0b00000001);
+        bus.ir_handler.write_if(0b00000001);
+
+        let mut cpu = CPU::new();
+        // High byte of PC is pushed to 0xFFFF (IE) and disables VBlank
+        cpu.reg_pc = 0x0200;
+        cpu.write_sp(0x0000);
+        cpu.ime.set_now(true);
+
+        let cycles = cpu.step(&mut bus).unwrap();
+
+        assert_eq!(cycles, 5);
+        assert_eq!(cpu.read_pc(), 0x0000);
+        assert_eq!(cpu.read_sp(), 0xFFFE);
+        assert_eq!(bus.ir_handler.read_ie(), 0x02);
+        // Interrupt was not serviced, so its flag is not cleared
+        assert_eq!(bus.ir_handler.read_if(), 0b00000001);
+    }
+
+    #[test]
+    fn interrupt_dispatch_redirected_by_ie_push() {
+        let mut bus = TestBus::new();
+        bus.ir_handler.write_ie(0b00000001);
+        bus.ir_handler.write_if(0b00000101);
+
+        let mut cpu = CPU::new();
+        // High byte of PC pushed to IE disables VBlank and enables Timer
+        cpu.reg_pc = 0x0400;
+        cpu.write_sp(0x0000);
+        cpu.ime.set_now(true);
+
+        cpu.step(&mut bus).unwrap();
+
+        assert_eq!(cpu.read_pc(), 0x0050);
+        assert_eq!(bus.ir_handler.read_if(), 0b00000001);
+    }
 }
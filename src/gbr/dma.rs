@@ -8,15 +8,18 @@ use super::{
     },
     oam::ObjAttributeMemory,
     ppu::PPU,
+    watchpoint::{MemAccess, Watchpoints},
     GbError,
 };
 
+#[derive(Clone)]
 enum SourceType {
     Cart,
     Vram,
     Wram,
 }
 
+#[derive(Clone)]
 pub struct DMA {
     source_addr: u16,
     curr_index: u16,
@@ -40,6 +43,7 @@ impl DMA {
         ppu: &PPU,
         mbc: &MBC,
         oam: &mut ObjAttributeMemory,
+        watchpoints: &Watchpoints,
         cycles: u8,
     ) -> Result<(), GbError> {
         if self.started {
@@ -51,8 +55,11 @@ impl DMA {
                         Ok(wram[(self.source_addr - WRAM_START + self.curr_index) as usize])
                     }
                 }?;
+                watchpoints.check(self.source_addr + self.curr_index, data, MemAccess::Read);
 
-                oam.write_byte(OBJ_ATTRIBUTE_TABLE_START + self.curr_index, data)?;
+                let dest_addr = OBJ_ATTRIBUTE_TABLE_START + self.curr_index;
+                oam.write_byte(dest_addr, data)?;
+                watchpoints.check(dest_addr, data, MemAccess::Write);
 
                 self.curr_index += 1;
 
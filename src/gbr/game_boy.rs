@@ -4,23 +4,38 @@ use std::{
         mpsc::{channel, Sender},
         Arc, RwLock, RwLockWriteGuard,
     },
-    time::{Duration, SystemTime},
+    time::{Duration, Instant},
 };
 
+use enum_primitive::FromPrimitive;
 use flume::Receiver;
 
-use crate::gbr::{bus::Bus, cpu::CPU, ppu::PPU, GbError};
+use crate::gbr::{bus::Bus, cpu::CPU, instruction::opcode::Opcode, ppu::PPU, GbError};
 
 use super::{
-    bus::BusAccess,
+    apu::StereoSample,
+    bus::{BusAccess, BusSnapshot, MemWrite},
     cpu::CpuState,
+    gdb::{GdbCommand, GdbStub},
+    input_log::{ExternalInput, InputLog},
     interrupts::InterruptHandlerState,
     joypad::{Buttons, Directions, Joypad},
+    link::SerialPeer,
     mbc::MbcState,
+    memory_map::BankedAddress,
+    movie::{crc32, Movie, MovieMode, MovieTime},
     oam::ObjAttribute,
-    ppu::PpuState,
+    ppu::{palette::ColorScheme, PpuState, ScreenBuffer},
+    speed::{Pacer, Speed},
+    trace::{self, Tracer},
+    watchpoint::{WatchedBus, Watchpoint, WatchpointHit, Watchpoints},
 };
 
+// Debugger states are sent at most at the display rate while running
+const STATE_INTERVAL: Duration = Duration::from_millis(16);
+// Wait between event checks while paused
+const PAUSED_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
 #[derive(Default, Clone)]
 pub struct GbState {
     pub cpu: CpuState,
@@ -29,11 +44,37 @@ pub struct GbState {
     pub mbc: MbcState,
     pub oam: Vec<ObjAttribute>,
     pub joypad: Joypad,
+    pub watchpoint_hit: Option<WatchpointHit>,
+}
+
+/// Frame sent to a recording, with the audio output while it was drawn.
+pub struct CapturedFrame {
+    pub screen: ScreenBuffer,
+    pub samples: Vec<StereoSample>,
+}
+
+/// Emulator state saved by `GameBoy::snapshot`.
+pub struct Snapshot {
+    cpu: CPU,
+    bus: BusSnapshot,
+    cycles: u64,
+    frames: u64,
+    frame_start: u64,
 }
 
 pub struct GameBoy {
     cpu: CPU,
     bus: Bus,
+    cycles: u64,
+    // Frames completed and cycles count when the current one started
+    frames: u64,
+    frame_start: u64,
+    tracer: Option<Tracer>,
+    movie: Option<MovieMode>,
+    inputs: InputLog,
+    // Receives every frame, for recordings, and the last frame sent to it
+    capture: Option<flume::Sender<CapturedFrame>>,
+    captured: u64,
 }
 
 impl GameBoy {
@@ -41,9 +82,53 @@ impl GameBoy {
         Self {
             cpu: CPU::new(),
             bus: Bus::new(boot_rom_filename, cart_rom_filename),
+            cycles: 0,
+            frames: 0,
+            frame_start: 0,
+            tracer: None,
+            movie: None,
+            inputs: InputLog::default(),
+            capture: None,
+            captured: 0,
         }
     }
 
+    /// Create a Game Boy running the given cartridge ROM, skipping the boot ROM.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `GbError` if the cartridge header cannot be parsed.
+    ///
+    #[cfg(test)]
+    pub fn from_rom(rom: &[u8]) -> Result<Self, GbError> {
+        Ok(Self {
+            cpu: CPU::new_post_boot(),
+            bus: Bus::from_rom(rom)?,
+            cycles: 0,
+            frames: 0,
+            frame_start: 0,
+            tracer: None,
+            movie: None,
+            inputs: InputLog::default(),
+            capture: None,
+            captured: 0,
+        })
+    }
+
+    /// Create a Game Boy about to run `program`, stored after the cartridge
+    /// header of an otherwise empty 32 KiB ROM.
+    #[cfg(test)]
+    pub fn with_program(program: &[u8]) -> Self {
+        let mut rom = vec![0; 0x8000];
+        // JP 0x0150 from the entry point, over the header
+        rom[0x0100..0x0103].copy_from_slice(&[0xC3, 0x50, 0x01]);
+        rom[0x0150..0x0150 + program.len()].copy_from_slice(program);
+
+        let mut gb = Self::from_rom(&rom).unwrap();
+        gb.step().unwrap();
+        gb
+    }
+
     /// Run the Game Boy for a single instruction.
     ///
     /// Returns whether a vblank interrupt was triggered.
@@ -53,22 +138,101 @@ impl GameBoy {
     /// Returns a `GbError` if an error occurs during the step.
     ///
     pub fn step(&mut self) -> Result<bool, GbError> {
-        let cycles = self.cpu.step(&mut self.bus)?;
+        let time = self.time();
+        while let Some(input) = self.movie.as_mut().and_then(|m| m.next_input(time)) {
+            self.apply_input(input);
+        }
+        while let Some(input) = self.inputs.next_input(self.cycles) {
+            match input {
+                ExternalInput::Joypad(input) => self.apply_input(input),
+                ExternalInput::Serial(byte) => self.bus.serial_mut().replay_byte(byte),
+            }
+        }
+        let cycles_before = self.cycles;
+
+        self.bus.watchpoints_mut().set_pc(self.cpu.read_pc());
+
+        let trace_line = self.trace_line();
+
+        let cycles = self.cpu.step(&mut WatchedBus::new(&mut self.bus))?;
+        self.cycles += 4 * cycles as u64;
+
+        if let Some(line) = trace_line.filter(|_| self.cpu.executed_instruction()) {
+            self.write_trace(&line);
+        }
+
+        let vblank = self.bus.step(4 * cycles)?;
+        if vblank {
+            self.frames += 1;
+            self.frame_start = self.cycles;
+            self.capture_frame();
+
+            // Recorded inputs reach the file once per frame
+            if let Some(movie) = self.movie.as_mut() {
+                let result = movie.flush();
+                self.movie_error(result);
+            }
+        }
 
-        self.bus.step(4 * cycles)
+        for byte in self.bus.serial_mut().take_received() {
+            self.inputs.push(cycles_before, ExternalInput::Serial(byte));
+        }
+        if !self.inputs.is_replaying() {
+            self.bus.serial_mut().set_replaying(false);
+        }
+
+        Ok(vblank)
     }
 
-    pub fn run_to_vblank(&mut self) -> Result<(), GbError> {
+    /// Run the Game Boy until a vblank interrupt, or until `should_break`
+    /// returns true after an instruction.
+    ///
+    /// Returns whether execution was interrupted by `should_break`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `GbError` if an error occurs during a step.
+    ///
+    pub fn run_to_vblank(
+        &mut self,
+        mut should_break: impl FnMut(&mut GameBoy) -> bool,
+    ) -> Result<bool, GbError> {
         loop {
-            if self.step()? {
-                break;
+            let vblank = self.step()?;
+
+            if should_break(self) {
+                return Ok(true);
             }
-        }
 
-        Ok(())
+            if vblank {
+                return Ok(false);
+            }
+        }
     }
 
+    /// Apply a user input. Ignored while a movie is played back, or while
+    /// the inputs logged after a restored snapshot are replayed.
     pub fn handle_input(&mut self, input: InputType) {
+        if self.inputs.is_replaying() {
+            log::debug!("Input ignored while replaying: {:?}", input);
+            return;
+        }
+
+        let time = self.time();
+
+        let result = match self.movie.as_mut() {
+            Some(movie) if movie.is_playing() => return,
+            Some(movie) => movie.record(time, &input),
+            None => Ok(()),
+        };
+        self.movie_error(result);
+
+        self.inputs
+            .push(self.cycles, ExternalInput::Joypad(input.clone()));
+        self.apply_input(input);
+    }
+
+    fn apply_input(&mut self, input: InputType) {
         match input {
             InputType::Pressed(GenericInput::Button(button)) => {
                 self.bus.joypad_mut().press_button(button)
@@ -85,27 +249,240 @@ impl GameBoy {
         }
     }
 
+    /// Plug a link cable into the serial port, or unplug it with `None`.
+    pub fn set_serial_peer(&mut self, peer: Option<Box<dyn SerialPeer>>) {
+        self.bus.set_serial_peer(peer);
+    }
+
+    /// Reset and record the inputs from power on to a movie file.
+    pub fn record_movie(&mut self, path: PathBuf) -> Result<(), GbError> {
+        let movie = Movie::new(crc32(&self.bus.mbc().rom()), self.bus.has_boot_rom());
+        let movie = MovieMode::start_recording(path, movie)?;
+
+        self.reset();
+        self.movie = Some(movie);
+        Ok(())
+    }
+
+    /// Reset and play back `movie`, ignoring user inputs.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `GbError` if the movie was recorded with another ROM or boot ROM setting.
+    ///
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), GbError> {
+        if movie.rom_crc32 != crc32(&self.bus.mbc().rom()) {
+            return Err(GbError::Movie("recorded with another ROM".into()));
+        }
+        if movie.boot_rom != self.bus.has_boot_rom() {
+            return Err(GbError::Movie("boot ROM setting differs".into()));
+        }
+
+        self.reset();
+        self.movie = Some(MovieMode::Play { movie, next: 0 });
+        Ok(())
+    }
+
+    fn time(&self) -> MovieTime {
+        (self.frames, self.cycles - self.frame_start)
+    }
+
+    fn movie_error(&mut self, result: Result<(), GbError>) {
+        if let Err(e) = result {
+            log::error!("Movie stopped: {}", e);
+            self.movie = None;
+        }
+    }
+
+    /// Log every executed instruction to `tracer`, or stop tracing with `None`.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    // State before the next instruction, if it passes the trace filter
+    fn trace_line(&self) -> Option<String> {
+        let tracer = self.tracer.as_ref()?;
+
+        let addr = BankedAddress::resolve(self.cpu.read_pc(), &self.bus.mbc().state());
+        if !tracer.filter().matches(&addr) {
+            return None;
+        }
+
+        Some(trace::trace_line(&self.cpu, &self.bus))
+    }
+
+    fn write_trace(&mut self, line: &str) {
+        if let Some(Err(e)) = self.tracer.as_mut().map(|tracer| tracer.write(line)) {
+            log::error!("Trace disabled, write failed: {}", e);
+            self.tracer = None;
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cpu: self.cpu.clone(),
+            bus: self.bus.snapshot(),
+            cycles: self.cycles,
+            frames: self.frames,
+            frame_start: self.frame_start,
+        }
+    }
+
+    /// Go back to a snapshot, keeping watchpoints and the link cable.
+    ///
+    /// The external inputs logged after the snapshot are replayed by the
+    /// following steps.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.cpu = snapshot.cpu.clone();
+        self.bus.restore(&snapshot.bus);
+        self.cycles = snapshot.cycles;
+        self.frames = snapshot.frames;
+        self.frame_start = snapshot.frame_start;
+
+        self.inputs.rewind(self.cycles);
+        let replaying = self.inputs.is_replaying();
+        self.bus.serial_mut().set_replaying(replaying);
+
+        let time = self.time();
+        if let Some(movie) = self.movie.as_mut() {
+            movie.seek(time);
+        }
+    }
+
+    /// Take another branch from the current step: forget the inputs logged
+    /// after it and let live inputs apply again. A recorded movie is cut there.
+    pub fn new_branch(&mut self) {
+        self.inputs.truncate(self.cycles);
+        self.bus.serial_mut().set_replaying(false);
+
+        let time = self.time();
+        if let Some(movie) = self.movie.as_mut() {
+            let result = movie.truncate(time);
+            self.movie_error(result);
+        }
+    }
+
+    /// Whether inputs logged after a restored snapshot are being replayed.
+    pub fn is_replaying(&self) -> bool {
+        self.inputs.is_replaying()
+    }
+
+    /// Start or stop logging the memory writes of the CPU.
+    pub fn log_writes(&mut self, enabled: bool) {
+        self.bus.log_writes(enabled);
+    }
+
+    /// Take the CPU writes logged since the last call.
+    pub fn take_writes(&mut self) -> Vec<MemWrite> {
+        self.bus.take_writes()
+    }
+
+    /// Start or stop logging the external inputs replayed after a restore.
+    pub fn log_inputs(&mut self, enabled: bool) {
+        self.inputs.set_enabled(enabled);
+        self.bus.serial_mut().set_replaying(false);
+    }
+
+    /// Forget the inputs logged before `snapshot`, it is not restored anymore.
+    pub fn forget_inputs_before(&mut self, snapshot: &Snapshot) {
+        self.inputs.forget_before(snapshot.cycles);
+    }
+
+    pub fn watchpoints_mut(&mut self) -> &mut Watchpoints {
+        self.bus.watchpoints_mut()
+    }
+
+    /// Take the first watchpoint hit since the last call, if any.
+    pub fn take_watchpoint_hit(&mut self) -> Option<WatchpointHit> {
+        self.bus.watchpoints_mut().take_hit()
+    }
+
     pub fn update_settings(&mut self, settings: &EmuSettings) {
         todo!()
     }
 
     pub fn reset(&mut self) {
-        self.cpu = CPU::new();
+        self.cpu = if self.bus.has_boot_rom() {
+            CPU::new()
+        } else {
+            CPU::new_post_boot()
+        };
         self.bus.reset();
+        self.cycles = 0;
+        self.frames = 0;
+        self.frame_start = 0;
+        self.captured = 0;
+        self.inputs.truncate(0);
+
+        // A movie starts over with the emulation
+        if let Some(movie) = self.movie.as_mut() {
+            let result = movie.restart();
+            self.movie_error(result);
+        }
+    }
+
+    /// Number of clock cycles elapsed since power on or reset.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
     }
 
     pub fn cpu(&self) -> &CPU {
         &self.cpu
     }
 
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
     pub fn bus(&self) -> &Bus {
         &self.bus
     }
 
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
     pub fn ppu(&self) -> &PPU {
         &self.bus.ppu()
     }
 
+    /// Only send one frame every `frame_skip + 1` to the renderer.
+    pub fn set_frame_skip(&mut self, frame_skip: u8) {
+        self.bus.ppu_mut().set_frame_skip(frame_skip);
+    }
+
+    /// Send every frame to `capture`, or stop with None.
+    pub fn set_capture(&mut self, capture: Option<flume::Sender<CapturedFrame>>) {
+        self.capture = capture;
+        self.captured = self.frames;
+    }
+
+    fn capture_frame(&mut self) {
+        // Samples are taken every frame, recording or not
+        let samples = self.bus.apu_mut().take_samples();
+
+        // Frames run again after going back in time were already sent
+        if self.frames <= self.captured {
+            return;
+        }
+        self.captured = self.frames;
+
+        // The recording stops once its receiver is gone
+        if let Some(capture) = &self.capture {
+            let frame = CapturedFrame {
+                screen: self.bus.ppu().frame().clone(),
+                samples,
+            };
+            if capture.send(frame).is_err() {
+                self.capture = None;
+            }
+        }
+    }
+
+    pub fn set_color_scheme(&mut self, colors: ColorScheme) {
+        self.bus.ppu_mut().set_color_scheme(colors);
+    }
+
     pub fn collect_state(&self) -> GbState {
         GbState {
             cpu: self.cpu.state(),
@@ -114,6 +491,7 @@ impl GameBoy {
             mbc: self.bus.mbc().state(),
             oam: self.bus.oam().state(),
             joypad: self.bus.joypad().clone(),
+            watchpoint_hit: self.bus.watchpoints().last_hit(),
         }
     }
 }
@@ -123,16 +501,47 @@ pub enum GbrEvent {
     Stop,
     Pause,
     Step,
+    StepOver,
+    StepOut,
+    RunTo(BankedAddress),
+    RunFrames(u32),
     Input(InputType),
+    SetSpeed(Speed),
+    // Start recording the frames to the given channel, or stop with None
+    Capture(Option<flume::Sender<CapturedFrame>>),
+    SetColorScheme(ColorScheme),
+    // Run unlimited while held
+    FastForward(bool),
     UpdateSettings(EmuSettings),
     Debug(DebugEvent),
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum GenericInput {
     Button(Buttons),
     Direction(Directions),
 }
+
+impl GenericInput {
+    /// Key name, e.g. "Start" or "Left".
+    pub fn name(&self) -> &'static str {
+        let name = match self {
+            GenericInput::Button(button) => button.iter_names().next().map(|(name, _)| name),
+            GenericInput::Direction(direction) => {
+                direction.iter_names().next().map(|(name, _)| name)
+            }
+        };
+        name.unwrap_or("?")
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Buttons::from_name(name)
+            .map(GenericInput::Button)
+            .or_else(|| Directions::from_name(name).map(GenericInput::Direction))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum InputType {
     Pressed(GenericInput),
     Released(GenericInput),
@@ -144,17 +553,42 @@ pub struct EmuSettings {
 }
 
 pub enum DebugEvent {
-    SetBreakpoint(u16),
-    ClearBreakpoint(u16),
+    SetBreakpoint {
+        addr: BankedAddress,
+        condition: Option<String>,
+        ignore_count: u32,
+    },
+    ClearBreakpoint(BankedAddress),
+    SetWatchpoint(Watchpoint),
+    ClearWatchpoint(Watchpoint),
+    RecordHistory(bool),
+    StepBack,
+    ReverseContinue,
+    RewindToLastWrite(u16),
+    // Stop replaying the inputs after the current step and forget them
+    NewBranch,
+    // Start sending the memory content with the given banks, or stop with None
+    ViewMemory(Option<MemoryBanks>),
+    WriteMemory(BankedAddress, u8),
     DumpVram,
 }
 
+/// Cartridge banks shown in the memory view, the mapped ones when None.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MemoryBanks {
+    pub rom: Option<u16>,
+    pub ram: Option<u16>,
+}
+
 pub trait Debugger {
-    fn handle_event(&mut self, gb: &RwLockWriteGuard<GameBoy>, ev: &DebugEvent);
+    fn handle_event(&mut self, gb: &mut RwLockWriteGuard<GameBoy>, ev: &DebugEvent);
 
     fn send_state(&mut self, gb: &RwLockWriteGuard<GameBoy>);
 
-    fn should_break(&self, gb: &RwLockWriteGuard<GameBoy>) -> bool;
+    /// Called after every step, before asking whether to break.
+    fn record_step(&mut self, gb: &mut GameBoy);
+
+    fn should_break(&mut self, gb: &mut GameBoy) -> bool;
 }
 
 pub enum EmuState {
@@ -163,65 +597,323 @@ pub enum EmuState {
     Error,
 }
 
+// Condition ending a debugger run command
+enum RunUntil {
+    // Back after the stepped over call, with its frame popped
+    Return { pc: u16, sp: u16 },
+    // A return popped the frame active when stepping out
+    StepOut { sp: u16 },
+    Address(BankedAddress),
+}
+
+impl RunUntil {
+    fn reached(&self, gb: &GameBoy) -> bool {
+        let cpu = gb.cpu();
+
+        match self {
+            RunUntil::Return { pc, sp } => cpu.read_pc() == *pc && cpu.read_sp() >= *sp,
+            RunUntil::StepOut { sp } => {
+                let last_opcode = gb.bus().read_byte(cpu.reg_pc_prev).ok();
+
+                cpu.read_sp() > *sp
+                    && last_opcode
+                        .and_then(Opcode::from_u8)
+                        .is_some_and(|opcode| opcode.is_return())
+            }
+            RunUntil::Address(addr) => {
+                BankedAddress::resolve(cpu.read_pc(), &gb.bus().mbc().state()) == *addr
+            }
+        }
+    }
+}
+
+enum RunMode {
+    Paused,
+    Running,
+    Step,
+    Until(RunUntil),
+    Frames(u32),
+}
+
+impl RunMode {
+    // Treat CALL and RST as a single instruction
+    fn step_over(gb: &GameBoy) -> Self {
+        let pc = gb.cpu().read_pc();
+        let opcode = gb.bus().read_byte(pc).ok().and_then(Opcode::from_u8);
+
+        match opcode {
+            Some(opcode) if opcode.is_call() => RunMode::Until(RunUntil::Return {
+                pc: pc.wrapping_add(opcode.length() as u16),
+                sp: gb.cpu().read_sp(),
+            }),
+            _ => RunMode::Step,
+        }
+    }
+}
+
+// Let the debugger record the step, then check both for a break
+fn after_step(debugger: &mut impl Debugger, gdb: &Option<GdbStub>, gb: &mut GameBoy) -> bool {
+    debugger.record_step(gb);
+
+    let hit = debugger.should_break(gb);
+    hit || gdb.as_ref().is_some_and(|gdb| gdb.should_break(gb))
+}
+
 pub fn start_gb_thread<DebuggerType: Debugger + Sync + Send + 'static>(
     gb: Arc<RwLock<GameBoy>>,
     mut debugger: DebuggerType,
+    mut gdb: Option<GdbStub>,
 ) -> (Sender<GbrEvent>, Receiver<EmuState>) {
     let (ev_sender, ev_listener) = channel();
     let (emu_state_sig, emu_state_slot) = flume::bounded(1);
 
     std::thread::spawn(move || {
-        let mut running = false;
-        let mut stepping = false;
+        let mut mode = RunMode::Paused;
         let mut gb = gb.write().unwrap();
 
-        let frame_time = Duration::from_secs_f64(1.0 / 59.7);
-        let mut now = SystemTime::now();
+        let mut pacer = Pacer::default();
+        let mut state_sent = Instant::now();
         loop {
             if let Ok(ev) = ev_listener.try_recv() {
                 match ev {
                     GbrEvent::Start => {
-                        running = true;
+                        mode = RunMode::Running;
                         emu_state_sig.send(EmuState::Running).ok();
                     }
                     GbrEvent::Pause => {
-                        running = false;
+                        mode = RunMode::Paused;
                         emu_state_sig.send(EmuState::Idle).ok();
+
+                        if let Some(gdb) = gdb.as_mut() {
+                            gdb.stopped(&gb);
+                        }
+                    }
+                    GbrEvent::Step => mode = RunMode::Step,
+                    GbrEvent::StepOver => {
+                        mode = RunMode::step_over(&gb);
+                        if matches!(mode, RunMode::Until(_)) {
+                            emu_state_sig.send(EmuState::Running).ok();
+                        }
+                    }
+                    GbrEvent::StepOut => {
+                        mode = RunMode::Until(RunUntil::StepOut {
+                            sp: gb.cpu().read_sp(),
+                        });
+                        emu_state_sig.send(EmuState::Running).ok();
                     }
-                    GbrEvent::Step => stepping = true,
+                    GbrEvent::RunTo(addr) => {
+                        mode = RunMode::Until(RunUntil::Address(addr));
+                        emu_state_sig.send(EmuState::Running).ok();
+                    }
+                    GbrEvent::RunFrames(frames) if frames > 0 => {
+                        mode = RunMode::Frames(frames);
+                        emu_state_sig.send(EmuState::Running).ok();
+                    }
+                    GbrEvent::RunFrames(_) => (),
                     GbrEvent::Stop => {
-                        running = false;
+                        mode = RunMode::Paused;
                         gb.reset();
 
                         emu_state_sig.send(EmuState::Idle).ok();
                     }
                     GbrEvent::Input(input) => gb.handle_input(input),
+                    GbrEvent::SetSpeed(speed) => {
+                        pacer.set_speed(speed);
+                        gb.set_frame_skip(pacer.speed().frame_skip());
+                    }
+                    GbrEvent::Capture(capture) => gb.set_capture(capture),
+                    GbrEvent::SetColorScheme(colors) => gb.set_color_scheme(colors),
+                    GbrEvent::FastForward(fast_forward) => {
+                        pacer.set_fast_forward(fast_forward);
+                        gb.set_frame_skip(pacer.speed().frame_skip());
+                    }
                     GbrEvent::UpdateSettings(settings) => gb.update_settings(&settings),
-                    GbrEvent::Debug(ev) => debugger.handle_event(&gb, &ev),
+                    GbrEvent::Debug(ev) => debugger.handle_event(&mut gb, &ev),
                 }
             }
 
-            if running {
-                gb.run_to_vblank().unwrap();
+            let mut halt = false;
+            match gdb.as_mut().and_then(|gdb| gdb.poll(&mut gb)) {
+                Some(GdbCommand::Halt) => halt = true,
+                Some(GdbCommand::Continue) => {
+                    mode = RunMode::Running;
+                    emu_state_sig.send(EmuState::Running).ok();
+                }
+                Some(GdbCommand::Step) => mode = RunMode::Step,
+                None => (),
+            }
 
-                let elapsed = SystemTime::now().duration_since(now).unwrap();
-                now = SystemTime::now();
+            let pause = match &mut mode {
+                _ if halt => true,
+                RunMode::Paused => false,
+                RunMode::Running => {
+                    let interrupted = gb
+                        .run_to_vblank(|gb| after_step(&mut debugger, &gdb, gb))
+                        .unwrap();
 
-                // if elapsed < frame_time {
-                //     std::thread::sleep(frame_time - elapsed);
-                // }
-            } else if stepping {
-                gb.step().unwrap();
-            }
+                    if let Some(delay) = pacer.frame_delay(Instant::now()) {
+                        std::thread::sleep(delay);
+                    }
+
+                    interrupted
+                }
+                RunMode::Step => {
+                    gb.step().unwrap();
+                    debugger.record_step(&mut gb);
+
+                    // Execution stops anyway, only keep hit counts up to date
+                    debugger.should_break(&mut gb);
+                    true
+                }
+                RunMode::Until(until) => gb
+                    .run_to_vblank(|gb| after_step(&mut debugger, &gdb, gb) || until.reached(gb))
+                    .unwrap(),
+                RunMode::Frames(frames) => {
+                    let interrupted = gb
+                        .run_to_vblank(|gb| after_step(&mut debugger, &gdb, gb))
+                        .unwrap();
+                    *frames -= 1;
+
+                    if let Some(delay) = pacer.frame_delay(Instant::now()) {
+                        std::thread::sleep(delay);
+                    }
+
+                    interrupted || *frames == 0
+                }
+            };
 
-            if debugger.should_break(&gb) {
-                running = false;
+            if pause {
+                mode = RunMode::Paused;
                 emu_state_sig.send(EmuState::Idle).ok();
+
+                if let Some(gdb) = gdb.as_mut() {
+                    gdb.stopped(&gb);
+                }
             }
 
-            debugger.send_state(&gb);
+            // The UI shows at most one state per redraw, while running only
+            // send them at the display rate
+            let paused = matches!(mode, RunMode::Paused);
+            if paused || state_sent.elapsed() >= STATE_INTERVAL {
+                debugger.send_state(&gb);
+                state_sent = Instant::now();
+            }
+
+            if paused {
+                std::thread::sleep(PAUSED_POLL_INTERVAL);
+            }
         }
     });
 
     (ev_sender, emu_state_slot)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::gbr::trace::{TraceFilter, Tracer};
+
+    use super::{CapturedFrame, GameBoy, RunMode, RunUntil};
+
+    fn game_boy() -> GameBoy {
+        GameBoy::with_program(&[
+            0xCD, 0x54, 0x01, // CALL 0x0154
+            0x00, // NOP
+            0x00, // NOP
+            0x00, // NOP
+            0xC9, // RET
+        ])
+    }
+
+    fn run_until(gb: &mut GameBoy, mode: RunMode) -> usize {
+        let RunMode::Until(until) = mode else {
+            panic!("expected a run until mode");
+        };
+
+        let mut steps = 0;
+        while !until.reached(gb) {
+            gb.step().unwrap();
+            steps += 1;
+        }
+        steps
+    }
+
+    #[test]
+    fn step_over_call() {
+        let mut gb = game_boy();
+
+        let mode = RunMode::step_over(&gb);
+        assert_eq!(run_until(&mut gb, mode), 4);
+        assert_eq!(gb.cpu().read_pc(), 0x0153);
+
+        // Not a call, step a single instruction
+        assert!(matches!(RunMode::step_over(&gb), RunMode::Step));
+    }
+
+    #[test]
+    fn step_out() {
+        let mut gb = game_boy();
+        gb.step().unwrap();
+        gb.step().unwrap();
+        assert_eq!(gb.cpu().read_pc(), 0x0155);
+
+        let mode = RunMode::Until(RunUntil::StepOut {
+            sp: gb.cpu().read_sp(),
+        });
+        assert_eq!(run_until(&mut gb, mode), 2);
+        assert_eq!(gb.cpu().read_pc(), 0x0153);
+    }
+
+    #[test]
+    fn trace_filtered_instructions() {
+        let path = std::env::temp_dir().join(format!("gbr_trace_gb_{}.log", std::process::id()));
+        let filter = TraceFilter {
+            pc: Some(0x0154..=0x01FF),
+            bank: None,
+        };
+
+        let mut gb = game_boy();
+        gb.set_tracer(Some(Tracer::new(path.clone(), None, filter).unwrap()));
+        for _ in 0..4 {
+            gb.step().unwrap();
+        }
+        gb.set_tracer(None);
+
+        let trace = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let pcs: Vec<&str> = trace
+            .lines()
+            .filter_map(|line| line.split_whitespace().nth(9))
+            .collect();
+        assert_eq!(pcs, ["PC:0154", "PC:0155", "PC:0156"]);
+    }
+
+    fn run_frames(gb: &mut GameBoy, frames: usize) {
+        for _ in 0..frames {
+            while !gb.step().unwrap() {}
+        }
+    }
+
+    #[test]
+    fn capture_frames_once() {
+        let mut gb = GameBoy::with_program(&[0x18, 0xFE]);
+        let (sender, receiver) = flume::unbounded();
+        gb.set_capture(Some(sender));
+
+        let snapshot = gb.snapshot();
+        run_frames(&mut gb, 2);
+
+        // The frames run again are not sent twice
+        gb.restore(&snapshot);
+        run_frames(&mut gb, 3);
+
+        // The snapshot does not keep the recording open
+        gb.set_capture(None);
+        let frames: Vec<CapturedFrame> = receiver.iter().collect();
+        assert_eq!(frames.len(), 3);
+
+        // 44100 Hz / 59.73 fps, the audio stays in step with the frames
+        let samples: Vec<usize> = frames.iter().map(|frame| frame.samples.len()).collect();
+        assert_eq!(samples, [738, 738, 739]);
+    }
+}
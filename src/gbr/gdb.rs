@@ -0,0 +1,628 @@
+use std::{
+    collections::HashSet,
+    io::{ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+};
+
+use super::{
+    bus::BusAccess,
+    game_boy::GameBoy,
+    watchpoint::{WatchKind, WatchOwner, Watchpoint},
+    GbError,
+};
+
+// Largest packet accepted from GDB, in bytes
+const PACKET_SIZE: usize = 0x1000;
+
+// Interrupt request sent by GDB outside of a packet
+const INTERRUPT: u8 = 0x03;
+
+// SIGTRAP, reported for every stop
+const SIGTRAP: u8 = 5;
+
+/// Run control requested by GDB.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GdbCommand {
+    Halt,
+    Continue,
+    Step,
+}
+
+// Outcome of a packet
+#[derive(Debug, PartialEq)]
+enum Response {
+    Reply(String),
+    Resume(GdbCommand),
+    Close,
+}
+
+/// GDB remote serial protocol server.
+///
+/// Registers are AF, BC, DE, HL, SP and PC, 16 bit little endian, in the order
+/// of the GDB z80 target. Only one client is served at a time.
+pub struct GdbStub {
+    listener: TcpListener,
+    stream: Option<TcpStream>,
+    input: Vec<u8>,
+    breakpoints: HashSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    // Whether GDB waits for a stop reply
+    running: bool,
+}
+
+impl GdbStub {
+    pub fn listen<A: ToSocketAddrs>(addr: A) -> Result<Self, GbError> {
+        let listener = TcpListener::bind(addr).map_err(|e| GbError::Gdb(e.to_string()))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| GbError::Gdb(e.to_string()))?;
+
+        Ok(Self {
+            listener,
+            stream: None,
+            input: vec![],
+            breakpoints: HashSet::new(),
+            watchpoints: vec![],
+            running: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr().ok()
+    }
+
+    /// Accept a client and handle the packets received so far.
+    ///
+    /// Returns the last run control command, if any.
+    pub fn poll(&mut self, gb: &mut GameBoy) -> Option<GdbCommand> {
+        let mut command = None;
+
+        if self.stream.is_none() {
+            match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    log::info!("GDB connected from {}", addr);
+                    stream.set_nodelay(true).ok();
+                    stream.set_nonblocking(true).ok();
+                    self.stream = Some(stream);
+                    self.input.clear();
+
+                    // GDB expects the target to be stopped when attaching
+                    command = Some(GdbCommand::Halt);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return None,
+                Err(e) => {
+                    log::error!("GDB accept failed: {}", e);
+                    return None;
+                }
+            }
+        }
+
+        if let Err(e) = self.receive() {
+            self.disconnect(gb, &e.to_string());
+            return command;
+        }
+
+        while let Some(packet) = self.next_packet() {
+            let response = match packet {
+                Packet::Interrupt => Response::Resume(GdbCommand::Halt),
+                Packet::Invalid => {
+                    self.send_raw(b"-");
+                    continue;
+                }
+                Packet::Data(data) => {
+                    self.send_raw(b"+");
+                    self.handle(gb, &data)
+                }
+            };
+
+            match response {
+                Response::Reply(reply) => self.send(&reply),
+                Response::Resume(resume) => {
+                    // An interrupt is answered once the target stopped
+                    if resume != GdbCommand::Halt {
+                        self.running = true;
+                    }
+                    command = Some(resume);
+                }
+                Response::Close => {
+                    self.disconnect(gb, "detached");
+                    break;
+                }
+            }
+        }
+
+        command
+    }
+
+    /// Whether a GDB breakpoint is set at PC.
+    pub fn should_break(&self, gb: &GameBoy) -> bool {
+        self.stream.is_some() && self.breakpoints.contains(&gb.cpu().read_pc())
+    }
+
+    /// Report to GDB that execution stopped, if it was waiting for it.
+    pub fn stopped(&mut self, gb: &GameBoy) {
+        if !self.running {
+            return;
+        }
+        self.running = false;
+
+        let mut reply = format!("T{:02x}", SIGTRAP);
+
+        // GDB watchpoint hit by the last instruction
+        let cpu = gb.cpu();
+        let hit = gb
+            .bus()
+            .watchpoints()
+            .last_hit()
+            .filter(|hit| hit.pc == cpu.reg_pc_prev);
+        let watched = hit.and_then(|hit| {
+            self.watchpoints
+                .iter()
+                .find(|w| w.matches(hit.addr, hit.value, hit.access))
+                .map(|w| (w.kind, hit.addr))
+        });
+
+        if let Some((kind, addr)) = watched {
+            let name = match kind {
+                WatchKind::Write => "watch",
+                WatchKind::Read => "rwatch",
+                WatchKind::Access => "awatch",
+            };
+            reply += &format!("{}:{:04x};", name, addr);
+        }
+
+        self.send(&reply);
+    }
+
+    fn disconnect(&mut self, gb: &mut GameBoy, reason: &str) {
+        log::info!("GDB disconnected: {}", reason);
+        self.stream = None;
+        self.running = false;
+        self.breakpoints.clear();
+
+        for watchpoint in self.watchpoints.drain(..) {
+            gb.watchpoints_mut().remove(WatchOwner::Gdb, &watchpoint);
+        }
+    }
+
+    fn receive(&mut self) -> std::io::Result<()> {
+        let Some(stream) = self.stream.as_mut() else {
+            return Ok(());
+        };
+
+        let mut buffer = [0; 1024];
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(len) => self.input.extend_from_slice(&buffer[..len]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+
+            if self.input.len() > 2 * PACKET_SIZE {
+                return Err(ErrorKind::InvalidData.into());
+            }
+        }
+    }
+
+    // Extract the next packet, skipping acknowledgements
+    fn next_packet(&mut self) -> Option<Packet> {
+        loop {
+            match self.input.first()? {
+                b'$' => break,
+                &INTERRUPT => {
+                    self.input.remove(0);
+                    return Some(Packet::Interrupt);
+                }
+                _ => {
+                    self.input.remove(0);
+                }
+            }
+        }
+
+        let end = self.input.iter().position(|b| *b == b'#')?;
+        if self.input.len() < end + 3 {
+            return None;
+        }
+
+        let packet: Vec<u8> = self.input.drain(..end + 3).collect();
+        let data = &packet[1..end];
+        let checksum = std::str::from_utf8(&packet[end + 1..])
+            .ok()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        if checksum != Some(checksum_of(data)) {
+            return Some(Packet::Invalid);
+        }
+
+        Some(Packet::Data(String::from_utf8_lossy(data).into_owned()))
+    }
+
+    fn send(&mut self, data: &str) {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.send_raw(packet.as_bytes());
+    }
+
+    fn send_raw(&mut self, data: &[u8]) {
+        let Some(stream) = self.stream.as_mut() else {
+            return;
+        };
+
+        // Replies are small, a non blocking write only fails when the client is
+        // gone. The next poll then sees the closed stream and cleans up.
+        if let Err(e) = stream.write_all(data) {
+            log::error!("GDB send failed: {}", e);
+            stream.shutdown(Shutdown::Both).ok();
+        }
+    }
+
+    fn handle(&mut self, gb: &mut GameBoy, packet: &str) -> Response {
+        let reply = |data: &str| Response::Reply(data.to_string());
+        let command = packet.get(..1).unwrap_or("");
+        let args = packet.get(1..).unwrap_or("");
+
+        match command {
+            "?" => Response::Reply(format!("S{:02x}", SIGTRAP)),
+            "g" => Response::Reply(read_registers(gb)),
+            "G" => match write_registers(gb, args) {
+                Some(()) => reply("OK"),
+                None => reply("E01"),
+            },
+            "p" => match parse_hex(args).and_then(|reg| read_register(gb, reg)) {
+                Some(value) => Response::Reply(hex_word(value)),
+                None => reply("E01"),
+            },
+            "P" => {
+                let written = args.split_once('=').and_then(|(reg, value)| {
+                    write_register(gb, parse_hex(reg)?, parse_word(value)?)
+                });
+
+                match written {
+                    Some(()) => reply("OK"),
+                    None => reply("E01"),
+                }
+            }
+            "m" => match parse_range(args) {
+                Some((addr, len)) => Response::Reply(read_memory(gb, addr, len)),
+                None => reply("E01"),
+            },
+            "M" => match write_memory(gb, args) {
+                Some(()) => reply("OK"),
+                None => reply("E01"),
+            },
+            "Z" | "z" => self.set_breakpoint(gb, command == "Z", args),
+            "c" => Response::Resume(GdbCommand::Continue),
+            "s" => Response::Resume(GdbCommand::Step),
+            "H" => reply("OK"),
+            "D" => Response::Close,
+            "k" => Response::Close,
+            "q" if args.starts_with("Supported") => {
+                Response::Reply(format!("PacketSize={:x}", PACKET_SIZE))
+            }
+            "q" if args == "Attached" => reply("1"),
+            "q" if args == "C" => reply("QC1"),
+            "q" if args == "fThreadInfo" => reply("m1"),
+            "q" if args == "sThreadInfo" => reply("l"),
+            // Empty reply for unsupported packets
+            _ => reply(""),
+        }
+    }
+
+    // "type,addr,kind" of the Z and z packets
+    fn set_breakpoint(&mut self, gb: &mut GameBoy, insert: bool, args: &str) -> Response {
+        let mut fields = args.split(',');
+        let (Some(kind), Some(addr), Some(len)) = (
+            fields.next(),
+            fields.next().and_then(parse_hex),
+            fields.next().and_then(parse_hex),
+        ) else {
+            return Response::Reply("E01".to_string());
+        };
+
+        let watch_kind = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.breakpoints.insert(addr);
+                } else {
+                    self.breakpoints.remove(&addr);
+                }
+                return Response::Reply("OK".to_string());
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return Response::Reply(String::new()),
+        };
+
+        let watchpoint = Watchpoint {
+            start: addr,
+            end: addr.saturating_add(len.max(1) - 1),
+            kind: watch_kind,
+            value: None,
+        };
+
+        if insert {
+            gb.watchpoints_mut().add(WatchOwner::Gdb, watchpoint);
+            if !self.watchpoints.contains(&watchpoint) {
+                self.watchpoints.push(watchpoint);
+            }
+        } else {
+            gb.watchpoints_mut().remove(WatchOwner::Gdb, &watchpoint);
+            self.watchpoints.retain(|w| *w != watchpoint);
+        }
+
+        Response::Reply("OK".to_string())
+    }
+}
+
+enum Packet {
+    Data(String),
+    Invalid,
+    Interrupt,
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, b| sum.wrapping_add(*b))
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    u16::from_str_radix(text, 16).ok()
+}
+
+// 16 bit value in target byte order
+fn parse_word(text: &str) -> Option<u16> {
+    u16::from_str_radix(text, 16).ok().map(u16::swap_bytes)
+}
+
+fn hex_word(value: u16) -> String {
+    format!("{:04x}", value.swap_bytes())
+}
+
+// "addr,len" of the m and M packets
+fn parse_range(text: &str) -> Option<(u16, u16)> {
+    let (addr, len) = text.split_once(',')?;
+    Some((parse_hex(addr)?, parse_hex(len)?))
+}
+
+fn read_register(gb: &GameBoy, reg: u16) -> Option<u16> {
+    let cpu = gb.cpu();
+
+    match reg {
+        0 => Some(cpu.read_af()),
+        1 => Some(cpu.read_bc()),
+        2 => Some(cpu.read_de()),
+        3 => Some(cpu.read_hl()),
+        4 => Some(cpu.read_sp()),
+        5 => Some(cpu.read_pc()),
+        _ => None,
+    }
+}
+
+fn write_register(gb: &mut GameBoy, reg: u16, value: u16) -> Option<()> {
+    let cpu = gb.cpu_mut();
+
+    match reg {
+        0 => cpu.write_af(value),
+        1 => cpu.write_bc(value),
+        2 => cpu.write_de(value),
+        3 => cpu.write_hl(value),
+        4 => cpu.write_sp(value),
+        5 => cpu.write_pc(value),
+        _ => return None,
+    }
+
+    Some(())
+}
+
+fn read_registers(gb: &GameBoy) -> String {
+    (0..6)
+        .map(|reg| hex_word(read_register(gb, reg).unwrap()))
+        .collect()
+}
+
+fn write_registers(gb: &mut GameBoy, data: &str) -> Option<()> {
+    if data.len() < 6 * 4 || !data.is_ascii() {
+        return None;
+    }
+
+    for reg in 0..6 {
+        let start = reg as usize * 4;
+        write_register(gb, reg, parse_word(&data[start..start + 4])?)?;
+    }
+
+    Some(())
+}
+
+fn read_memory(gb: &GameBoy, addr: u16, len: u16) -> String {
+    (0..len)
+        .map(|i| {
+            let value = gb.bus().read_byte(addr.wrapping_add(i)).unwrap_or(0xFF);
+            format!("{:02x}", value)
+        })
+        .collect()
+}
+
+// "addr,len:data" of the M packet
+fn write_memory(gb: &mut GameBoy, args: &str) -> Option<()> {
+    let (range, data) = args.split_once(':')?;
+    let (addr, len) = parse_range(range)?;
+
+    if data.len() != 2 * len as usize || !data.is_ascii() {
+        return None;
+    }
+
+    for i in 0..len {
+        let start = 2 * i as usize;
+        let value = u8::from_str_radix(&data[start..start + 2], 16).ok()?;
+        gb.bus_mut().write_byte(addr.wrapping_add(i), value).ok()?;
+    }
+
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        time::{Duration, Instant},
+    };
+
+    use crate::gbr::{bus::BusAccess, game_boy::GameBoy};
+
+    use super::{checksum_of, GdbCommand, GdbStub, WatchKind, WatchOwner, Watchpoint};
+
+    fn game_boy() -> GameBoy {
+        GameBoy::with_program(&[
+            0x3C, // INC A
+            0xEA, 0x00, 0xC0, // LD (0xC000), A
+            0x00, // NOP
+        ])
+    }
+
+    struct Client {
+        stream: TcpStream,
+        received: Vec<u8>,
+    }
+
+    impl Client {
+        fn connect(stub: &mut GdbStub, gb: &mut GameBoy) -> Self {
+            let stream = TcpStream::connect(stub.local_addr().unwrap()).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_millis(10)))
+                .unwrap();
+
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while stub.poll(gb) != Some(GdbCommand::Halt) {
+                assert!(Instant::now() < deadline, "no connection");
+            }
+
+            Self {
+                stream,
+                received: vec![],
+            }
+        }
+
+        fn send(&mut self, packet: &str) {
+            let data = format!("${}#{:02x}", packet, checksum_of(packet.as_bytes()));
+            self.stream.write_all(data.as_bytes()).unwrap();
+        }
+
+        // Poll the stub until a reply packet arrives
+        fn reply(&mut self, stub: &mut GdbStub, gb: &mut GameBoy) -> String {
+            let deadline = Instant::now() + Duration::from_secs(5);
+
+            loop {
+                stub.poll(gb);
+
+                let mut buffer = [0; 256];
+                if let Ok(len) = self.stream.read(&mut buffer) {
+                    self.received.extend_from_slice(&buffer[..len]);
+                }
+
+                while self.received.first() == Some(&b'+') {
+                    self.received.remove(0);
+                }
+
+                if let Some(end) = self.received.iter().position(|b| *b == b'#') {
+                    if self.received.len() >= end + 3 {
+                        let packet: Vec<u8> = self.received.drain(..end + 3).collect();
+                        let data = String::from_utf8(packet[1..end].to_vec()).unwrap();
+                        return data;
+                    }
+                }
+
+                assert!(Instant::now() < deadline, "no reply");
+            }
+        }
+
+        fn exchange(&mut self, stub: &mut GdbStub, gb: &mut GameBoy, packet: &str) -> String {
+            self.send(packet);
+            self.reply(stub, gb)
+        }
+    }
+
+    fn command(stub: &mut GdbStub, gb: &mut GameBoy) -> GdbCommand {
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        loop {
+            if let Some(command) = stub.poll(gb) {
+                return command;
+            }
+            assert!(Instant::now() < deadline, "no command");
+        }
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let mut gb = game_boy();
+        let mut stub = GdbStub::listen("127.0.0.1:0").unwrap();
+        let mut client = Client::connect(&mut stub, &mut gb);
+
+        assert_eq!(
+            client.exchange(&mut stub, &mut gb, "g"),
+            "b0011300d8004d01feff5001"
+        );
+
+        assert_eq!(client.exchange(&mut stub, &mut gb, "P1=3412"), "OK");
+        assert_eq!(gb.cpu().read_bc(), 0x1234);
+        assert_eq!(client.exchange(&mut stub, &mut gb, "p5"), "5001");
+
+        assert_eq!(client.exchange(&mut stub, &mut gb, "m150,4"), "3cea00c0");
+        assert_eq!(client.exchange(&mut stub, &mut gb, "Mc010,2:abcd"), "OK");
+        assert_eq!(gb.bus().read_byte(0xC011).unwrap(), 0xCD);
+    }
+
+    #[test]
+    fn breakpoints_and_watchpoints() {
+        let mut gb = game_boy();
+        let mut stub = GdbStub::listen("127.0.0.1:0").unwrap();
+        let mut client = Client::connect(&mut stub, &mut gb);
+
+        assert_eq!(client.exchange(&mut stub, &mut gb, "Z0,151,1"), "OK");
+        client.send("c");
+        assert_eq!(command(&mut stub, &mut gb), GdbCommand::Continue);
+
+        // Run as the emulator thread does, until the stub asks to break
+        gb.step().unwrap();
+        assert!(stub.should_break(&gb));
+        stub.stopped(&gb);
+        assert_eq!(client.reply(&mut stub, &mut gb), "T05");
+
+        assert_eq!(client.exchange(&mut stub, &mut gb, "z0,151,1"), "OK");
+        assert_eq!(client.exchange(&mut stub, &mut gb, "Z2,c000,1"), "OK");
+        client.send("s");
+        assert_eq!(command(&mut stub, &mut gb), GdbCommand::Step);
+
+        gb.step().unwrap();
+        assert!(gb.take_watchpoint_hit().is_some());
+        stub.stopped(&gb);
+        assert_eq!(client.reply(&mut stub, &mut gb), "T05watch:c000;");
+    }
+
+    #[test]
+    fn access_watchpoint_reply() {
+        let mut gb = game_boy();
+        let write = Watchpoint {
+            start: 0xC000,
+            end: 0xC000,
+            kind: WatchKind::Write,
+            value: None,
+        };
+        gb.watchpoints_mut().add(WatchOwner::Debugger, write);
+
+        let mut stub = GdbStub::listen("127.0.0.1:0").unwrap();
+        let mut client = Client::connect(&mut stub, &mut gb);
+
+        // The kind of the GDB watchpoint is reported, not the debugger one
+        assert_eq!(client.exchange(&mut stub, &mut gb, "Z4,c000,1"), "OK");
+        client.send("c");
+        assert_eq!(command(&mut stub, &mut gb), GdbCommand::Continue);
+
+        gb.step().unwrap();
+        gb.step().unwrap();
+        assert!(gb.take_watchpoint_hit().is_some());
+        stub.stopped(&gb);
+        assert_eq!(client.reply(&mut stub, &mut gb), "T05awatch:c000;");
+    }
+}
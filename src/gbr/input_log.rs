@@ -0,0 +1,73 @@
+use super::game_boy::InputType;
+
+/// Input coming from outside the emulator, which running it again cannot reproduce.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExternalInput {
+    Joypad(InputType),
+    // Byte received from the link cable peer
+    Serial(u8),
+}
+
+/// External inputs applied to a `GameBoy`, with the clock cycles they were
+/// applied at.
+///
+/// After going back to a snapshot, the inputs logged after it are replayed
+/// so that running again goes through the same states.
+#[derive(Default)]
+pub struct InputLog {
+    enabled: bool,
+    inputs: Vec<(u64, ExternalInput)>,
+    // Next input to replay, None when not replaying
+    next: Option<usize>,
+}
+
+impl InputLog {
+    /// Start or stop logging, forgetting the logged inputs.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        *self = Self {
+            enabled,
+            ..Default::default()
+        };
+    }
+
+    /// Add an input applied at `cycles`. Ignored while replaying.
+    pub fn push(&mut self, cycles: u64, input: ExternalInput) {
+        if self.enabled && !self.is_replaying() {
+            self.inputs.push((cycles, input));
+        }
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.next.is_some()
+    }
+
+    /// Replay the inputs applied from `cycles` on.
+    pub fn rewind(&mut self, cycles: u64) {
+        let next = self.inputs.partition_point(|(at, _)| *at < cycles);
+        self.next = (next < self.inputs.len()).then_some(next);
+    }
+
+    /// Take the next input to replay at `cycles`.
+    pub fn next_input(&mut self, cycles: u64) -> Option<ExternalInput> {
+        let index = self.next?;
+
+        let (_, input) = self.inputs.get(index).filter(|(at, _)| *at <= cycles)?;
+        self.next = (index + 1 < self.inputs.len()).then_some(index + 1);
+
+        Some(input.clone())
+    }
+
+    /// Forget the inputs applied from `cycles` on and stop replaying.
+    pub fn truncate(&mut self, cycles: u64) {
+        let len = self.inputs.partition_point(|(at, _)| *at < cycles);
+        self.inputs.truncate(len);
+        self.next = None;
+    }
+
+    /// Forget the inputs applied before `cycles`, which cannot be replayed anymore.
+    pub fn forget_before(&mut self, cycles: u64) {
+        let len = self.inputs.partition_point(|(at, _)| *at < cycles);
+        self.inputs.drain(..len);
+        self.next = self.next.map(|next| next.saturating_sub(len));
+    }
+}
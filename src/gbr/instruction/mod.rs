@@ -8,6 +8,19 @@ use super::GbError;
 
 use self::opcode::{CbOpcode, Opcode};
 
+/// Names of addresses, shown instead of the hex operands.
+pub trait SymbolResolver {
+    fn symbol(&self, addr: u16) -> Option<&str>;
+}
+
+struct NoSymbols;
+
+impl SymbolResolver for NoSymbols {
+    fn symbol(&self, _addr: u16) -> Option<&str> {
+        None
+    }
+}
+
 #[derive(Clone)]
 pub enum InstructionType {
     Nop,
@@ -38,16 +51,37 @@ pub enum JumpType {
     RegAddr(DoubleRegType),
 }
 
-impl Display for JumpType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl JumpType {
+    // Relative jumps are resolved from `pc`, the address of the jump instruction
+    fn fmt_with(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        pc: u16,
+        symbols: &dyn SymbolResolver,
+    ) -> std::fmt::Result {
         match self {
-            Self::Offset(offset) => write!(f, "Rel({:#04X})", offset),
-            Self::Addr(addr) => write!(f, "Abs({:#06X})", addr),
+            Self::Offset(offset) => {
+                let target = pc.wrapping_add(2).wrapping_add(*offset as u16);
+                match symbols.symbol(target) {
+                    Some(name) => write!(f, "{}", name),
+                    None => write!(f, "Rel({:#04X})", offset),
+                }
+            }
+            Self::Addr(addr) => match symbols.symbol(*addr) {
+                Some(name) => write!(f, "{}", name),
+                None => write!(f, "Abs({:#06X})", addr),
+            },
             Self::RegAddr(reg) => write!(f, "Abs(Addr({}))", reg),
         }
     }
 }
 
+impl Display for JumpType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_with(f, 0, &NoSymbols)
+    }
+}
+
 #[derive(Clone)]
 pub enum ArithmeticType {
     Inc(GenericRegType),
@@ -166,6 +200,22 @@ pub enum Source {
     SpWithOffset(i8),
 }
 
+impl Source {
+    fn fmt_with(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        symbols: &dyn SymbolResolver,
+    ) -> std::fmt::Result {
+        match self {
+            Self::Addr(addr) => match symbols.symbol(*addr) {
+                Some(name) => write!(f, "Addr({})", name),
+                None => write!(f, "Addr({:#06X})", addr),
+            },
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
 impl Display for Source {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -189,6 +239,22 @@ pub enum Dest {
     IoPortReg(SingleRegType),
 }
 
+impl Dest {
+    fn fmt_with(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        symbols: &dyn SymbolResolver,
+    ) -> std::fmt::Result {
+        match self {
+            Self::Addr(addr) => match symbols.symbol(*addr) {
+                Some(name) => write!(f, "{}", name),
+                None => write!(f, "{:#06X}", addr),
+            },
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
 impl Display for Dest {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -875,8 +941,22 @@ impl Instruction {
     }
 }
 
-impl Display for Instruction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Instruction {
+    /// Display the instruction at `pc` with the addresses named by `symbols`.
+    pub fn display<'a>(&'a self, pc: u16, symbols: &'a dyn SymbolResolver) -> impl Display + 'a {
+        WithSymbols {
+            instr: self,
+            pc,
+            symbols,
+        }
+    }
+
+    fn fmt_with(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        pc: u16,
+        symbols: &dyn SymbolResolver,
+    ) -> std::fmt::Result {
         use InstructionType::*;
 
         write!(f, "({:#04X}) ", self.opcode as u8)?;
@@ -888,22 +968,58 @@ impl Display for Instruction {
             SetCarry => write!(f, "Set CY"),
             MasterInterrupt(enable) => write!(f, "IME {}", enable),
             Arithmetic(ar_type) => write!(f, "{}", ar_type),
-            Jump(cond, jump_type) => write!(f, "J {}, {}", cond, jump_type),
-            Load(reg, source) => write!(f, "Load {} {}", reg, source),
+            Jump(cond, jump_type) => {
+                write!(f, "J {}, ", cond)?;
+                jump_type.fmt_with(f, pc, symbols)
+            }
+            Load(reg, source) => {
+                write!(f, "Load {} ", reg)?;
+                source.fmt_with(f, symbols)
+            }
             LoadWithOp(reg, source, post_load) => {
-                write!(f, "Load {} {} {}", reg, source, post_load)
+                write!(f, "Load {} ", reg)?;
+                source.fmt_with(f, symbols)?;
+                write!(f, " {}", post_load)
+            }
+            Store(dest, reg) => {
+                write!(f, "Store ")?;
+                dest.fmt_with(f, symbols)?;
+                write!(f, " ")?;
+                reg.fmt_with(f, symbols)
             }
-            Store(dest, reg) => write!(f, "Store {} {}", dest, reg),
             LoadSP(src) => write!(f, "SP = {}", src),
             StoreWithOp(dest, reg, op) => {
-                write!(f, "Store {} {} {}", dest, reg, op)
+                write!(f, "Store ")?;
+                dest.fmt_with(f, symbols)?;
+                write!(f, " {} {}", reg, op)
             }
             StoreSP(addr) => write!(f, "Store SP {}", addr),
             Push(reg) => write!(f, "Push {}", reg),
             Pop(reg) => write!(f, "Pop {}", reg),
-            Call(addr, cond) => write!(f, "Call {}, {:#06X}", cond, addr),
+            Call(addr, cond) => match symbols.symbol(*addr) {
+                Some(name) => write!(f, "Call {}, {}", cond, name),
+                None => write!(f, "Call {}, {:#06X}", cond, addr),
+            },
             Ret(cond) => write!(f, "Ret {}", cond),
             RetI => write!(f, "RetI"),
         }
     }
 }
+
+struct WithSymbols<'a> {
+    instr: &'a Instruction,
+    pc: u16,
+    symbols: &'a dyn SymbolResolver,
+}
+
+impl Display for WithSymbols<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.instr.fmt_with(f, self.pc, self.symbols)
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_with(f, 0, &NoSymbols)
+    }
+}
@@ -260,6 +260,33 @@ pub enum Opcode {
 }
 
 impl Opcode {
+    /// Whether the instruction pushes a return address, that is CALL or RST.
+    pub fn is_call(&self) -> bool {
+        matches!(
+            self,
+            Self::Call
+                | Self::CallZ
+                | Self::CallNZ
+                | Self::CallC
+                | Self::CallNC
+                | Self::Rst0
+                | Self::Rst1
+                | Self::Rst2
+                | Self::Rst3
+                | Self::Rst4
+                | Self::Rst5
+                | Self::Rst6
+                | Self::Rst7
+        )
+    }
+
+    pub fn is_return(&self) -> bool {
+        matches!(
+            self,
+            Self::Ret | Self::RetZ | Self::RetNZ | Self::RetC | Self::RetNC | Self::RetI
+        )
+    }
+
     pub fn length(&self) -> u8 {
         match self {
             Self::LdAd8
@@ -287,6 +314,7 @@ impl Opcode {
             | Self::JrNZ
             | Self::JrC
             | Self::JrNC
+            | Self::Stop
             | Self::Prefix => 2,
 
             Self::LdBCd16
@@ -728,10 +756,22 @@ impl CbOpcode {
 
 #[cfg(test)]
 mod tests {
+    use super::Opcode;
 
     #[test]
     fn jump_macro() {
         assert_eq!(jumped!(true, 4, 3), 4);
         assert_eq!(jumped!(false, 4, 3), 3)
     }
+
+    #[test]
+    fn call_and_return() {
+        assert!(Opcode::CallNZ.is_call());
+        assert!(Opcode::Rst7.is_call());
+        assert!(!Opcode::Jp.is_call());
+
+        assert!(Opcode::RetI.is_return());
+        assert!(Opcode::RetC.is_return());
+        assert!(!Opcode::PopAF.is_return());
+    }
 }
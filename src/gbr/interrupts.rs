@@ -4,6 +4,7 @@ pub struct Interrupt {
     pub set: bool,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum InterruptType {
     VBlank,
     LcdStat,
@@ -93,6 +94,21 @@ impl InterruptHandler {
         }
     }
 
+    /// Returns the pending interrupt with the highest priority, if any.
+    ///
+    /// Priority follows the bit order of IF/IE: VBlank first, Joypad last.
+    pub fn highest_priority_pending(&self) -> Option<InterruptType> {
+        [
+            InterruptType::VBlank,
+            InterruptType::LcdStat,
+            InterruptType::Timer,
+            InterruptType::Serial,
+            InterruptType::Joypad,
+        ]
+        .into_iter()
+        .find(|ir| self.test(*ir))
+    }
+
     pub fn any_pending_interrupt(&self) -> bool {
         self.test(InterruptType::VBlank)
             || self.test(InterruptType::LcdStat)
@@ -111,3 +127,61 @@ impl InterruptHandler {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{InterruptHandler, InterruptType};
+
+    #[test]
+    fn no_pending_interrupt() {
+        let mut ir_handler = InterruptHandler::default();
+
+        ir_handler.write_if(0b00011111);
+
+        assert!(!ir_handler.any_pending_interrupt());
+        assert_eq!(ir_handler.highest_priority_pending(), None);
+    }
+
+    #[test]
+    fn simultaneous_interrupts_priority() {
+        let mut ir_handler = InterruptHandler::default();
+
+        ir_handler.write_ie(0b00011111);
+        ir_handler.write_if(0b00011111);
+
+        let expected = [
+            InterruptType::VBlank,
+            InterruptType::LcdStat,
+            InterruptType::Timer,
+            InterruptType::Serial,
+            InterruptType::Joypad,
+        ];
+
+        for ir in expected {
+            assert_eq!(ir_handler.highest_priority_pending(), Some(ir));
+            ir_handler.clear(ir);
+        }
+
+        assert_eq!(ir_handler.highest_priority_pending(), None);
+    }
+
+    #[test]
+    fn disabled_interrupts_are_skipped() {
+        let mut ir_handler = InterruptHandler::default();
+
+        ir_handler.write_ie(0b00010100);
+        ir_handler.write_if(0b00011011);
+
+        assert_eq!(
+            ir_handler.highest_priority_pending(),
+            Some(InterruptType::Joypad)
+        );
+
+        ir_handler.set(InterruptType::Timer);
+
+        assert_eq!(
+            ir_handler.highest_priority_pending(),
+            Some(InterruptType::Timer)
+        );
+    }
+}
@@ -26,6 +26,8 @@ pub struct Joypad {
     select_directions: bool,
     buttons: Buttons,
     directions: Directions,
+    // Set when a selected input line goes from high to low
+    interrupt: bool,
 }
 
 impl Default for Joypad {
@@ -35,34 +37,58 @@ impl Default for Joypad {
             select_directions: true,
             buttons: Buttons::empty(),
             directions: Directions::empty(),
+            interrupt: false,
         }
     }
 }
 
 impl Joypad {
     pub fn write(&mut self, value: u8) {
-        self.select_buttons = (value & 0b00100000) == 0;
-        self.select_directions = (value & 0b00010000) == 0;
+        self.update_lines(|joypad| {
+            joypad.select_buttons = (value & 0b00100000) == 0;
+            joypad.select_directions = (value & 0b00010000) == 0;
+        });
     }
 
     pub fn read(&self) -> u8 {
         // Need to invert bits because in register logic 0 means selected and
-        // 1 not selected
+        // 1 not selected. With both groups selected their lines are ANDed.
+        let select = (!self.select_buttons as u8) << 5 | (!self.select_directions as u8) << 4;
+        0xC0 | select | (!self.lines() & 0x0F)
+    }
+
+    // Input lines pulled low by the selected groups, 1 means low
+    fn lines(&self) -> u8 {
+        let mut lines = 0;
         if self.select_buttons {
-            0x10 | self.buttons.complement().bits()
-        } else if self.select_directions {
-            0x20 | self.directions.complement().bits()
-        } else {
-            0x3F
+            lines |= self.buttons.bits();
+        }
+        if self.select_directions {
+            lines |= self.directions.bits();
+        }
+        lines
+    }
+
+    fn update_lines(&mut self, update: impl FnOnce(&mut Self)) {
+        let before = self.lines();
+        update(self);
+
+        if self.lines() & !before != 0 {
+            self.interrupt = true;
         }
     }
 
+    /// Returns whether a joypad interrupt was raised since the last call.
+    pub fn take_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.interrupt)
+    }
+
     pub fn press_button(&mut self, button: Buttons) {
-        self.buttons.set(button, true);
+        self.update_lines(|joypad| joypad.buttons.set(button, true));
     }
 
     pub fn press_direction(&mut self, direction: Directions) {
-        self.directions.set(direction, true);
+        self.update_lines(|joypad| joypad.directions.set(direction, true));
     }
 
     pub fn release_button(&mut self, button: Buttons) {
@@ -73,3 +99,50 @@ impl Joypad {
         self.directions.set(direction, false);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Buttons, Directions, Joypad};
+
+    #[test]
+    fn matrix_read() {
+        let mut joypad = Joypad::default();
+        joypad.press_button(Buttons::A);
+        joypad.press_direction(Directions::Left);
+
+        joypad.write(0x10);
+        assert_eq!(joypad.read(), 0xDE);
+
+        joypad.write(0x20);
+        assert_eq!(joypad.read(), 0xED);
+
+        // Both groups selected
+        joypad.write(0x00);
+        assert_eq!(joypad.read(), 0xCC);
+
+        joypad.write(0x30);
+        assert_eq!(joypad.read(), 0xFF);
+    }
+
+    #[test]
+    fn interrupt_on_high_to_low() {
+        let mut joypad = Joypad::default();
+        joypad.write(0x20);
+
+        // Buttons are not selected
+        joypad.press_button(Buttons::Start);
+        assert!(!joypad.take_interrupt());
+
+        joypad.press_direction(Directions::Up);
+        assert!(joypad.take_interrupt());
+        assert!(!joypad.take_interrupt());
+
+        // Selecting the buttons pulls the Start line low
+        joypad.write(0x00);
+        assert!(joypad.take_interrupt());
+
+        joypad.release_direction(Directions::Up);
+        joypad.release_button(Buttons::Start);
+        assert!(!joypad.take_interrupt());
+    }
+}
@@ -0,0 +1,374 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use super::GbError;
+
+// How long the clock master waits for the peer to answer a transfer, the
+// emulation keeps running meanwhile. The slave drops a byte from the master
+// which waited that long for its transfer to start.
+const EXCHANGE_TIMEOUT: Duration = Duration::from_millis(500);
+// The slave reads the socket at most once per bit period, 8192 Hz
+const POLL_INTERVAL: Duration = Duration::from_micros(122);
+
+// Value shifted in when no peer drives the serial input line
+pub const DISCONNECTED_BYTE: u8 = 0xFF;
+
+/// Other end of the link cable.
+///
+/// The side using the internal clock is the master: it calls `send` when a
+/// transfer starts, then `answer` until the peer byte is there. The side using
+/// the external clock is the slave: it calls `poll` to check whether the
+/// master clocked a byte in.
+///
+/// No method blocks, the emulation keeps running while waiting for the peer.
+pub trait SerialPeer: Send + Sync {
+    /// Start a transfer sending `data` to the peer.
+    fn send(&mut self, data: u8);
+
+    /// Byte shifted in from the peer for the last `send`, None until it arrived.
+    fn answer(&mut self) -> Option<u8>;
+
+    /// If the peer started a transfer, answer with `data` and return the byte
+    /// received from the peer.
+    fn poll(&mut self, data: u8) -> Option<u8>;
+
+    /// The Game Boy on this side was reset.
+    fn reset(&mut self) {}
+
+    /// Called while no transfer is enabled on this side. A byte from the peer
+    /// is kept for the next transfer enabled here, until the peer gave up on it.
+    fn idle(&mut self) {}
+}
+
+/// Serial output wired back to its own input.
+#[derive(Default)]
+pub struct LoopbackPeer {
+    sent: Option<u8>,
+}
+
+impl SerialPeer for LoopbackPeer {
+    fn send(&mut self, data: u8) {
+        self.sent = Some(data);
+    }
+
+    fn answer(&mut self) -> Option<u8> {
+        self.sent.take()
+    }
+
+    fn poll(&mut self, _data: u8) -> Option<u8> {
+        // Nobody else can drive the clock
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FrameKind {
+    Transfer = 0,
+    Answer = 1,
+}
+
+// Kind, sequence number and data byte
+const FRAME_SIZE: usize = 3;
+
+/// Link cable to another emulator instance over a TCP connection.
+///
+/// Each transfer is a frame sent by the master and a frame answered by the
+/// slave, both tagged with the sequence number of the transfer: an answer
+/// arriving after the master gave up on it is dropped instead of being taken
+/// for the answer to the next transfer. The socket is non-blocking.
+pub struct TcpPeer {
+    listener: Option<TcpListener>,
+    stream: Option<TcpStream>,
+    // Sequence number of the last transfer started on this side
+    sequence: u8,
+    // When the byte waiting for an answer was sent
+    sent: Option<Instant>,
+    answer: Option<u8>,
+    // Transfer started by the peer with its sequence number and data, and when
+    // it arrived
+    pending: Option<(u8, u8, Instant)>,
+    // Bytes of a frame not completely received yet
+    partial: Vec<u8>,
+    last_poll: Instant,
+}
+
+impl TcpPeer {
+    /// Wait for the other instance to connect on `addr`.
+    ///
+    /// Does not block, the connection is accepted on first use.
+    pub fn listen<A: ToSocketAddrs>(addr: A) -> Result<Self, GbError> {
+        let listener = TcpListener::bind(addr).map_err(|e| GbError::Link(e.to_string()))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| GbError::Link(e.to_string()))?;
+
+        Ok(Self {
+            listener: Some(listener),
+            stream: None,
+            sequence: 0,
+            sent: None,
+            answer: None,
+            pending: None,
+            partial: Vec::new(),
+            last_poll: Instant::now(),
+        })
+    }
+
+    /// Connect to an instance listening on `addr`.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self, GbError> {
+        let stream = TcpStream::connect(addr).map_err(|e| GbError::Link(e.to_string()))?;
+        stream
+            .set_nodelay(true)
+            .and_then(|_| stream.set_nonblocking(true))
+            .map_err(|e| GbError::Link(e.to_string()))?;
+
+        Ok(Self {
+            listener: None,
+            stream: Some(stream),
+            sequence: 0,
+            sent: None,
+            answer: None,
+            pending: None,
+            partial: Vec::new(),
+            last_poll: Instant::now(),
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<std::net::SocketAddr> {
+        match (&self.listener, &self.stream) {
+            (Some(listener), _) => listener.local_addr().ok(),
+            (None, Some(stream)) => stream.local_addr().ok(),
+            _ => None,
+        }
+    }
+
+    fn stream(&mut self) -> Option<&mut TcpStream> {
+        if self.stream.is_none() {
+            if let Some(listener) = &self.listener {
+                match listener.accept() {
+                    Ok((stream, addr)) => {
+                        log::info!("Link cable connected to {}", addr);
+                        stream.set_nonblocking(true).ok();
+                        stream.set_nodelay(true).ok();
+                        self.stream = Some(stream);
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => (),
+                    Err(e) => log::error!("Link cable accept failed: {}", e),
+                }
+            }
+        }
+
+        self.stream.as_mut()
+    }
+
+    fn disconnect(&mut self, e: std::io::Error) {
+        log::warn!("Link cable disconnected: {}", e);
+        self.stream = None;
+        self.partial.clear();
+    }
+
+    fn write_frame(&mut self, kind: FrameKind, sequence: u8, data: u8) -> bool {
+        let Some(stream) = self.stream() else {
+            return false;
+        };
+
+        match stream.write_all(&[kind as u8, sequence, data]) {
+            Ok(()) => true,
+            Err(e) => {
+                self.disconnect(e);
+                false
+            }
+        }
+    }
+
+    // Read the frames received so far, keeping the answer to the transfer
+    // waiting on this side and the latest transfer started by the peer
+    fn receive(&mut self) {
+        let mut buf = [0; 64];
+
+        loop {
+            let Some(stream) = self.stream() else {
+                return;
+            };
+
+            match stream.read(&mut buf) {
+                Ok(0) => return self.disconnect(ErrorKind::UnexpectedEof.into()),
+                Ok(len) => self.partial.extend_from_slice(&buf[..len]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return self.disconnect(e),
+            }
+        }
+
+        let frames = self.partial.len() / FRAME_SIZE * FRAME_SIZE;
+        let received: Vec<u8> = self.partial.drain(..frames).collect();
+
+        for frame in received.chunks_exact(FRAME_SIZE) {
+            let (sequence, data) = (frame[1], frame[2]);
+
+            if frame[0] == FrameKind::Transfer as u8 {
+                self.pending = Some((sequence, data, Instant::now()));
+            } else if self.sent.is_some() && sequence == self.sequence {
+                self.answer = Some(data);
+            } else {
+                log::debug!("Link cable dropped a late answer {:#04X}", data);
+            }
+        }
+
+        // The peer gave up on its transfer
+        if let Some((_, _, arrived)) = self.pending {
+            if arrived.elapsed() >= EXCHANGE_TIMEOUT {
+                self.pending = None;
+            }
+        }
+    }
+
+    fn poll_due(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+        true
+    }
+}
+
+impl SerialPeer for TcpPeer {
+    fn send(&mut self, data: u8) {
+        self.sequence = self.sequence.wrapping_add(1);
+        self.answer = None;
+
+        if self.write_frame(FrameKind::Transfer, self.sequence, data) {
+            self.sent = Some(Instant::now());
+        }
+    }
+
+    fn answer(&mut self) -> Option<u8> {
+        // Not connected when the transfer started, or disconnected since
+        let Some(sent) = self.sent else {
+            return Some(DISCONNECTED_BYTE);
+        };
+
+        self.receive();
+
+        let answer = match self.answer.take() {
+            Some(answer) => answer,
+            None if self.stream.is_none() => DISCONNECTED_BYTE,
+            None if sent.elapsed() < EXCHANGE_TIMEOUT => return None,
+            None => {
+                log::warn!("Link cable peer did not answer");
+                DISCONNECTED_BYTE
+            }
+        };
+
+        self.sent = None;
+        Some(answer)
+    }
+
+    fn poll(&mut self, data: u8) -> Option<u8> {
+        if !self.poll_due() {
+            return None;
+        }
+
+        self.receive();
+
+        let (sequence, received, _) = self.pending.take()?;
+        self.write_frame(FrameKind::Answer, sequence, data)
+            .then_some(received)
+    }
+
+    fn idle(&mut self) {
+        if self.poll_due() {
+            self.receive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        LoopbackPeer, SerialPeer, TcpPeer, DISCONNECTED_BYTE, EXCHANGE_TIMEOUT, POLL_INTERVAL,
+    };
+
+    #[test]
+    fn loopback() {
+        let mut peer = LoopbackPeer::default();
+
+        peer.send(0xA5);
+        assert_eq!(peer.answer(), Some(0xA5));
+        assert_eq!(peer.poll(0xA5), None);
+    }
+
+    #[test]
+    fn tcp_exchange() {
+        let mut slave = TcpPeer::listen("127.0.0.1:0").unwrap();
+        let mut master = TcpPeer::connect(slave.local_addr().unwrap()).unwrap();
+
+        master.send(0x12);
+        // Waiting for the slave does not block
+        assert_eq!(master.answer(), None);
+
+        // The byte is kept until the slave enables its transfer
+        for _ in 0..10 {
+            slave.idle();
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        let received = loop {
+            if let Some(byte) = slave.poll(0x34) {
+                break byte;
+            }
+        };
+        let answer = loop {
+            if let Some(byte) = master.answer() {
+                break byte;
+            }
+        };
+
+        assert_eq!(received, 0x12);
+        assert_eq!(answer, 0x34);
+    }
+
+    #[test]
+    fn tcp_late_answer() {
+        let mut slave = TcpPeer::listen("127.0.0.1:0").unwrap();
+        let mut master = TcpPeer::connect(slave.local_addr().unwrap()).unwrap();
+
+        // The slave starts its transfer once the master gave up
+        master.send(0x12);
+        std::thread::sleep(EXCHANGE_TIMEOUT);
+        assert_eq!(master.answer(), Some(DISCONNECTED_BYTE));
+        let received = loop {
+            if let Some(byte) = slave.poll(0x34) {
+                break byte;
+            }
+        };
+        assert_eq!(received, 0x12);
+
+        // Its late answer is not taken for the next one
+        master.send(0x56);
+        let received = loop {
+            if let Some(byte) = slave.poll(0x78) {
+                break byte;
+            }
+        };
+        let answer = loop {
+            if let Some(byte) = master.answer() {
+                break byte;
+            }
+        };
+
+        assert_eq!(received, 0x56);
+        assert_eq!(answer, 0x78);
+    }
+
+    #[test]
+    fn tcp_not_connected() {
+        let mut peer = TcpPeer::listen("127.0.0.1:0").unwrap();
+
+        peer.send(0x12);
+        assert_eq!(peer.answer(), Some(DISCONNECTED_BYTE));
+        assert_eq!(peer.poll(0x12), None);
+    }
+}
@@ -0,0 +1,362 @@
+use std::sync::{Arc, Mutex};
+
+use super::{
+    game_boy::GameBoy,
+    link::{SerialPeer, DISCONNECTED_BYTE},
+    GbError,
+};
+
+const CYCLES_PER_BIT: u64 = 512;
+const CYCLES_PER_BYTE: u64 = 8 * CYCLES_PER_BIT;
+
+#[derive(Default)]
+struct Port {
+    // Data of a transfer enabled with the external clock, and when it was enabled
+    ready: Option<(u8, u64)>,
+    // Byte clocked in by the master and when its last bit is shifted
+    received: Option<(u8, u64)>,
+    // Byte sent by this side as master and when, waiting for the other side's clock
+    sent: Option<(u8, u64)>,
+    answer: Option<u8>,
+}
+
+// Clock cycles of each side, at the start of the instruction being executed
+#[derive(Default)]
+struct Link {
+    clocks: [u64; 2],
+    ports: [Port; 2],
+}
+
+impl Link {
+    // Settle the transfer started by `side` once the other side's clock
+    // reached its first clock edge, whatever the order the two sides are
+    // stepped in
+    fn resolve(&mut self, side: usize) {
+        let other = 1 - side;
+
+        let Some((data, sent_at)) = self.ports[side].sent else {
+            return;
+        };
+        let first_edge = sent_at + CYCLES_PER_BIT;
+        if self.clocks[other] < first_edge {
+            return;
+        }
+
+        // The other side gets the whole byte if its transfer was enabled
+        // before the first bit was shifted
+        let answer = match self.ports[other].ready {
+            Some((other_data, ready_at)) if ready_at < first_edge => {
+                self.ports[other].ready = None;
+                self.ports[other].received = Some((data, sent_at + CYCLES_PER_BYTE));
+                other_data
+            }
+            _ => DISCONNECTED_BYTE,
+        };
+
+        self.ports[side].sent = None;
+        self.ports[side].answer = Some(answer);
+    }
+}
+
+/// One end of an in-memory link cable between two emulators in the same process.
+///
+/// Transfers are sequenced with the clocks of both sides: a master transfer
+/// waits for the other side to reach its first clock edge, then exchanges
+/// with it if its transfer was enabled by then. The slave byte is complete
+/// when the master shifted the 8 bits.
+pub struct MemoryPeer {
+    link: Arc<Mutex<Link>>,
+    side: usize,
+}
+
+impl MemoryPeer {
+    pub fn pair() -> (MemoryPeer, MemoryPeer) {
+        let link = Arc::new(Mutex::new(Link::default()));
+
+        (
+            MemoryPeer {
+                link: link.clone(),
+                side: 0,
+            },
+            MemoryPeer { link, side: 1 },
+        )
+    }
+
+    /// Set the clock of this side, before stepping it.
+    pub fn set_clock(&self, cycles: u64) {
+        self.link.lock().unwrap().clocks[self.side] = cycles;
+    }
+
+    fn clone_end(&self) -> MemoryPeer {
+        MemoryPeer {
+            link: self.link.clone(),
+            side: self.side,
+        }
+    }
+}
+
+impl SerialPeer for MemoryPeer {
+    fn send(&mut self, data: u8) {
+        let mut link = self.link.lock().unwrap();
+        let clock = link.clocks[self.side];
+
+        link.ports[self.side].sent = Some((data, clock));
+        link.resolve(self.side);
+    }
+
+    fn answer(&mut self) -> Option<u8> {
+        let mut link = self.link.lock().unwrap();
+
+        link.resolve(self.side);
+        link.ports[self.side].answer.take()
+    }
+
+    fn poll(&mut self, data: u8) -> Option<u8> {
+        let mut link = self.link.lock().unwrap();
+        let clock = link.clocks[self.side];
+
+        let port = &mut link.ports[self.side];
+        if port.received.is_none() {
+            let ready_at = port.ready.map_or(clock, |(_, at)| at);
+            port.ready = Some((data, ready_at));
+        }
+
+        link.resolve(1 - self.side);
+
+        let port = &mut link.ports[self.side];
+        match port.received {
+            Some((byte, done_at)) if clock >= done_at => {
+                port.received = None;
+                Some(byte)
+            }
+            _ => None,
+        }
+    }
+
+    fn idle(&mut self) {
+        let mut link = self.link.lock().unwrap();
+        let port = &mut link.ports[self.side];
+
+        port.ready = None;
+        port.received = None;
+    }
+}
+
+/// Two Game Boys in the same process connected by an in-memory link cable.
+///
+/// The one behind in clock cycles is always stepped first, so a run is fully
+/// deterministic. Serial transfers are synchronized on the cycle they start
+/// and end, not on instruction boundaries.
+pub struct LinkedPair {
+    gbs: [GameBoy; 2],
+    peers: [MemoryPeer; 2],
+}
+
+impl LinkedPair {
+    pub fn new(mut first: GameBoy, mut second: GameBoy) -> Self {
+        let (first_peer, second_peer) = MemoryPeer::pair();
+        let peers = [first_peer.clone_end(), second_peer.clone_end()];
+
+        first.set_serial_peer(Some(Box::new(first_peer)));
+        second.set_serial_peer(Some(Box::new(second_peer)));
+
+        Self {
+            gbs: [first, second],
+            peers,
+        }
+    }
+
+    pub fn first(&self) -> &GameBoy {
+        &self.gbs[0]
+    }
+
+    pub fn second(&self) -> &GameBoy {
+        &self.gbs[1]
+    }
+
+    /// Run a single instruction on the Game Boy which is behind.
+    ///
+    /// Returns the index of the stepped Game Boy and whether a vblank
+    /// interrupt was triggered on it.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `GbError` if an error occurs during the step.
+    ///
+    pub fn step(&mut self) -> Result<(usize, bool), GbError> {
+        let index = if self.gbs[1].cycles() < self.gbs[0].cycles() {
+            1
+        } else {
+            0
+        };
+
+        self.peers[index].set_clock(self.gbs[index].cycles());
+        let vblank = self.gbs[index].step()?;
+
+        Ok((index, vblank))
+    }
+
+    /// Run both Game Boys until each of them executed at least `cycles` clock cycles.
+    pub fn run_cycles(&mut self, cycles: u64) -> Result<(), GbError> {
+        let target = [self.gbs[0].cycles() + cycles, self.gbs[1].cycles() + cycles];
+
+        while self.gbs[0].cycles() < target[0] || self.gbs[1].cycles() < target[1] {
+            self.step()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gbr::{
+        bus::BusAccess,
+        game_boy::GameBoy,
+        link::{SerialPeer, DISCONNECTED_BYTE},
+    };
+
+    use super::{LinkedPair, MemoryPeer, CYCLES_PER_BIT, CYCLES_PER_BYTE};
+
+    const ROM_SIZE: usize = 0x8000;
+    const ENTRY_POINT: usize = 0x0100;
+
+    fn rom(program: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; ROM_SIZE];
+        rom[ENTRY_POINT..ENTRY_POINT + program.len()].copy_from_slice(program);
+        rom
+    }
+
+    // Wait `nops` instructions, send `data` over the serial port and store the
+    // received byte at 0xC000
+    fn transfer_program(data: u8, internal_clock: bool, nops: usize) -> Vec<u8> {
+        let mut program = vec![0x00; nops];
+
+        program.extend_from_slice(&[
+            0x3E,
+            data, // LD A, data
+            0xE0,
+            0x01, // LDH (SB), A
+            0x3E,
+            0x80 | internal_clock as u8, // LD A, SC
+            0xE0,
+            0x02, // LDH (SC), A
+            0xF0,
+            0x02, // LDH A, (SC)
+            0xE6,
+            0x80, // AND 0x80
+            0x20,
+            0xFA, // JR NZ, -6
+            0xF0,
+            0x01, // LDH A, (SB)
+            0xEA,
+            0x00,
+            0xC0, // LD (0xC000), A
+            0x18,
+            0xFE, // JR -2
+        ]);
+
+        rom(&program)
+    }
+
+    // Run until both sides completed their transfer, returning the received
+    // bytes and when each transfer completed
+    fn run_transfer(master_nops: usize, slave_nops: usize) -> ([u8; 2], [u64; 2]) {
+        let mut pair = LinkedPair::new(
+            GameBoy::from_rom(&transfer_program(0x42, true, master_nops)).unwrap(),
+            GameBoy::from_rom(&transfer_program(0x24, false, slave_nops)).unwrap(),
+        );
+
+        let mut done = [None; 2];
+        while done.iter().any(|d| d.is_none()) && pair.first().cycles() < 100_000 {
+            let (index, _) = pair.step().unwrap();
+            let gb = &pair.gbs[index];
+
+            // Serial interrupt requested
+            if done[index].is_none() && gb.bus().read_byte(0xFF0F).unwrap() & 0x08 != 0 {
+                done[index] = Some(gb.cycles());
+            }
+        }
+        pair.run_cycles(1000).unwrap();
+
+        (
+            [
+                pair.first().bus().read_byte(0xC000).unwrap(),
+                pair.second().bus().read_byte(0xC000).unwrap(),
+            ],
+            [done[0].unwrap_or(0), done[1].unwrap_or(0)],
+        )
+    }
+
+    #[test]
+    fn memory_exchange() {
+        let (mut master, mut slave) = MemoryPeer::pair();
+
+        // The master waits for the slave clock to reach its first clock edge,
+        // the slave has not enabled a transfer by then
+        master.send(0x12);
+        assert_eq!(master.answer(), None);
+        slave.set_clock(CYCLES_PER_BIT);
+        assert_eq!(master.answer(), Some(DISCONNECTED_BYTE));
+
+        // Enabled after the master started its transfer, before the first bit
+        master.set_clock(CYCLES_PER_BYTE);
+        master.send(0x12);
+        slave.set_clock(CYCLES_PER_BYTE + 4);
+        assert_eq!(slave.poll(0x34), None);
+        assert_eq!(master.answer(), None);
+        slave.set_clock(CYCLES_PER_BYTE + CYCLES_PER_BIT);
+        assert_eq!(slave.poll(0x34), None);
+        assert_eq!(master.answer(), Some(0x34));
+
+        // Received once the master shifted the 8 bits
+        slave.set_clock(2 * CYCLES_PER_BYTE);
+        assert_eq!(slave.poll(0x34), Some(0x12));
+    }
+
+    #[test]
+    fn lock_step() {
+        // JR -2, 12 cycles
+        let idle = rom(&[0x18, 0xFE]);
+        let mut pair = LinkedPair::new(
+            GameBoy::from_rom(&idle).unwrap(),
+            GameBoy::from_rom(&idle).unwrap(),
+        );
+
+        for _ in 0..1000 {
+            pair.step().unwrap();
+
+            let (first, second) = (pair.first().cycles(), pair.second().cycles());
+            assert!(first.abs_diff(second) <= 12);
+        }
+    }
+
+    #[test]
+    fn serial_transfer() {
+        // Both sides enable their transfer on the same cycle
+        let (received, done) = run_transfer(0, 0);
+
+        assert_eq!(received, [0x24, 0x42]);
+        // Both bytes are complete one instruction apart at most
+        assert!(done[0].abs_diff(done[1]) <= 12);
+    }
+
+    #[test]
+    fn slave_enabled_after_master() {
+        // The slave enables its transfer a NOP later, before the first bit is
+        // shifted, and still gets the byte
+        let (received, done) = run_transfer(0, 1);
+        assert_eq!(received, [0x24, 0x42]);
+        // Both bytes are complete on the cycle the master shifted the last
+        // bit, seen at the next instruction at most
+        assert!(done[0].abs_diff(done[1]) <= 24, "{:?}", done);
+
+        // A NOP earlier is fine
+        let (received, _) = run_transfer(1, 0);
+        assert_eq!(received, [0x24, 0x42]);
+
+        // Enabled after the first bit, the master shifts in all ones
+        let (received, _) = run_transfer(0, 200);
+        assert_eq!(received[0], DISCONNECTED_BYTE);
+    }
+}
@@ -1,12 +1,12 @@
-use std::path::Path;
+use std::{path::Path, sync::Arc};
 
 use byteorder::{ByteOrder, LittleEndian};
 
 use super::{
     cart_header::{CartHeader, MapperType},
     memory_map::{
-        CART_RAM_END, CART_RAM_START, CART_ROM_ACTIVE_BANK_END, CART_ROM_ACTIVE_BANK_START,
-        CART_ROM_BANK0_END, CART_ROM_BANK0_START,
+        BankedAddress, CART_RAM_END, CART_RAM_START, CART_ROM_ACTIVE_BANK_END,
+        CART_ROM_ACTIVE_BANK_START, CART_ROM_BANK0_END, CART_ROM_BANK0_START,
     },
     GbError,
 };
@@ -46,10 +46,11 @@ pub enum BankingMode {
     Advanced,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct MBC {
     mbc_type: MapperType,
-    rom: Box<[u8]>,
+    // Shared, the ROM is never written and snapshots need not copy it
+    rom: Arc<[u8]>,
     ram: Box<[u8]>,
     rom_banks_count: u16,
     ram_banks_count: u16,
@@ -64,7 +65,11 @@ impl MBC {
         let rom = std::fs::read(rom_path)
             .map_err(|e| GbError::HeaderParsing(format!("failed to parse rom {}", e)))?;
 
-        let header = CartHeader::parse(&rom)?;
+        MBC::from_rom(&rom)
+    }
+
+    pub fn from_rom(rom: &[u8]) -> Result<MBC, GbError> {
+        let header = CartHeader::parse(rom)?;
 
         log::info!("{:#?} ", header);
 
@@ -75,11 +80,11 @@ impl MBC {
         }
 
         let mut mbc_rom = vec![0; ROM_BANK_SIZE * header.rom_banks() as usize].into_boxed_slice();
-        mbc_rom.copy_from_slice(&rom);
+        mbc_rom.copy_from_slice(rom);
 
         Ok(Self {
             mbc_type: header.mapper_type(),
-            rom: mbc_rom,
+            rom: mbc_rom.into(),
             ram: vec![0; RAM_BANK_SIZE * header.ram_banks() as usize].into_boxed_slice(),
             rom_banks_count: header.rom_banks(),
             ram_banks_count: header.ram_banks(),
@@ -200,6 +205,55 @@ impl MBC {
             + (self.active_rom_bank - 1) as usize * ROM_BANK_SIZE
     }
 
+    // Offset in the ROM or RAM of a banked address, whatever the mapped banks
+    fn banked_offset(addr: BankedAddress) -> Option<usize> {
+        match addr.addr {
+            CART_ROM_BANK0_START..=CART_ROM_BANK0_END => Some(addr.addr as usize),
+            CART_ROM_ACTIVE_BANK_START..=CART_ROM_ACTIVE_BANK_END => Some(
+                addr.bank as usize * ROM_BANK_SIZE
+                    + (addr.addr - CART_ROM_ACTIVE_BANK_START) as usize,
+            ),
+            CART_RAM_START..=CART_RAM_END => {
+                Some(addr.bank as usize * RAM_BANK_SIZE + (addr.addr - CART_RAM_START) as usize)
+            }
+            _ => None,
+        }
+    }
+
+    /// Read any ROM or RAM bank, even when RAM is disabled.
+    pub fn read_banked(&self, addr: BankedAddress) -> Result<u8, GbError> {
+        let memory: &[u8] = match addr.addr {
+            CART_RAM_START..=CART_RAM_END => &self.ram,
+            _ => &self.rom,
+        };
+
+        Self::banked_offset(addr)
+            .and_then(|offset| memory.get(offset))
+            .copied()
+            .ok_or(GbError::MbcAddrOutOfBounds(addr.addr))
+    }
+
+    /// Write any RAM bank, even when RAM is disabled.
+    pub fn write_ram_banked(&mut self, addr: BankedAddress, value: u8) -> Result<(), GbError> {
+        if !(CART_RAM_START..=CART_RAM_END).contains(&addr.addr) {
+            return Err(GbError::IllegalOp(format!(
+                "Write to read only cartridge addr {:#06X}",
+                addr.addr
+            )));
+        }
+
+        let byte = Self::banked_offset(addr)
+            .and_then(|offset| self.ram.get_mut(offset))
+            .ok_or(GbError::MbcAddrOutOfBounds(addr.addr))?;
+        *byte = value;
+
+        Ok(())
+    }
+
+    pub fn rom(&self) -> Arc<[u8]> {
+        self.rom.clone()
+    }
+
     pub fn state(&self) -> MbcState {
         MbcState {
             mbc_type: self.mbc_type,
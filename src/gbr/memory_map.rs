@@ -1,3 +1,7 @@
+use std::fmt::Display;
+
+use super::mbc::MbcState;
+
 pub const BOOT_ROM_SIZE: usize = 0x100;
 
 pub const CART_ROM_BANK0_START: u16 = 0x0000;
@@ -41,7 +45,9 @@ pub const APU_REGISTERS_END: u16 = 0xFF3F;
 pub const PPU_REGISTERS_LOW_START: u16 = 0xFF40;
 pub const PPU_REGISTERS_LOW_END: u16 = 0xFF45;
 
+pub const LCD_CONTROL_REGISTER: u16 = 0xFF40;
 pub const DMA_REGISTER: u16 = 0xFF46;
+pub const BG_PALETTE_REGISTER: u16 = 0xFF47;
 
 pub const PPU_REGISTERS_HIGH_START: u16 = 0xFF47;
 pub const PPU_REGISTERS_HIGH_END: u16 = 0xFF4B;
@@ -101,14 +107,73 @@ pub fn map_address(addr: u16) -> MappedAddress {
     }
 }
 
+/// Address qualified with the cartridge bank mapped at it.
+///
+/// Addresses outside the switchable ROM and cartridge RAM areas are in bank 0.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BankedAddress {
+    pub bank: u16,
+    pub addr: u16,
+}
+
+impl BankedAddress {
+    pub fn new(bank: u16, addr: u16) -> Self {
+        Self { bank, addr }
+    }
+
+    /// Qualify `addr` with the bank currently mapped by the MBC.
+    pub fn resolve(addr: u16, mbc: &MbcState) -> Self {
+        let bank = match addr {
+            CART_ROM_ACTIVE_BANK_START..=CART_ROM_ACTIVE_BANK_END => mbc.active_rom_bank,
+            CART_RAM_START..=CART_RAM_END => mbc.active_ram_bank,
+            _ => 0,
+        };
+
+        Self { bank, addr }
+    }
+}
+
+impl Display for BankedAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02X}:{:04X}", self.bank, self.addr)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::gbr::memory_map::MappedAddress;
+    use crate::gbr::{mbc::MbcState, memory_map::MappedAddress};
 
-    use super::map_address;
+    use super::{map_address, BankedAddress};
 
     #[test]
     fn wram_mapping() {
         assert_eq!(map_address(0xC000), MappedAddress::WorkRam);
     }
+
+    #[test]
+    fn banked_address() {
+        let mbc = MbcState {
+            active_rom_bank: 5,
+            active_ram_bank: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            BankedAddress::resolve(0x0150, &mbc),
+            BankedAddress::new(0, 0x0150)
+        );
+        assert_eq!(
+            BankedAddress::resolve(0x4123, &mbc),
+            BankedAddress::new(5, 0x4123)
+        );
+        assert_eq!(
+            BankedAddress::resolve(0xA000, &mbc),
+            BankedAddress::new(2, 0xA000)
+        );
+        assert_eq!(
+            BankedAddress::resolve(0xC000, &mbc),
+            BankedAddress::new(0, 0xC000)
+        );
+        assert_eq!(BankedAddress::new(0x1F, 0x4123).to_string(), "1F:4123");
+    }
 }
@@ -4,14 +4,24 @@ pub mod cart_header;
 pub mod cpu;
 pub mod dma;
 pub mod game_boy;
+pub mod gdb;
+pub mod input_log;
 pub mod instruction;
 pub mod interrupts;
 pub mod joypad;
+pub mod link;
+#[cfg(test)]
+pub mod linked_pair;
 pub mod mbc;
 pub mod memory_map;
+pub mod movie;
 pub mod oam;
 pub mod ppu;
+pub mod printer;
+pub mod speed;
 pub mod timer;
+pub mod trace;
+pub mod watchpoint;
 
 mod alu;
 mod serial;
@@ -34,4 +44,12 @@ pub enum GbError {
     MbcAddrOutOfBounds(u16),
     #[error("Header parsing: {0}")]
     HeaderParsing(String),
+    #[error("Expression parsing: {0}")]
+    ExprParsing(String),
+    #[error("Link cable: {0}")]
+    Link(String),
+    #[error("GDB server: {0}")]
+    Gdb(String),
+    #[error("Movie: {0}")]
+    Movie(String),
 }
@@ -0,0 +1,444 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    game_boy::{GenericInput, InputType},
+    GbError,
+};
+
+const MOVIE_VERSION: &str = "gb-r movie 1";
+
+/// CRC-32 (IEEE) of `data`, identifying the ROM a movie was recorded with.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+/// Frame number and clock cycles since the start of the frame.
+pub type MovieTime = (u64, u64);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputRecord {
+    pub time: MovieTime,
+    pub input: InputType,
+}
+
+/// Inputs applied since power on, with the ROM and boot ROM they were applied to.
+///
+/// Saved as text, a header followed by one "frame cycle press|release key" line per input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub rom_crc32: u32,
+    pub boot_rom: bool,
+    pub inputs: Vec<InputRecord>,
+}
+
+impl Movie {
+    pub fn new(rom_crc32: u32, boot_rom: bool) -> Self {
+        Self {
+            rom_crc32,
+            boot_rom,
+            inputs: vec![],
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, GbError> {
+        let error = |line: usize, msg: &str| GbError::Movie(format!("line {}: {}", line + 1, msg));
+
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, l)| l.trim()) != Some(MOVIE_VERSION) {
+            return Err(GbError::Movie("not a gb-r movie".into()));
+        }
+
+        let mut rom_crc32 = None;
+        let mut boot_rom = false;
+        let mut inputs = vec![];
+
+        for (i, line) in lines {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+
+            match tokens.as_slice() {
+                [] => (),
+                ["rom_crc32", crc] => {
+                    rom_crc32 = Some(
+                        u32::from_str_radix(crc, 16).map_err(|_| error(i, "invalid ROM CRC"))?,
+                    )
+                }
+                ["boot_rom", value] => boot_rom = *value == "yes",
+                // Movies always start at power on
+                ["start", "power-on"] => (),
+                ["start", _] => return Err(error(i, "unsupported start state")),
+                [frame, cycle, action, key] => {
+                    let time = (
+                        frame.parse().map_err(|_| error(i, "invalid frame"))?,
+                        cycle.parse().map_err(|_| error(i, "invalid cycle"))?,
+                    );
+                    let key =
+                        GenericInput::from_name(key).ok_or_else(|| error(i, "unknown key"))?;
+                    let input = match *action {
+                        "press" => InputType::Pressed(key),
+                        "release" => InputType::Released(key),
+                        _ => return Err(error(i, "unknown action")),
+                    };
+
+                    if inputs
+                        .last()
+                        .is_some_and(|last: &InputRecord| last.time > time)
+                    {
+                        return Err(error(i, "inputs out of order"));
+                    }
+                    inputs.push(InputRecord { time, input });
+                }
+                _ => return Err(error(i, "unknown line")),
+            }
+        }
+
+        Ok(Self {
+            rom_crc32: rom_crc32.ok_or_else(|| GbError::Movie("missing ROM CRC".into()))?,
+            boot_rom,
+            inputs,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, GbError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| GbError::Movie(format!("{}: {}", path.display(), e)))?;
+        Self::parse(&text)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "{}\nrom_crc32 {:08X}\nboot_rom {}\nstart power-on\n",
+            MOVIE_VERSION,
+            self.rom_crc32,
+            if self.boot_rom { "yes" } else { "no" }
+        );
+
+        for record in &self.inputs {
+            text += &Movie::input_line(record);
+        }
+
+        text
+    }
+
+    fn input_line(record: &InputRecord) -> String {
+        let (action, key) = match &record.input {
+            InputType::Pressed(key) => ("press", key),
+            InputType::Released(key) => ("release", key),
+        };
+
+        format!(
+            "{} {} {} {}\n",
+            record.time.0,
+            record.time.1,
+            action,
+            key.name()
+        )
+    }
+}
+
+fn file_error(path: &Path, e: std::io::Error) -> GbError {
+    GbError::Movie(format!("{}: {}", path.display(), e))
+}
+
+/// Movie being recorded to a file or played back by a `GameBoy`.
+///
+/// Recorded inputs are appended to the file, which is only rewritten when
+/// the inputs after a point are forgotten.
+pub enum MovieMode {
+    Record {
+        path: PathBuf,
+        movie: Movie,
+        writer: BufWriter<File>,
+    },
+    Play {
+        movie: Movie,
+        next: usize,
+    },
+}
+
+impl MovieMode {
+    /// Create the movie file and record the inputs to it.
+    pub fn start_recording(path: PathBuf, movie: Movie) -> Result<Self, GbError> {
+        let writer = MovieMode::create(&path, &movie)?;
+        Ok(MovieMode::Record {
+            path,
+            movie,
+            writer,
+        })
+    }
+
+    fn create(path: &Path, movie: &Movie) -> Result<BufWriter<File>, GbError> {
+        let mut writer = BufWriter::new(File::create(path).map_err(|e| file_error(path, e))?);
+        writer
+            .write_all(movie.to_text().as_bytes())
+            .map_err(|e| file_error(path, e))?;
+
+        Ok(writer)
+    }
+
+    /// Start over from power on.
+    pub fn restart(&mut self) -> Result<(), GbError> {
+        self.seek((0, 0));
+        self.truncate((0, 0))
+    }
+
+    /// Go back to `time` while playing back. Recorded inputs are kept, the
+    /// emulator replays them when running again.
+    pub fn seek(&mut self, time: MovieTime) {
+        if let MovieMode::Play { movie, next } = self {
+            *next = movie.inputs.partition_point(|r| r.time < time);
+        }
+    }
+
+    /// Forget the inputs recorded from `time` on, rewriting the file.
+    pub fn truncate(&mut self, time: MovieTime) -> Result<(), GbError> {
+        if let MovieMode::Record {
+            path,
+            movie,
+            writer,
+        } = self
+        {
+            let len = movie.inputs.partition_point(|r| r.time < time);
+            movie.inputs.truncate(len);
+            *writer = MovieMode::create(path, movie)?;
+        }
+
+        Ok(())
+    }
+
+    /// Add an input applied at `time` while recording.
+    pub fn record(&mut self, time: MovieTime, input: &InputType) -> Result<(), GbError> {
+        let MovieMode::Record {
+            path,
+            movie,
+            writer,
+        } = self
+        else {
+            return Ok(());
+        };
+
+        let record = InputRecord {
+            time,
+            input: input.clone(),
+        };
+        writer
+            .write_all(Movie::input_line(&record).as_bytes())
+            .map_err(|e| file_error(path, e))?;
+        movie.inputs.push(record);
+
+        Ok(())
+    }
+
+    /// Write the buffered inputs to the file.
+    pub fn flush(&mut self) -> Result<(), GbError> {
+        match self {
+            MovieMode::Record { path, writer, .. } => {
+                writer.flush().map_err(|e| file_error(path, e))
+            }
+            MovieMode::Play { .. } => Ok(()),
+        }
+    }
+
+    /// Take the next input to apply at `time` while playing back.
+    pub fn next_input(&mut self, time: MovieTime) -> Option<InputType> {
+        let MovieMode::Play { movie, next } = self else {
+            return None;
+        };
+
+        let record = movie.inputs.get(*next).filter(|r| r.time <= time)?;
+        *next += 1;
+
+        if *next == movie.inputs.len() {
+            log::info!("Movie playback finished at frame {}", time.0);
+        }
+
+        Some(record.input.clone())
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self, MovieMode::Play { .. })
+    }
+}
+
+impl Drop for MovieMode {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            log::error!("Movie not saved: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gbr::{
+        bus::BusAccess,
+        game_boy::{GameBoy, GenericInput, InputType},
+        joypad::{Buttons, Directions},
+    };
+
+    use super::{crc32, InputRecord, Movie};
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn text_format() {
+        let mut movie = Movie::new(0x1234ABCD, false);
+        movie.inputs = vec![
+            InputRecord {
+                time: (3, 120),
+                input: InputType::Pressed(GenericInput::Button(Buttons::Start)),
+            },
+            InputRecord {
+                time: (10, 0),
+                input: InputType::Released(GenericInput::Direction(Directions::Left)),
+            },
+        ];
+
+        let text = movie.to_text();
+        assert!(text.contains("3 120 press Start\n10 0 release Left\n"));
+        assert_eq!(Movie::parse(&text).unwrap(), movie);
+
+        assert!(Movie::parse("gb-r movie 1\nrom_crc32 00\n1 0 press Turbo\n").is_err());
+        assert!(Movie::parse("gb-r movie 1\nrom_crc32 00\n5 0 press A\n1 0 press B\n").is_err());
+        assert!(Movie::parse("gb-r movie 1\n1 0 press A\n").is_err());
+    }
+
+    // Store the joypad register to 0xC000 + the number of reads, forever
+    fn game_boy() -> GameBoy {
+        GameBoy::with_program(&[
+            0x21, 0x00, 0xC0, // LD HL, 0xC000
+            0x3E, 0x10, // LD A, 0x10
+            0xE0, 0x00, // LDH (0x00), A
+            0xF0, 0x00, // LDH A, (0x00)
+            0x22, // LD (HL+), A
+            0x18, 0xFB, // JR -5
+        ])
+    }
+
+    #[test]
+    fn record_and_play() {
+        let path = std::env::temp_dir().join(format!("gbr_movie_{}.txt", std::process::id()));
+
+        let mut gb = game_boy();
+        gb.record_movie(path.clone()).unwrap();
+
+        let a = |input: fn(GenericInput) -> InputType| input(GenericInput::Button(Buttons::A));
+        for step in 0..200 {
+            match step {
+                50 => gb.handle_input(a(InputType::Pressed)),
+                120 => gb.handle_input(a(InputType::Released)),
+                _ => (),
+            }
+            gb.step().unwrap();
+        }
+        let recorded: Vec<u8> = (0..0x40)
+            .map(|i| gb.bus().read_byte(0xC000 + i).unwrap())
+            .collect();
+        assert!(recorded.contains(&0xDE));
+
+        // Saved when the recording stops
+        drop(gb);
+        let movie = Movie::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(movie.inputs.len(), 2);
+
+        let mut gb = game_boy();
+        gb.play_movie(movie).unwrap();
+        for _ in 0..200 {
+            // Ignored while playing back
+            gb.handle_input(InputType::Pressed(GenericInput::Button(Buttons::B)));
+            gb.step().unwrap();
+        }
+        let played: Vec<u8> = (0..0x40)
+            .map(|i| gb.bus().read_byte(0xC000 + i).unwrap())
+            .collect();
+
+        assert_eq!(played, recorded);
+
+        // Another ROM
+        let mut other = GameBoy::with_program(&[]);
+        assert!(other.play_movie(Movie::new(0, false)).is_err());
+    }
+
+    // Record a press of A after 50 steps and go back to power on
+    fn record_and_restore(path: &std::path::Path) -> GameBoy {
+        let mut gb = game_boy();
+        gb.record_movie(path.to_path_buf()).unwrap();
+        gb.log_inputs(true);
+
+        let snapshot = gb.snapshot();
+        for step in 0..100 {
+            if step == 50 {
+                gb.handle_input(InputType::Pressed(GenericInput::Button(Buttons::A)));
+            }
+            gb.step().unwrap();
+        }
+
+        gb.restore(&snapshot);
+        assert!(gb.is_replaying());
+        gb
+    }
+
+    #[test]
+    fn restore_keeps_inputs() {
+        let path = std::env::temp_dir().join(format!("gbr_restore_{}.txt", std::process::id()));
+
+        let mut gb = record_and_restore(&path);
+        for _ in 0..100 {
+            gb.step().unwrap();
+        }
+        assert!(!gb.is_replaying());
+        drop(gb);
+
+        let movie = Movie::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(movie.inputs.len(), 1);
+        assert_eq!(
+            movie.inputs[0].input,
+            InputType::Pressed(GenericInput::Button(Buttons::A))
+        );
+    }
+
+    #[test]
+    fn new_branch() {
+        let path = std::env::temp_dir().join(format!("gbr_branch_{}.txt", std::process::id()));
+
+        let mut gb = record_and_restore(&path);
+        for _ in 0..10 {
+            gb.step().unwrap();
+        }
+        gb.new_branch();
+        assert!(!gb.is_replaying());
+
+        // Live inputs apply again, the press of A is forgotten
+        gb.handle_input(InputType::Pressed(GenericInput::Button(Buttons::B)));
+        drop(gb);
+
+        let movie = Movie::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(movie.inputs.len(), 1);
+        assert_eq!(
+            movie.inputs[0].input,
+            InputType::Pressed(GenericInput::Button(Buttons::B))
+        );
+    }
+}
@@ -6,7 +6,7 @@ use crate::gbr::{
 const OBJ_ATTR_SIZE: usize = 4; // bytes
 const OBJ_ATTR_COUNT: usize = OBJ_ATTRIBUTE_TABLE_SIZE / OBJ_ATTR_SIZE;
 
-#[derive(Default, Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct ObjAttribute {
     top: i16,
     left: i16,
@@ -17,6 +17,21 @@ pub struct ObjAttribute {
     palette_id: u8,
 }
 
+// Zeroed attributes, above and left of the screen
+impl Default for ObjAttribute {
+    fn default() -> Self {
+        Self {
+            top: -16,
+            left: -8,
+            tile_index: 0,
+            bg_win_prio: false,
+            flip_y: false,
+            flip_x: false,
+            palette_id: 0,
+        }
+    }
+}
+
 impl ObjAttribute {
     fn write_attr(&mut self, attr_id: usize, value: u8) -> Result<(), GbError> {
         match attr_id {
@@ -35,6 +50,21 @@ impl ObjAttribute {
         Ok(())
     }
 
+    // Bits not kept by `write_attr` read as 0
+    fn read_attr(&self, attr_id: usize) -> u8 {
+        match attr_id {
+            0 => (self.top + 16) as u8,
+            1 => (self.left + 8) as u8,
+            2 => self.tile_index,
+            _ => {
+                (self.bg_win_prio as u8) << 7
+                    | (self.flip_y as u8) << 6
+                    | (self.flip_x as u8) << 5
+                    | self.palette_id << 4
+            }
+        }
+    }
+
     pub fn top(&self) -> i16 {
         self.top
     }
@@ -72,6 +102,7 @@ impl ObjAttribute {
     }
 }
 
+#[derive(Clone)]
 pub struct ObjAttributeMemory {
     attributes: Box<[ObjAttribute]>,
 }
@@ -91,6 +122,13 @@ impl ObjAttributeMemory {
         self.attributes[obj_index].write_attr(attr_id, value)
     }
 
+    /// Read `addr` for the debugger, the CPU cannot read OAM.
+    pub fn peek(&self, addr: u16) -> u8 {
+        let rel_addr = (addr - OBJ_ATTRIBUTE_TABLE_START) as usize;
+
+        self.attributes[rel_addr / OBJ_ATTR_SIZE].read_attr(rel_addr % OBJ_ATTR_SIZE)
+    }
+
     pub fn get_objs_at_line(&self, ly: u8) -> Vec<ObjAttribute> {
         let mut objs = vec![];
         objs.reserve(10);
@@ -117,3 +155,58 @@ impl ObjAttributeMemory {
         state
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::gbr::{bus::BusAccess, game_boy::GameBoy, ppu::palette::GrayShade};
+
+    use super::ObjAttributeMemory;
+
+    #[test]
+    fn peek() {
+        let mut oam = ObjAttributeMemory::new();
+
+        for (addr, value) in [
+            (0xFE04, 0x20),
+            (0xFE05, 0x10),
+            (0xFE06, 0x42),
+            (0xFE07, 0xB0),
+        ] {
+            oam.write_byte(addr, value).unwrap();
+            assert_eq!(oam.peek(addr), value);
+        }
+
+        // The low bits of the flags are not kept
+        oam.write_byte(0xFE9F, 0x0F).unwrap();
+        assert_eq!(oam.peek(0xFE9F), 0x00);
+    }
+
+    #[test]
+    fn hidden_at_power_on() {
+        let oam = ObjAttributeMemory::new();
+        assert_eq!(oam.peek(0xFE00), 0x00);
+        assert_eq!(oam.peek(0xFE01), 0x00);
+
+        // JR -2
+        let mut gb = GameBoy::with_program(&[0x18, 0xFE]);
+        let bus = gb.bus_mut();
+        bus.write_byte(0xFF40, 0x00).unwrap();
+        // Tile 0 in colour 3, white BG and black objects
+        for addr in 0x8000..0x8010 {
+            bus.write_byte(addr, 0xFF).unwrap();
+        }
+        bus.write_byte(0xFF47, 0x00).unwrap();
+        bus.write_byte(0xFF48, 0xFF).unwrap();
+        // LCD, objects and BG on, tiles at $8000
+        bus.write_byte(0xFF40, 0x93).unwrap();
+
+        for _ in 0..2 {
+            while !gb.step().unwrap() {}
+        }
+
+        // The objects never written are not drawn over the top left corner
+        let frame = gb.bus().ppu().frame();
+        assert_eq!(frame[0..4], GrayShade::White.to_rgba().rgba);
+        assert_eq!(frame[4 * 7..4 * 8], GrayShade::White.to_rgba().rgba);
+    }
+}
@@ -10,8 +10,8 @@ use byteorder::{ByteOrder, LittleEndian};
 use self::{
     lcd_control_register::LcdControlRegister,
     lcd_status_register::{LcsStatusRegister, ScreenMode},
-    palette::Palette,
-    pixel_processor::PixelProcessor,
+    palette::{ColorScheme, Palette},
+    pixel_processor::{PixelProcessor, RenderContext},
     rgba::Rgba,
     tile::{TileData, TileMap},
 };
@@ -38,7 +38,7 @@ pub const SCREEN_HEIGHT: u32 = 144;
 
 pub const TILE_WIDTH: u32 = 8;
 pub const TILE_HEIGHT: u32 = 8;
-const TILE_DATA_SIZE: usize = 16;
+pub const TILE_DATA_SIZE: usize = 16;
 const TILE_MAP_DATA_ROWS: usize = 32;
 const TILE_MAP_DATA_COLS: usize = 32;
 
@@ -86,6 +86,7 @@ pub struct PpuState {
     pub tilemaps: [TileMap; 2],
 }
 
+#[derive(Clone)]
 pub struct PPU {
     vram: Box<[u8]>,
     lcd_control: LcdControlRegister,
@@ -99,6 +100,12 @@ pub struct PPU {
     tiles: TileData,
     tilemaps: [TileMap; 2],
     render_ch: (flume::Sender<ScreenBuffer>, flume::Receiver<ScreenBuffer>),
+    // Frames not rendered after each rendered one, and frames skipped so far
+    frame_skip: u8,
+    skipped: u8,
+    // Last completed frame
+    frame: ScreenBuffer,
+    colors: ColorScheme,
     dots: u16,
     mode_3_dots: u16,
     pixel_processor: PixelProcessor,
@@ -119,6 +126,10 @@ impl PPU {
             tiles: TileData::new(),
             tilemaps: Default::default(),
             render_ch: flume::bounded(1),
+            frame_skip: 0,
+            skipped: 0,
+            frame: vec![0; (SCREEN_WIDTH * SCREEN_HEIGHT * 4) as usize],
+            colors: ColorScheme::default(),
             dots: 0,
             mode_3_dots: 0,
             pixel_processor: PixelProcessor::new(),
@@ -137,6 +148,7 @@ impl PPU {
         self.tiles.clear();
         self.dots = 0;
         self.pixel_processor = PixelProcessor::new();
+        self.frame.fill(0);
     }
 
     pub fn step(
@@ -176,17 +188,17 @@ impl PPU {
             self.lcd_status.mode.set(ScreenMode::SreachingOAM);
         } else if self.lcd_status.mode.get() == ScreenMode::SreachingOAM && self.mode_3_dots == 0 {
             self.lcd_status.mode.set(ScreenMode::TransferringData);
-            self.mode_3_dots = self.pixel_processor.draw_line(
-                self.ly,
-                &self.viewport,
-                &self.win_pos,
-                &self.lcd_control,
-                oam,
-                &self.tiles,
-                &self.tilemaps,
-                &self.bg_palette,
-                &self.obj_palettes,
-            );
+            let ctx = RenderContext {
+                viewport: &self.viewport,
+                win_position: &self.win_pos,
+                lcd_ctrl: &self.lcd_control,
+                tiles: &self.tiles,
+                tilemaps: &self.tilemaps,
+                bg_palette: &self.bg_palette,
+                obj_palettes: &self.obj_palettes,
+                colors: &self.colors,
+            };
+            self.mode_3_dots = self.pixel_processor.draw_line(self.ly, oam, &ctx);
         } else if self.lcd_status.mode.get() == ScreenMode::TransferringData && self.mode_3_dots > 0
         {
             if self.mode_3_dots < cpu_cycles {
@@ -194,18 +206,7 @@ impl PPU {
             } else {
                 self.mode_3_dots -= cpu_cycles;
             }
-            // self.pixel_processor.process(
-            //     self.ly,
-            //     self.dots,
-            //     &self.viewport,
-            //     &self.win_pos,
-            //     &self.lcd_control,
-            //     &self.vram,
-            //     &self.tiles,
-            //     &self.tilemaps,
-            //     &self.bg_palette,
-            //     &self.obj_palettes,
-            // );
+            // self.pixel_processor.process(self.ly, self.dots, &self.vram, &ctx);
             if self.dots > MODE_3_DOTS_MAX {
                 log::error!("mode 3 out of bounds {}", self.dots);
             }
@@ -229,6 +230,11 @@ impl PPU {
         Ok(self.vram[(addr - VRAM_START) as usize])
     }
 
+    /// Read VRAM for the debugger, even while it is not accessible to the CPU.
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.vram[(addr - VRAM_START) as usize]
+    }
+
     pub fn read_word(&self, addr: u16) -> Result<u16, GbError> {
         // VRAM is not accessible when display is enabled and PPU is in Mode3
         if self.lcd_control.display_enable
@@ -326,11 +332,40 @@ impl PPU {
         self.render_ch.1.clone()
     }
 
+    pub fn set_frame_skip(&mut self, frame_skip: u8) {
+        self.frame_skip = frame_skip;
+        self.skipped = 0;
+    }
+
+    /// Last completed frame.
+    pub fn frame(&self) -> &ScreenBuffer {
+        &self.frame
+    }
+
+    /// Colours of the LCD shades, used from the next drawn line.
+    pub fn set_color_scheme(&mut self, colors: ColorScheme) {
+        self.colors = colors;
+    }
+
+    /// Go back to the state of `snapshot`, keeping the frame skip and colours.
+    pub fn restore(&mut self, snapshot: &PPU) {
+        let frame_skip = self.frame_skip;
+        let colors = self.colors;
+
+        *self = snapshot.clone();
+        self.set_frame_skip(frame_skip);
+        self.colors = colors;
+    }
+
     pub fn render(&mut self) -> Result<(), GbError> {
-        self.render_ch
-            .0
-            .try_send(self.pixel_processor.screen_buffer.clone())
-            .ok();
+        std::mem::swap(&mut self.frame, &mut self.pixel_processor.screen_buffer);
+
+        if self.skipped >= self.frame_skip {
+            self.render_ch.0.try_send(self.frame.clone()).ok();
+            self.skipped = 0;
+        } else {
+            self.skipped += 1;
+        }
         self.pixel_processor.screen_buffer.fill(0);
         Ok(())
     }
@@ -98,3 +98,87 @@ impl From<Palette> for u8 {
             | (value.indexed[3] as u8) << 6
     }
 }
+
+/// LCD colours of the four shades, for the background and window and for each
+/// object palette, like the CGB boot ROM colourises DMG games.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorScheme {
+    pub bg: [Rgba; 4],
+    pub obj: [[Rgba; 4]; 2],
+}
+
+impl ColorScheme {
+    /// Same colours for all the palettes, from white to black.
+    pub fn uniform(colors: [Rgba; 4]) -> Self {
+        Self {
+            bg: colors,
+            obj: [colors; 2],
+        }
+    }
+
+    pub fn presets() -> Vec<(&'static str, ColorScheme)> {
+        let hex = |colors: [u32; 4]| Self::uniform(colors.map(Rgba::from_rgb));
+
+        vec![
+            ("Grey", Self::default()),
+            ("DMG green", hex([0x9BBC0F, 0x8BAC0F, 0x306230, 0x0F380F])),
+            ("Pocket", hex([0xC4CFA1, 0x8B956D, 0x4D533C, 0x1F1F1F])),
+            ("Light teal", hex([0x00B581, 0x009A71, 0x00694A, 0x004F3B])),
+        ]
+    }
+
+    pub fn bg_rgba(&self, shade: GrayShade) -> &Rgba {
+        &self.bg[u8::from(shade) as usize]
+    }
+
+    pub fn obj_rgba(&self, palette_id: usize, shade: GrayShade) -> &Rgba {
+        &self.obj[palette_id][u8::from(shade) as usize]
+    }
+}
+
+impl Default for ColorScheme {
+    fn default() -> Self {
+        Self::uniform([
+            GrayShade::White.to_rgba(),
+            GrayShade::LightGray.to_rgba(),
+            GrayShade::DarkGray.to_rgba(),
+            GrayShade::Black.to_rgba(),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorScheme, GrayShade, Palette, Rgba};
+
+    #[test]
+    fn color_scheme_shades() {
+        let mut colors = ColorScheme::presets()[1].1;
+        colors.obj[1][3] = Rgba::from_rgb(0xFF0000);
+
+        // Shades 3, 2, 1, 0
+        let palette = Palette::from(0b00_01_10_11);
+
+        assert_eq!(
+            colors.bg_rgba(palette.indexed(0)).rgba,
+            [0x0F, 0x38, 0x0F, 255]
+        );
+        assert_eq!(
+            colors.bg_rgba(palette.indexed(3)).rgba,
+            [0x9B, 0xBC, 0x0F, 255]
+        );
+        assert_eq!(
+            colors.obj_rgba(0, palette.indexed(0)).rgba,
+            [0x0F, 0x38, 0x0F, 255]
+        );
+        assert_eq!(
+            colors.obj_rgba(1, palette.indexed(0)).rgba,
+            [0xFF, 0, 0, 255]
+        );
+
+        assert_eq!(
+            ColorScheme::default().bg_rgba(palette.indexed(1)),
+            &GrayShade::DarkGray.to_rgba()
+        );
+    }
+}
@@ -5,12 +5,13 @@ use crate::gbr::{
 
 use super::{
     lcd_control_register::LcdControlRegister,
-    palette::Palette,
+    palette::{ColorScheme, Palette},
+    rgba::Rgba,
     tile::{TileData, TileMap, TILE_COLOR_ID},
     Point, MODE_2_DOTS, SCREEN_HEIGHT, SCREEN_WIDTH, TILEMAP_BLOCK1_START,
 };
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone)]
 enum Step {
     GetTileIndex,
     GetTileData,
@@ -19,6 +20,7 @@ enum Step {
     PopPixels,
 }
 
+#[derive(Clone)]
 struct Pixel {
     color_id: u8,
     palette_id: usize,
@@ -46,6 +48,32 @@ impl Pixel {
     }
 }
 
+/// PPU registers and memory a line is drawn from.
+pub struct RenderContext<'a> {
+    pub viewport: &'a Point<u8>,
+    pub win_position: &'a Point<u8>,
+    pub lcd_ctrl: &'a LcdControlRegister,
+    pub tiles: &'a TileData,
+    pub tilemaps: &'a [TileMap],
+    pub bg_palette: &'a Palette,
+    pub obj_palettes: &'a [Palette],
+    pub colors: &'a ColorScheme,
+}
+
+impl RenderContext<'_> {
+    fn rgba(&self, pixel: &Pixel) -> &Rgba {
+        if pixel.is_bg {
+            self.colors.bg_rgba(self.bg_palette.indexed(pixel.color_id))
+        } else {
+            self.colors.obj_rgba(
+                pixel.palette_id,
+                self.obj_palettes[pixel.palette_id].indexed(pixel.color_id),
+            )
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct PixelProcessor {
     scan_line_x: u8,
     old_dots: u16,
@@ -99,18 +127,16 @@ impl PixelProcessor {
         self.scan_line_x as u32 >= SCREEN_WIDTH
     }
 
-    pub fn draw_line(
-        &mut self,
-        ly: u8,
-        viewport: &Point<u8>,
-        win_position: &Point<u8>,
-        lcd_ctrl: &LcdControlRegister,
-        oam: &ObjAttributeMemory,
-        tiles: &TileData,
-        tilemaps: &[TileMap],
-        bg_palette: &Palette,
-        obj_palettes: &[Palette],
-    ) -> u16 {
+    pub fn draw_line(&mut self, ly: u8, oam: &ObjAttributeMemory, ctx: &RenderContext) -> u16 {
+        let RenderContext {
+            viewport,
+            win_position,
+            lcd_ctrl,
+            tiles,
+            tilemaps,
+            ..
+        } = *ctx;
+
         let mut fifo_line = vec![];
         fifo_line.reserve(SCREEN_WIDTH as usize);
 
@@ -180,14 +206,8 @@ impl PixelProcessor {
         for (x, pixel) in fifo_line.iter().enumerate() {
             let screen_index = (ly as usize * SCREEN_WIDTH as usize + x) * 4;
 
-            let palette = if pixel.is_bg {
-                &bg_palette
-            } else {
-                &obj_palettes[pixel.palette_id]
-            };
-
             self.screen_buffer[screen_index..screen_index + 4]
-                .copy_from_slice(&palette.rgba(pixel.color_id).rgba);
+                .copy_from_slice(&ctx.rgba(pixel).rgba);
 
             // if pixel.is_bg {
             //     self.screen_buffer[screen_index..screen_index + 4]
@@ -202,22 +222,19 @@ impl PixelProcessor {
         (172 + 6 * objs.len()) as u16
     }
 
-    pub fn process(
-        &mut self,
-        ly: u8,
-        dots: u16,
-        viewport: &Point<u8>,
-        win_position: &Point<u8>,
-        lcd_ctrl: &LcdControlRegister,
-        vram: &[u8],
-        tiles: &TileData,
-        bg_palette: &Palette,
-        obj_palettes: &[Palette],
-    ) {
+    pub fn process(&mut self, ly: u8, dots: u16, vram: &[u8], ctx: &RenderContext) {
         if dots < self.old_dots {
             return;
         }
 
+        let RenderContext {
+            viewport,
+            win_position,
+            lcd_ctrl,
+            tiles,
+            ..
+        } = *ctx;
+
         let mut delta_dots = (dots - self.old_dots) as i16;
 
         while delta_dots > 0 && !self.finished() {
@@ -255,7 +272,7 @@ impl PixelProcessor {
 
             if self.curr_step == Step::PopPixels {
                 self.curr_step = Step::GetTileIndex;
-                self.pop_pixels(ly, bg_palette, obj_palettes);
+                self.pop_pixels(ly, ctx);
                 self.scan_line_x += 8;
                 delta_dots -= 1;
             }
@@ -371,20 +388,14 @@ impl PixelProcessor {
         }
     }
 
-    fn pop_pixels(&mut self, ly: u8, bg_palette: &Palette, obj_palettes: &[Palette]) {
+    fn pop_pixels(&mut self, ly: u8, ctx: &RenderContext) {
         for (x, pixel) in self.pixel_fifo.iter().enumerate() {
             let screen_x = self.scan_line_x as usize + x;
             let screen_y = ly as usize;
             let screen_index = (screen_y * SCREEN_WIDTH as usize + screen_x) * 4;
 
-            let palette = if pixel.is_bg {
-                &bg_palette
-            } else {
-                &obj_palettes[pixel.palette_id]
-            };
-
             self.screen_buffer[screen_index..screen_index + 4]
-                .copy_from_slice(&palette.rgba(pixel.color_id).rgba);
+                .copy_from_slice(&ctx.rgba(pixel).rgba);
 
             // if pixel.is_bg {
             //     self.screen_buffer[screen_index..screen_index + 4]
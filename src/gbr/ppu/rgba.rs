@@ -1,9 +1,16 @@
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Rgba {
     pub rgba: [u8; 4],
 }
 
 impl Rgba {
+    /// Opaque colour from a 0xRRGGBB value.
+    pub const fn from_rgb(rgb: u32) -> Self {
+        Self {
+            rgba: [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 255],
+        }
+    }
+
     pub fn black() -> Self {
         Self {
             rgba: [0, 0, 0, 255],
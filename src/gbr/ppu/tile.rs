@@ -61,6 +61,21 @@ impl Tile {
         }
     }
 
+    /// Tile in the VRAM format, 2 bytes per line, low bits first.
+    pub fn data(&self) -> [u8; TILE_DATA_SIZE] {
+        let mut data = [0; TILE_DATA_SIZE];
+
+        for (line, bytes) in self.pixels.iter().zip(data.chunks_exact_mut(2)) {
+            for (x, color_id) in line.iter().enumerate() {
+                let shift = 7 - x;
+                bytes[0] |= (color_id & 0b1) << shift;
+                bytes[1] |= (color_id >> 1 & 0b1) << shift;
+            }
+        }
+
+        data
+    }
+
     pub fn line(&self, line_index: usize, flip_y: bool, flip_x: bool) -> [u8; TILE_WIDTH as usize] {
         let index = if flip_y {
             TILE_HEIGHT as usize - line_index
@@ -151,3 +166,21 @@ impl TileMap {
         line
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Tile;
+
+    #[test]
+    fn tile_data() {
+        // Pan Docs example tile
+        let data = [
+            0x3C, 0x7E, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x7E, 0x5E, 0x7E, 0x0A, 0x7C, 0x56,
+            0x38, 0x7C,
+        ];
+
+        let tile = Tile::from_data(&data);
+        assert_eq!(tile.pixels[0], [0, 2, 3, 3, 3, 3, 2, 0]);
+        assert_eq!(tile.data(), data);
+    }
+}
@@ -0,0 +1,504 @@
+use std::path::PathBuf;
+
+use bitflags::bitflags;
+use image::RgbaImage;
+
+use super::{
+    link::SerialPeer,
+    ppu::{palette::GrayShade, tile::Tile},
+};
+
+const MAGIC_BYTE_1: u8 = 0x88;
+const MAGIC_BYTE_2: u8 = 0x33;
+const ALIVE_BYTE: u8 = 0x81;
+
+const INIT_COMMAND: u8 = 0x01;
+const PRINT_COMMAND: u8 = 0x02;
+const DATA_COMMAND: u8 = 0x04;
+const STATUS_COMMAND: u8 = 0x0F;
+
+const PAPER_WIDTH: usize = 160;
+const TILES_PER_ROW: usize = PAPER_WIDTH / 8;
+const TILE_SIZE: usize = 16;
+
+// A data packet holds two rows of tiles
+const BAND_SIZE: usize = 2 * TILES_PER_ROW * TILE_SIZE;
+const BAND_HEIGHT: usize = 16;
+const BUFFER_SIZE: usize = 9 * BAND_SIZE;
+
+// Blank lines fed for each margin unit of a print command
+const MARGIN_LINES: usize = 8;
+
+// Palette used by games which leave the print palette at 0
+const DEFAULT_PALETTE: u8 = 0b11100100;
+
+// Number of status requests answered as busy after a print command
+const PRINT_BUSY_POLLS: u8 = 4;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct PrinterStatus: u8 {
+        const LowBattery = 1 << 7;
+        const OtherError = 1 << 6;
+        const PaperJam = 1 << 5;
+        const PacketError = 1 << 4;
+        const Unprocessed = 1 << 3;
+        const Full = 1 << 2;
+        const Printing = 1 << 1;
+        const ChecksumError = 1 << 0;
+    }
+}
+
+#[derive(Default, PartialEq)]
+enum PacketState {
+    #[default]
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    KeepAlive,
+    Status,
+}
+
+#[derive(Default)]
+struct Packet {
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    expected_checksum: u16,
+}
+
+/// Game Boy Printer connected to the link port.
+///
+/// Printed sheets are saved as PNG files in the output directory once the
+/// paper is fed out, that is when a print command has a non zero bottom margin.
+/// A sheet still in the printer is saved when the Game Boy is reset or the
+/// printer unplugged.
+pub struct Printer {
+    output_dir: PathBuf,
+    state: PacketState,
+    packet: Packet,
+    status: PrinterStatus,
+    busy_polls: u8,
+
+    // Received tile data waiting for a print command
+    buffer: Vec<u8>,
+    // Printed lines of the current sheet, as gray shades
+    sheet: Vec<[u8; PAPER_WIDTH]>,
+    printed: usize,
+    // Reply to the last byte sent
+    answer: Option<u8>,
+}
+
+impl Printer {
+    pub fn new(output_dir: PathBuf) -> Self {
+        Self {
+            output_dir,
+            state: PacketState::default(),
+            packet: Packet::default(),
+            status: PrinterStatus::empty(),
+            busy_polls: 0,
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            sheet: vec![],
+            printed: 0,
+            answer: None,
+        }
+    }
+
+    fn receive(&mut self, data: u8) -> u8 {
+        let mut reply = 0x00;
+
+        self.state = match self.state {
+            PacketState::Magic1 if data == MAGIC_BYTE_1 => PacketState::Magic2,
+            PacketState::Magic1 => PacketState::Magic1,
+            PacketState::Magic2 if data == MAGIC_BYTE_2 => {
+                self.packet = Packet::default();
+                PacketState::Command
+            }
+            PacketState::Magic2 if data == MAGIC_BYTE_1 => PacketState::Magic2,
+            PacketState::Magic2 => PacketState::Magic1,
+            PacketState::Command => {
+                self.packet.command = data;
+                self.packet.checksum = data as u16;
+                PacketState::Compression
+            }
+            PacketState::Compression => {
+                self.packet.compressed = data & 0x01 != 0;
+                self.packet.checksum += data as u16;
+                PacketState::LengthLow
+            }
+            PacketState::LengthLow => {
+                self.packet.length = data as u16;
+                self.packet.checksum += data as u16;
+                PacketState::LengthHigh
+            }
+            PacketState::LengthHigh => {
+                self.packet.length |= (data as u16) << 8;
+                self.packet.checksum += data as u16;
+
+                if self.packet.length == 0 {
+                    PacketState::ChecksumLow
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::Data => {
+                self.packet.data.push(data);
+                self.packet.checksum = self.packet.checksum.wrapping_add(data as u16);
+
+                if self.packet.data.len() == self.packet.length as usize {
+                    PacketState::ChecksumLow
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::ChecksumLow => {
+                self.packet.expected_checksum = data as u16;
+                PacketState::ChecksumHigh
+            }
+            PacketState::ChecksumHigh => {
+                self.packet.expected_checksum |= (data as u16) << 8;
+                PacketState::KeepAlive
+            }
+            PacketState::KeepAlive => {
+                reply = ALIVE_BYTE;
+                PacketState::Status
+            }
+            PacketState::Status => {
+                // Status is answered before the command is processed
+                reply = self.status.bits();
+                self.process_packet();
+                PacketState::Magic1
+            }
+        };
+
+        reply
+    }
+
+    fn process_packet(&mut self) {
+        if self.packet.checksum != self.packet.expected_checksum {
+            log::warn!(
+                "Printer checksum mismatch: received {:#06X}, expected {:#06X}",
+                self.packet.expected_checksum,
+                self.packet.checksum
+            );
+            self.status.insert(PrinterStatus::ChecksumError);
+            return;
+        }
+        self.status.remove(PrinterStatus::ChecksumError);
+
+        match self.packet.command {
+            INIT_COMMAND => {
+                self.buffer.clear();
+                self.busy_polls = 0;
+                self.status = PrinterStatus::empty();
+            }
+            DATA_COMMAND => {
+                let data = std::mem::take(&mut self.packet.data);
+
+                if self.packet.compressed {
+                    decompress(&data, &mut self.buffer);
+                } else {
+                    self.buffer.extend_from_slice(&data);
+                }
+                self.buffer.truncate(BUFFER_SIZE);
+
+                self.status
+                    .set(PrinterStatus::Unprocessed, !self.buffer.is_empty());
+                self.status
+                    .set(PrinterStatus::Full, self.buffer.len() == BUFFER_SIZE);
+            }
+            PRINT_COMMAND => {
+                if self.packet.data.len() < 4 {
+                    self.status.insert(PrinterStatus::PacketError);
+                    return;
+                }
+
+                let sheets = self.packet.data[0];
+                let margins = self.packet.data[1];
+                let palette = match self.packet.data[2] {
+                    0 => DEFAULT_PALETTE,
+                    palette => palette,
+                };
+
+                self.print(sheets, margins >> 4, margins & 0x0F, palette);
+            }
+            STATUS_COMMAND => {
+                if self.busy_polls > 0 {
+                    self.busy_polls -= 1;
+                    if self.busy_polls == 0 {
+                        self.status.remove(PrinterStatus::Printing);
+                    }
+                }
+            }
+            command => {
+                log::warn!("Unknown printer command {:#04X}", command);
+                self.status.insert(PrinterStatus::PacketError);
+            }
+        }
+    }
+
+    fn print(&mut self, sheets: u8, margin_before: u8, margin_after: u8, palette: u8) {
+        self.feed(margin_before);
+
+        if sheets > 0 {
+            for band in self.buffer.chunks(BAND_SIZE) {
+                let mut lines = [[0; PAPER_WIDTH]; BAND_HEIGHT];
+
+                for (index, data) in band.chunks_exact(TILE_SIZE).enumerate() {
+                    let tile = Tile::from_data(data);
+                    let row = (index / TILES_PER_ROW) * 8;
+                    let col = (index % TILES_PER_ROW) * 8;
+
+                    for (y, line) in tile.pixels.iter().enumerate() {
+                        for (x, color_id) in line.iter().enumerate() {
+                            lines[row + y][col + x] = palette >> (2 * color_id) & 0b11;
+                        }
+                    }
+                }
+
+                let height = band.len().div_ceil(TILES_PER_ROW * TILE_SIZE) * 8;
+                self.sheet.extend_from_slice(&lines[..height]);
+            }
+        }
+
+        self.feed(margin_after);
+
+        self.buffer.clear();
+        self.status = PrinterStatus::Printing;
+        self.busy_polls = PRINT_BUSY_POLLS;
+
+        // Paper is cut once it is fed out after the image
+        if margin_after > 0 {
+            self.save_sheet();
+        }
+    }
+
+    fn feed(&mut self, margin: u8) {
+        let lines = margin as usize * MARGIN_LINES;
+        self.sheet
+            .resize(self.sheet.len() + lines, [0; PAPER_WIDTH]);
+    }
+
+    fn sheet_image(&self) -> RgbaImage {
+        let mut img = RgbaImage::new(PAPER_WIDTH as u32, self.sheet.len() as u32);
+
+        for (y, line) in self.sheet.iter().enumerate() {
+            for (x, shade) in line.iter().enumerate() {
+                let rgba = GrayShade::from(*shade).to_rgba().rgba;
+                img.put_pixel(x as u32, y as u32, image::Rgba(rgba));
+            }
+        }
+
+        img
+    }
+
+    fn save_pending_sheet(&mut self) {
+        if !self.sheet.is_empty() {
+            self.save_sheet();
+        }
+    }
+
+    fn save_sheet(&mut self) {
+        let img = self.sheet_image();
+        self.sheet.clear();
+
+        let path = loop {
+            self.printed += 1;
+            let path = self
+                .output_dir
+                .join(format!("print_{:03}.png", self.printed));
+            if !path.exists() {
+                break path;
+            }
+        };
+
+        match img.save(&path) {
+            Ok(()) => log::info!("Printed image saved to {}", path.display()),
+            Err(e) => log::error!("Failed to save printed image {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl SerialPeer for Printer {
+    fn send(&mut self, data: u8) {
+        self.answer = Some(self.receive(data));
+    }
+
+    fn answer(&mut self) -> Option<u8> {
+        self.answer.take()
+    }
+
+    fn poll(&mut self, _data: u8) -> Option<u8> {
+        // The printer never drives the clock
+        None
+    }
+
+    fn reset(&mut self) {
+        // A packet cut short by the reset is dropped
+        self.state = PacketState::default();
+        self.save_pending_sheet();
+    }
+}
+
+impl Drop for Printer {
+    fn drop(&mut self) {
+        self.save_pending_sheet();
+    }
+}
+
+// Run length decoding: a control byte with the msb set repeats the next byte
+// (control & 0x7F) + 2 times, otherwise the next control + 1 bytes are copied.
+fn decompress(data: &[u8], out: &mut Vec<u8>) {
+    let mut iter = data.iter();
+
+    while let Some(&control) = iter.next() {
+        if control & 0x80 != 0 {
+            let Some(&value) = iter.next() else {
+                break;
+            };
+            let count = (control & 0x7F) as usize + 2;
+            out.resize(out.len() + count, value);
+        } else {
+            out.extend(iter.by_ref().take(control as usize + 1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::gbr::link::SerialPeer;
+
+    use super::{decompress, Printer, PrinterStatus, BAND_SIZE};
+
+    fn output_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gbr_printer_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Send a packet and return the keep alive and status bytes
+    fn send_packet(printer: &mut Printer, command: u8, compressed: bool, data: &[u8]) -> (u8, u8) {
+        let len = data.len() as u16;
+        let mut packet = vec![command, compressed as u8, len as u8, (len >> 8) as u8];
+        packet.extend_from_slice(data);
+
+        let checksum = packet
+            .iter()
+            .fold(0u16, |sum, b| sum.wrapping_add(*b as u16));
+
+        for byte in [0x88, 0x33].iter().chain(&packet) {
+            assert_eq!(printer.receive(*byte), 0x00);
+        }
+        printer.receive(checksum as u8);
+        printer.receive((checksum >> 8) as u8);
+
+        (printer.receive(0x00), printer.receive(0x00))
+    }
+
+    #[test]
+    fn rle_decompression() {
+        let mut out = vec![];
+        decompress(&[0x81, 0xAA, 0x02, 0x01, 0x02, 0x03, 0x80, 0xFF], &mut out);
+
+        assert_eq!(out, vec![0xAA, 0xAA, 0xAA, 0x01, 0x02, 0x03, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn status_handshake() {
+        let dir = output_dir("handshake");
+        let mut printer = Printer::new(dir.clone());
+
+        assert_eq!(send_packet(&mut printer, 0x01, false, &[]), (0x81, 0x00));
+        send_packet(&mut printer, 0x04, false, &[0xFF; BAND_SIZE]);
+        assert_eq!(send_packet(&mut printer, 0x0F, false, &[]), (0x81, 0x08));
+
+        send_packet(&mut printer, 0x02, false, &[0x01, 0x00, 0xE4, 0x40]);
+
+        let (_, status) = send_packet(&mut printer, 0x0F, false, &[]);
+        assert!(PrinterStatus::from_bits_truncate(status).contains(PrinterStatus::Printing));
+
+        for _ in 0..8 {
+            send_packet(&mut printer, 0x0F, false, &[]);
+        }
+        assert_eq!(printer.status, PrinterStatus::empty());
+
+        // The sheet left in the printer is saved there
+        drop(printer);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn checksum_error() {
+        let mut printer = Printer::new(std::env::temp_dir());
+
+        for byte in [0x88, 0x33, 0x0F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00] {
+            printer.receive(byte);
+        }
+
+        assert_eq!(printer.status, PrinterStatus::ChecksumError);
+    }
+
+    #[test]
+    fn print_image() {
+        let dir = output_dir("print");
+        let mut printer = Printer::new(dir.clone());
+
+        send_packet(&mut printer, 0x01, false, &[]);
+
+        // First band black, second band compressed and dark gray
+        send_packet(&mut printer, 0x04, false, &[0xFF; BAND_SIZE]);
+        let mut compressed = vec![];
+        for _ in 0..BAND_SIZE / 2 {
+            compressed.extend_from_slice(&[0x01, 0xFF, 0x00]);
+        }
+        send_packet(&mut printer, 0x04, true, &compressed);
+        send_packet(&mut printer, 0x04, false, &[]);
+
+        // One sheet, one margin unit after, inverted palette
+        send_packet(&mut printer, 0x02, false, &[0x01, 0x01, 0x1B, 0x40]);
+
+        let img = image::open(dir.join("print_001.png")).unwrap().to_rgba8();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(img.width(), 160);
+        assert_eq!(img.height(), 2 * 16 + 8);
+
+        assert_eq!(img.get_pixel(0, 0).0, [255, 255, 255, 255]);
+        assert_eq!(img.get_pixel(159, 16).0, [84, 84, 84, 255]);
+        assert_eq!(img.get_pixel(0, 39).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn pending_sheet_saved() {
+        let dir = output_dir("pending");
+        let mut printer = Printer::new(dir.clone());
+
+        // No margin after, the paper stays in the printer
+        send_packet(&mut printer, 0x01, false, &[]);
+        send_packet(&mut printer, 0x04, false, &[0xFF; BAND_SIZE]);
+        send_packet(&mut printer, 0x02, false, &[0x01, 0x00, 0xE4, 0x40]);
+        assert!(!dir.join("print_001.png").exists());
+
+        // Saved on reset, then on unplug
+        printer.reset();
+        assert!(dir.join("print_001.png").exists());
+
+        send_packet(&mut printer, 0x04, false, &[0xFF; BAND_SIZE]);
+        send_packet(&mut printer, 0x02, false, &[0x01, 0x00, 0xE4, 0x40]);
+        drop(printer);
+        let saved = dir.join("print_002.png").exists();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert!(saved);
+    }
+}
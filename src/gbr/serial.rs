@@ -1,11 +1,21 @@
+use std::collections::VecDeque;
+
 use crate::gbr::interrupts::InterruptType;
 
-use super::{interrupts::InterruptHandler, GbError};
+use super::{
+    interrupts::InterruptHandler,
+    link::{SerialPeer, DISCONNECTED_BYTE},
+    GbError,
+};
 
 const SERIAL_TRANSFER_DATA_REG_ADDR: u16 = 0xFF01;
 const SERIAL_TRANSFER_CTRL_REG_ADDR: u16 = 0xFF02;
 
-#[derive(Default, PartialEq)]
+const CPU_FREQ: u64 = 4_194_304; // Hz
+const SERIAL_CLOCK_FREQ: u64 = 8_192; // Hz
+const CYCLES_PER_BIT: u64 = CPU_FREQ / SERIAL_CLOCK_FREQ;
+
+#[derive(Default, PartialEq, Clone)]
 enum ShiftClock {
     #[default]
     External,
@@ -18,9 +28,79 @@ pub struct Serial {
     transfer_start: bool,
     data: u8,
     out_buffer: Vec<u8>,
+
+    // Data sent to the peer for the current transfer
+    sent: bool,
+    // Byte being shifted in from the peer, None until the peer answered
+    in_data: Option<u8>,
+    bits_left: u8,
+    cycles_elapsed: u64,
+    peer: Option<Box<dyn SerialPeer>>,
+
+    // Bytes received from the peer since the last take
+    received: Vec<u8>,
+    // Bytes to receive instead of talking to the peer, None when not replaying
+    replay: Option<VecDeque<u8>>,
 }
 
 impl Serial {
+    pub fn set_peer(&mut self, peer: Option<Box<dyn SerialPeer>>) {
+        self.peer = peer;
+    }
+
+    /// Reset registers and transfer state, keeping the link cable plugged in.
+    pub fn reset(&mut self) {
+        let mut peer = self.peer.take();
+        if let Some(peer) = peer.as_mut() {
+            peer.reset();
+        }
+
+        *self = Self::default();
+        self.peer = peer;
+    }
+
+    /// Copy of the registers and transfer state, without the link cable.
+    pub fn snapshot(&self) -> Self {
+        Self {
+            shift_clock: self.shift_clock.clone(),
+            transfer_start: self.transfer_start,
+            data: self.data,
+            out_buffer: self.out_buffer.clone(),
+            sent: self.sent,
+            in_data: self.in_data,
+            bits_left: self.bits_left,
+            cycles_elapsed: self.cycles_elapsed,
+            peer: None,
+            received: vec![],
+            replay: None,
+        }
+    }
+
+    /// Restore a snapshot, keeping the link cable plugged in.
+    pub fn restore(&mut self, snapshot: &Serial) {
+        let peer = self.peer.take();
+        *self = snapshot.snapshot();
+        self.peer = peer;
+    }
+
+    /// Take the bytes from the peer instead of talking to it, the peer
+    /// already saw the transfers being replayed.
+    pub fn set_replaying(&mut self, replaying: bool) {
+        self.replay = replaying.then(VecDeque::new);
+    }
+
+    /// Queue a byte received from the peer at the same step before.
+    pub fn replay_byte(&mut self, byte: u8) {
+        if let Some(replay) = self.replay.as_mut() {
+            replay.push_back(byte);
+        }
+    }
+
+    /// Take the bytes received from the peer since the last call.
+    pub fn take_received(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.received)
+    }
+
     pub fn write(&mut self, addr: u16, value: u8) -> Result<(), GbError> {
         match addr {
             SERIAL_TRANSFER_DATA_REG_ADDR => self.data = value,
@@ -30,7 +110,12 @@ impl Serial {
                     ShiftClock::Internal
                 } else {
                     ShiftClock::External
-                }
+                };
+
+                self.sent = false;
+                self.in_data = None;
+                self.bits_left = 8;
+                self.cycles_elapsed = 0;
             }
             _ => {
                 return Err(GbError::IllegalOp(format!(
@@ -47,7 +132,8 @@ impl Serial {
         match addr {
             SERIAL_TRANSFER_DATA_REG_ADDR => Ok(self.data),
             SERIAL_TRANSFER_CTRL_REG_ADDR => {
-                let mut val = (self.transfer_start as u8) << 7;
+                // Unused bits always read as 1
+                let mut val = (self.transfer_start as u8) << 7 | 0b01111110;
                 if self.shift_clock == ShiftClock::Internal {
                     val += 1;
                 }
@@ -63,21 +149,237 @@ impl Serial {
         }
     }
 
-    pub fn step(&mut self, _cycles: u8, ir_handler: &mut InterruptHandler) {
-        // TODO: implement clock for data transfer
-        if self.transfer_start {
+    pub fn step(&mut self, cycles: u8, ir_handler: &mut InterruptHandler) {
+        self.cycles_elapsed += cycles as u64;
+
+        if !self.transfer_start {
+            // Nobody is listening on this side, a byte from the peer waits
+            // for the transfer to be enabled
+            if self.cycles_elapsed >= CYCLES_PER_BIT {
+                self.cycles_elapsed = 0;
+                if let Some(peer) = self.peer.as_mut().filter(|_| self.replay.is_none()) {
+                    peer.idle();
+                }
+            }
+            return;
+        }
+
+        match self.shift_clock {
+            ShiftClock::Internal => self.step_internal_clock(ir_handler),
+            ShiftClock::External => self.step_external_clock(ir_handler),
+        }
+    }
+
+    fn step_internal_clock(&mut self, ir_handler: &mut InterruptHandler) {
+        if !self.sent {
+            self.sent = true;
             self.out_buffer.push(self.data);
-            self.transfer_start = false;
-            ir_handler.set(InterruptType::Serial);
+            self.flush_out_buffer();
+
+            if let Some(peer) = self.peer.as_mut().filter(|_| self.replay.is_none()) {
+                peer.send(self.data);
+            }
+        }
+
+        // Bits are only shifted once the peer answered, a late answer
+        // delays the end of the transfer
+        if self.in_data.is_none() {
+            self.in_data = self.receive(|peer| match peer {
+                Some(peer) => peer.answer(),
+                None => Some(DISCONNECTED_BYTE),
+            });
+        }
+        let Some(in_data) = self.in_data else {
+            return;
+        };
+
+        while self.cycles_elapsed >= CYCLES_PER_BIT && self.bits_left > 0 {
+            self.cycles_elapsed -= CYCLES_PER_BIT;
+            self.bits_left -= 1;
+
+            let in_bit = (in_data >> self.bits_left) & 0b1;
+            self.data = self.data << 1 | in_bit;
+        }
+
+        if self.bits_left == 0 {
+            self.complete_transfer(ir_handler);
+        }
+    }
+
+    fn step_external_clock(&mut self, ir_handler: &mut InterruptHandler) {
+        // Bits are clocked by the peer, which tells when the byte is complete
+        let data = self.data;
+        let received = self.receive(|peer| peer?.poll(data));
+
+        if let Some(byte) = received {
+            self.data = byte;
+            self.complete_transfer(ir_handler);
+        }
+    }
+
+    // Byte from the peer, or the next replayed one while replaying
+    fn receive(
+        &mut self,
+        from_peer: impl FnOnce(Option<&mut Box<dyn SerialPeer>>) -> Option<u8>,
+    ) -> Option<u8> {
+        if let Some(replay) = self.replay.as_mut() {
+            return replay.pop_front();
         }
 
-        if self.out_buffer.len() > 0 && *self.out_buffer.last().unwrap() as char == '\n' {
-            log::info!(
-                "Serial out: {}",
-                std::str::from_utf8(&self.out_buffer).unwrap()
-            );
+        let byte = from_peer(self.peer.as_mut())?;
+        self.received.push(byte);
+        Some(byte)
+    }
+
+    fn complete_transfer(&mut self, ir_handler: &mut InterruptHandler) {
+        self.transfer_start = false;
+        self.sent = false;
+        self.in_data = None;
+        self.cycles_elapsed = 0;
+        ir_handler.set(InterruptType::Serial);
+    }
+
+    fn flush_out_buffer(&mut self) {
+        if self.out_buffer.last() == Some(&b'\n') {
+            log::info!("Serial out: {}", String::from_utf8_lossy(&self.out_buffer));
 
             self.out_buffer.clear();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::gbr::{
+        interrupts::{InterruptHandler, InterruptType},
+        link::{LoopbackPeer, SerialPeer},
+    };
+
+    use super::{Serial, CYCLES_PER_BIT};
+
+    // Answers 0x5A after being asked `delay` times
+    struct SlowPeer {
+        delay: u32,
+    }
+
+    impl SerialPeer for SlowPeer {
+        fn send(&mut self, _data: u8) {}
+
+        fn answer(&mut self) -> Option<u8> {
+            self.delay = self.delay.checked_sub(1)?;
+            (self.delay == 0).then_some(0x5A)
+        }
+
+        fn poll(&mut self, _data: u8) -> Option<u8> {
+            None
+        }
+    }
+
+    fn start_transfer(serial: &mut Serial, data: u8, internal_clock: bool) {
+        serial.write(0xFF01, data).unwrap();
+        serial
+            .write(0xFF02, 0b10000000 | internal_clock as u8)
+            .unwrap();
+    }
+
+    fn run(serial: &mut Serial, ir_handler: &mut InterruptHandler, cycles: u64) {
+        for _ in 0..cycles / 4 {
+            serial.step(4, ir_handler);
+        }
+    }
+
+    #[test]
+    fn internal_clock_timing() {
+        let mut serial = Serial::default();
+        let mut ir_handler = InterruptHandler::default();
+        ir_handler.write_ie(0xFF);
+
+        start_transfer(&mut serial, 0x00, true);
+
+        run(&mut serial, &mut ir_handler, CYCLES_PER_BIT);
+        assert_eq!(serial.read(0xFF01).unwrap(), 0b00000001);
+
+        run(&mut serial, &mut ir_handler, 6 * CYCLES_PER_BIT);
+        assert_eq!(serial.read(0xFF01).unwrap(), 0b01111111);
+        assert_eq!(serial.read(0xFF02).unwrap() & 0x80, 0x80);
+        assert!(!ir_handler.test(InterruptType::Serial));
+
+        run(&mut serial, &mut ir_handler, CYCLES_PER_BIT);
+
+        // No cable connected: all ones are shifted in
+        assert_eq!(serial.read(0xFF01).unwrap(), 0xFF);
+        assert_eq!(serial.read(0xFF02).unwrap() & 0x80, 0x00);
+        assert!(ir_handler.test(InterruptType::Serial));
+    }
+
+    #[test]
+    fn internal_clock_loopback() {
+        let mut serial = Serial::default();
+        serial.set_peer(Some(Box::<LoopbackPeer>::default()));
+        let mut ir_handler = InterruptHandler::default();
+
+        start_transfer(&mut serial, 0xA5, true);
+        run(&mut serial, &mut ir_handler, 8 * CYCLES_PER_BIT);
+
+        assert_eq!(serial.read(0xFF01).unwrap(), 0xA5);
+    }
+
+    #[test]
+    fn internal_clock_waits_for_answer() {
+        let mut serial = Serial::default();
+        serial.set_peer(Some(Box::new(SlowPeer { delay: 2000 })));
+        let mut ir_handler = InterruptHandler::default();
+        ir_handler.write_ie(0xFF);
+
+        start_transfer(&mut serial, 0xA5, true);
+        run(&mut serial, &mut ir_handler, 8 * CYCLES_PER_BIT);
+
+        assert_eq!(serial.read(0xFF01).unwrap(), 0xA5);
+        assert!(!ir_handler.test(InterruptType::Serial));
+
+        // All the bits are shifted in once the answer arrived
+        run(&mut serial, &mut ir_handler, 4000);
+
+        assert_eq!(serial.read(0xFF01).unwrap(), 0x5A);
+        assert!(ir_handler.test(InterruptType::Serial));
+    }
+
+    #[test]
+    fn external_clock_without_peer_never_completes() {
+        let mut serial = Serial::default();
+        let mut ir_handler = InterruptHandler::default();
+        ir_handler.write_ie(0xFF);
+
+        start_transfer(&mut serial, 0xA5, false);
+        run(&mut serial, &mut ir_handler, 64 * CYCLES_PER_BIT);
+
+        assert_eq!(serial.read(0xFF01).unwrap(), 0xA5);
+        assert_eq!(serial.read(0xFF02).unwrap() & 0x80, 0x80);
+        assert!(!ir_handler.test(InterruptType::Serial));
+    }
+
+    #[test]
+    fn replay_bypasses_peer() {
+        let mut serial = Serial::default();
+        serial.set_peer(Some(Box::new(SlowPeer { delay: 2000 })));
+        let mut ir_handler = InterruptHandler::default();
+
+        serial.set_replaying(true);
+        serial.replay_byte(0x5A);
+
+        start_transfer(&mut serial, 0xA5, true);
+        run(&mut serial, &mut ir_handler, 8 * CYCLES_PER_BIT);
+
+        // The replayed byte is shifted in without waiting for the peer
+        assert_eq!(serial.read(0xFF01).unwrap(), 0x5A);
+        assert!(serial.take_received().is_empty());
+
+        // Bytes from the peer are logged again once the replay is over
+        serial.set_replaying(false);
+        serial.set_peer(Some(Box::<LoopbackPeer>::default()));
+        start_transfer(&mut serial, 0xA5, true);
+        run(&mut serial, &mut ir_handler, 8 * CYCLES_PER_BIT);
+
+        assert_eq!(serial.take_received(), vec![0xA5]);
+    }
+}
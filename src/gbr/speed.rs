@@ -0,0 +1,175 @@
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+/// Frames per second of the Game Boy LCD, 4194304 Hz / 70224 cycles per frame.
+pub const FRAME_RATE: f64 = 59.7275;
+
+// Falling further behind than this drops the missed frames instead of catching up
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Speed {
+    Quarter,
+    Half,
+    #[default]
+    Normal,
+    Double,
+    Unlimited,
+}
+
+impl Speed {
+    pub const ALL: [Speed; 5] = [
+        Speed::Quarter,
+        Speed::Half,
+        Speed::Normal,
+        Speed::Double,
+        Speed::Unlimited,
+    ];
+
+    fn multiplier(self) -> Option<f64> {
+        match self {
+            Speed::Quarter => Some(0.25),
+            Speed::Half => Some(0.5),
+            Speed::Normal => Some(1.0),
+            Speed::Double => Some(2.0),
+            Speed::Unlimited => None,
+        }
+    }
+
+    /// Frames not sent to the renderer after each rendered one.
+    pub fn frame_skip(self) -> u8 {
+        match self {
+            Speed::Double => 1,
+            Speed::Unlimited => 7,
+            _ => 0,
+        }
+    }
+}
+
+impl Display for Speed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.multiplier() {
+            Some(multiplier) => write!(f, "{}%", multiplier * 100.0),
+            None => write!(f, "Unlimited"),
+        }
+    }
+}
+
+/// Keeps the emulated frames in step with the wall clock at the selected speed.
+pub struct Pacer {
+    speed: Speed,
+    fast_forward: bool,
+    // Frames emulated since `start` at the current speed
+    start: Instant,
+    frames: u32,
+}
+
+impl Default for Pacer {
+    fn default() -> Self {
+        Self {
+            speed: Speed::default(),
+            fast_forward: false,
+            start: Instant::now(),
+            frames: 0,
+        }
+    }
+}
+
+impl Pacer {
+    /// Speed in use, unlimited while fast forwarding.
+    pub fn speed(&self) -> Speed {
+        if self.fast_forward {
+            Speed::Unlimited
+        } else {
+            self.speed
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+        self.restart(Instant::now());
+    }
+
+    pub fn set_fast_forward(&mut self, fast_forward: bool) {
+        self.fast_forward = fast_forward;
+        self.restart(Instant::now());
+    }
+
+    fn restart(&mut self, now: Instant) {
+        self.start = now;
+        self.frames = 0;
+    }
+
+    /// Count a frame emulated at `now`, returns how long to wait before the next one.
+    pub fn frame_delay(&mut self, now: Instant) -> Option<Duration> {
+        let multiplier = self.speed().multiplier()?;
+
+        self.frames += 1;
+        let target =
+            self.start + Duration::from_secs_f64(self.frames as f64 / (FRAME_RATE * multiplier));
+
+        if target > now {
+            Some(target - now)
+        } else {
+            // After a pause or a slow frame, start again from now
+            if now - target > MAX_LAG {
+                self.restart(now);
+            }
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Pacer, Speed, FRAME_RATE};
+
+    #[test]
+    fn frame_pacing() {
+        let mut pacer = Pacer::default();
+        let start = Instant::now();
+        pacer.restart(start);
+
+        let frame = Duration::from_secs_f64(1.0 / FRAME_RATE);
+        let delay = pacer.frame_delay(start).unwrap();
+        assert!((delay.as_secs_f64() - frame.as_secs_f64()).abs() < 1e-6);
+
+        // Late by less than a frame, the next one waits less
+        let delay = pacer.frame_delay(start + frame + frame / 2).unwrap();
+        assert!((delay.as_secs_f64() - frame.as_secs_f64() / 2.0).abs() < 1e-6);
+
+        pacer.speed = Speed::Half;
+        pacer.restart(start);
+        let delay = pacer.frame_delay(start).unwrap();
+        assert!((delay.as_secs_f64() - 2.0 * frame.as_secs_f64()).abs() < 1e-6);
+
+        pacer.fast_forward = true;
+        assert_eq!(pacer.speed(), Speed::Unlimited);
+        assert_eq!(pacer.frame_delay(start), None);
+    }
+
+    #[test]
+    fn drops_frames_when_late() {
+        let mut pacer = Pacer::default();
+        let start = Instant::now();
+        pacer.restart(start);
+
+        let late = start + Duration::from_secs(1);
+        assert_eq!(pacer.frame_delay(late), None);
+
+        // Paced from the late frame on
+        let frame = Duration::from_secs_f64(1.0 / FRAME_RATE);
+        let delay = pacer.frame_delay(late).unwrap();
+        assert!((delay.as_secs_f64() - frame.as_secs_f64()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn speed_names() {
+        let names: Vec<String> = Speed::ALL.iter().map(|s| s.to_string()).collect();
+        assert_eq!(names, ["25%", "50%", "100%", "200%", "Unlimited"]);
+    }
+}
@@ -55,7 +55,7 @@ impl From<ClockSelect> for u8 {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Timer {
     divider: u8,
     counter: u8,
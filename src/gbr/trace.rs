@@ -0,0 +1,182 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use super::{bus::BusAccess, cpu::CPU, memory_map::BankedAddress};
+
+// Rotated files kept besides the active one, as <path>.1 (newest) to <path>.N
+const ROTATED_FILES: usize = 4;
+
+/// Only trace instructions in the `pc` range and/or in the given ROM bank.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TraceFilter {
+    pub pc: Option<RangeInclusive<u16>>,
+    pub bank: Option<u16>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, addr: &BankedAddress) -> bool {
+        self.pc.as_ref().is_none_or(|pc| pc.contains(&addr.addr))
+            && self.bank.is_none_or(|bank| bank == addr.bank)
+    }
+}
+
+/// Format the CPU state before executing the instruction at PC as
+/// "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02"
+pub fn trace_line(cpu: &CPU, bus: &dyn BusAccess) -> String {
+    let [a, f] = cpu.read_af().to_be_bytes();
+    let [b, c] = cpu.read_bc().to_be_bytes();
+    let [d, e] = cpu.read_de().to_be_bytes();
+    let [h, l] = cpu.read_hl().to_be_bytes();
+    let pc = cpu.read_pc();
+
+    let pc_mem: Vec<String> = (0..4)
+        .map(|i| format!("{:02X}", bus.read_byte(pc.wrapping_add(i)).unwrap_or(0xFF)))
+        .collect();
+
+    format!(
+        "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{}",
+        a,
+        f,
+        b,
+        c,
+        d,
+        e,
+        h,
+        l,
+        cpu.read_sp(),
+        pc,
+        pc_mem.join(",")
+    )
+}
+
+/// Execution trace written to a file, rotated once it grows past `max_size` bytes.
+pub struct Tracer {
+    path: PathBuf,
+    file: BufWriter<File>,
+    max_size: Option<u64>,
+    size: u64,
+    filter: TraceFilter,
+}
+
+impl Tracer {
+    pub fn new(path: PathBuf, max_size: Option<u64>, filter: TraceFilter) -> io::Result<Self> {
+        Ok(Self {
+            file: BufWriter::new(File::create(&path)?),
+            path,
+            max_size,
+            size: 0,
+            filter,
+        })
+    }
+
+    pub fn filter(&self) -> &TraceFilter {
+        &self.filter
+    }
+
+    pub fn write(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+
+        if self
+            .max_size
+            .is_some_and(|max| self.size > 0 && self.size + len > max)
+        {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+
+        Ok(())
+    }
+
+    /// Move the current trace to <path>.1, shifting older ones, and start a new file.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        for i in (1..ROTATED_FILES).rev() {
+            let from = rotated_path(&self.path, i);
+            if from.exists() {
+                std::fs::rename(from, rotated_path(&self.path, i + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, rotated_path(&self.path, 1))?;
+
+        self.file = BufWriter::new(File::create(&self.path)?);
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        self.file.flush().ok();
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::gbr::{game_boy::GameBoy, memory_map::BankedAddress};
+
+    use super::{rotated_path, trace_line, TraceFilter, Tracer};
+
+    fn trace_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("gbr_trace_{}_{}.log", name, std::process::id()))
+    }
+
+    #[test]
+    fn post_boot_line() {
+        let gb = GameBoy::with_program(&[0x00, 0xC3, 0x13, 0x02]);
+
+        assert_eq!(
+            trace_line(gb.cpu(), gb.bus()),
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0150 PCMEM:00,C3,13,02"
+        );
+    }
+
+    #[test]
+    fn filter() {
+        let filter = TraceFilter {
+            pc: Some(0x4000..=0x4FFF),
+            bank: Some(2),
+        };
+
+        assert!(filter.matches(&BankedAddress::new(2, 0x4100)));
+        assert!(!filter.matches(&BankedAddress::new(1, 0x4100)));
+        assert!(!filter.matches(&BankedAddress::new(2, 0x5000)));
+        assert!(TraceFilter::default().matches(&BankedAddress::new(7, 0x5000)));
+    }
+
+    #[test]
+    fn rotation() {
+        let path = trace_path("rotation");
+        let mut tracer = Tracer::new(path.clone(), Some(4), TraceFilter::default()).unwrap();
+
+        for line in ["one", "two", "three"] {
+            tracer.write(line).unwrap();
+        }
+        drop(tracer);
+
+        let read = |path: PathBuf| std::fs::read_to_string(&path).unwrap();
+        assert_eq!(read(path.clone()), "three\n");
+        assert_eq!(read(rotated_path(&path, 1)), "two\n");
+        assert_eq!(read(rotated_path(&path, 2)), "one\n");
+
+        for i in 1..=2 {
+            std::fs::remove_file(rotated_path(&path, i)).ok();
+        }
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,360 @@
+use std::{fmt::Display, sync::Mutex};
+
+use super::{
+    bus::{Bus, BusAccess, MemWrite},
+    interrupts::InterruptHandler,
+    joypad::Joypad,
+    GbError,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum WatchKind {
+    Read,
+    #[default]
+    Write,
+    Access,
+}
+
+/// Who set a watchpoint, each one only removes its own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchOwner {
+    Debugger,
+    Gdb,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemAccess {
+    Read,
+    Write,
+}
+
+/// Break on memory accesses in the `start..=end` address range, optionally
+/// only when `value` is read or written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+    pub value: Option<u8>,
+}
+
+impl Watchpoint {
+    pub fn matches(&self, addr: u16, value: u8, access: MemAccess) -> bool {
+        let kind_match = matches!(
+            (self.kind, access),
+            (WatchKind::Access, _)
+                | (WatchKind::Read, MemAccess::Read)
+                | (WatchKind::Write, MemAccess::Write)
+        );
+
+        kind_match
+            && (self.start..=self.end).contains(&addr)
+            && self.value.is_none_or(|v| v == value)
+    }
+}
+
+impl Display for Watchpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} {:#06X}", self.kind, self.start)?;
+
+        if self.end != self.start {
+            write!(f, "..={:#06X}", self.end)?;
+        }
+
+        if let Some(value) = self.value {
+            write!(f, " == {:#04X}", value)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchpointHit {
+    pub pc: u16,
+    pub addr: u16,
+    pub value: u8,
+    pub access: MemAccess,
+}
+
+impl Display for WatchpointHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} {:#04X} at {:#06X} from PC {:#06X}",
+            self.access, self.value, self.addr, self.pc
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct Watchpoints {
+    list: Vec<(WatchOwner, Watchpoint)>,
+    // Address of the instruction being executed
+    pc: u16,
+    // Reads only borrow the bus, so the pending hit needs interior mutability
+    hit: Mutex<Option<WatchpointHit>>,
+    last_hit: Option<WatchpointHit>,
+}
+
+impl Watchpoints {
+    pub fn add(&mut self, owner: WatchOwner, watchpoint: Watchpoint) {
+        if !self.list.contains(&(owner, watchpoint)) {
+            self.list.push((owner, watchpoint));
+        }
+    }
+
+    pub fn remove(&mut self, owner: WatchOwner, watchpoint: &Watchpoint) {
+        self.list.retain(|w| *w != (owner, *watchpoint));
+
+        // Drop a pending hit no remaining watchpoint accounts for
+        let hit = self.hit.get_mut().unwrap();
+        if hit.is_some_and(|hit| {
+            !self
+                .list
+                .iter()
+                .any(|(_, w)| w.matches(hit.addr, hit.value, hit.access))
+        }) {
+            *hit = None;
+        }
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    /// Record a hit if the access matches a watchpoint.
+    ///
+    /// Only the first hit is kept until it is taken.
+    pub fn check(&self, addr: u16, value: u8, access: MemAccess) {
+        if self.list.is_empty()
+            || !self
+                .list
+                .iter()
+                .any(|(_, w)| w.matches(addr, value, access))
+        {
+            return;
+        }
+
+        self.hit.lock().unwrap().get_or_insert(WatchpointHit {
+            pc: self.pc,
+            addr,
+            value,
+            access,
+        });
+    }
+
+    pub fn take_hit(&mut self) -> Option<WatchpointHit> {
+        let hit = self.hit.get_mut().unwrap().take();
+        if hit.is_some() {
+            self.last_hit = hit;
+        }
+
+        hit
+    }
+
+    pub fn last_hit(&self) -> Option<WatchpointHit> {
+        self.last_hit
+    }
+}
+
+/// Bus seen by the CPU, checking every access against the watchpoints and
+/// logging writes when enabled.
+pub struct WatchedBus<'a> {
+    bus: &'a mut Bus,
+}
+
+impl<'a> WatchedBus<'a> {
+    pub fn new(bus: &'a mut Bus) -> Self {
+        Self { bus }
+    }
+}
+
+impl<'a> BusAccess for WatchedBus<'a> {
+    fn read_byte(&self, addr: u16) -> Result<u8, GbError> {
+        let value = self.bus.read_byte(addr)?;
+        self.bus.watchpoints().check(addr, value, MemAccess::Read);
+
+        Ok(value)
+    }
+
+    fn write_byte(&mut self, addr: u16, value: u8) -> Result<(), GbError> {
+        let old = self
+            .bus
+            .logs_writes()
+            .then(|| self.bus.read_byte(addr).ok());
+
+        self.bus.write_byte(addr, value)?;
+        self.bus.watchpoints().check(addr, value, MemAccess::Write);
+
+        if let Some(old) = old {
+            self.bus.log_write(MemWrite { addr, old, value });
+        }
+
+        Ok(())
+    }
+
+    fn read_word(&self, addr: u16) -> Result<u16, GbError> {
+        let value = self.bus.read_word(addr)?;
+
+        let watchpoints = self.bus.watchpoints();
+        watchpoints.check(addr, value as u8, MemAccess::Read);
+        watchpoints.check(addr.wrapping_add(1), (value >> 8) as u8, MemAccess::Read);
+
+        Ok(value)
+    }
+
+    fn ir_handler(&self) -> &InterruptHandler {
+        self.bus.ir_handler()
+    }
+
+    fn ir_handler_mut(&mut self) -> &mut InterruptHandler {
+        self.bus.ir_handler_mut()
+    }
+
+    fn joypad(&self) -> &Joypad {
+        self.bus.joypad()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gbr::bus::{Bus, BusAccess};
+
+    use super::{MemAccess, WatchKind, WatchOwner, WatchedBus, Watchpoint, WatchpointHit};
+
+    fn bus() -> Bus {
+        Bus::from_rom(&vec![0; 0x8000]).unwrap()
+    }
+
+    #[test]
+    fn write_watchpoint_with_value() {
+        let mut bus = bus();
+        bus.watchpoints_mut().add(
+            WatchOwner::Debugger,
+            Watchpoint {
+                start: 0xC0A0,
+                end: 0xC0A0,
+                kind: WatchKind::Write,
+                value: Some(0),
+            },
+        );
+        bus.watchpoints_mut().set_pc(0x0150);
+
+        let mut watched = WatchedBus::new(&mut bus);
+        watched.write_byte(0xC0A0, 1).unwrap();
+        watched.read_byte(0xC0A0).unwrap();
+        assert_eq!(bus.watchpoints_mut().take_hit(), None);
+
+        WatchedBus::new(&mut bus).write_byte(0xC0A0, 0).unwrap();
+
+        assert_eq!(
+            bus.watchpoints_mut().take_hit(),
+            Some(WatchpointHit {
+                pc: 0x0150,
+                addr: 0xC0A0,
+                value: 0,
+                access: MemAccess::Write
+            })
+        );
+        assert_eq!(bus.watchpoints_mut().take_hit(), None);
+        assert!(bus.watchpoints().last_hit().is_some());
+    }
+
+    #[test]
+    fn read_watchpoint_range() {
+        let mut bus = bus();
+        bus.write_byte(0xC001, 0x12).unwrap();
+        bus.watchpoints_mut().add(
+            WatchOwner::Debugger,
+            Watchpoint {
+                start: 0xC001,
+                end: 0xC0FF,
+                kind: WatchKind::Read,
+                value: None,
+            },
+        );
+
+        // Unwatched accesses do not trigger
+        bus.read_byte(0xC001).unwrap();
+        WatchedBus::new(&mut bus).read_byte(0xC000).unwrap();
+        assert_eq!(bus.watchpoints_mut().take_hit(), None);
+
+        // Second byte of a word read
+        WatchedBus::new(&mut bus).read_word(0xC000).unwrap();
+
+        let hit = bus.watchpoints_mut().take_hit().unwrap();
+        assert_eq!((hit.addr, hit.value), (0xC001, 0x12));
+    }
+
+    #[test]
+    fn remove_clears_pending_hit() {
+        let mut bus = bus();
+        let watchpoint = Watchpoint {
+            start: 0xC000,
+            end: 0xC000,
+            kind: WatchKind::Access,
+            value: None,
+        };
+        let other = Watchpoint {
+            kind: WatchKind::Write,
+            ..watchpoint
+        };
+        bus.watchpoints_mut().add(WatchOwner::Debugger, watchpoint);
+        bus.watchpoints_mut().add(WatchOwner::Debugger, other);
+
+        // Still matched by the write watchpoint
+        WatchedBus::new(&mut bus).write_byte(0xC000, 1).unwrap();
+        bus.watchpoints_mut()
+            .remove(WatchOwner::Debugger, &watchpoint);
+        assert!(bus.watchpoints_mut().take_hit().is_some());
+
+        WatchedBus::new(&mut bus).write_byte(0xC000, 2).unwrap();
+        bus.watchpoints_mut().remove(WatchOwner::Debugger, &other);
+        assert_eq!(bus.watchpoints_mut().take_hit(), None);
+    }
+
+    #[test]
+    fn owners() {
+        let mut bus = bus();
+        let watchpoint = Watchpoint {
+            start: 0xC000,
+            end: 0xC000,
+            kind: WatchKind::Write,
+            value: None,
+        };
+        bus.watchpoints_mut().add(WatchOwner::Debugger, watchpoint);
+        bus.watchpoints_mut().add(WatchOwner::Gdb, watchpoint);
+
+        // The same watchpoint set from the debugger is kept
+        bus.watchpoints_mut().remove(WatchOwner::Gdb, &watchpoint);
+        WatchedBus::new(&mut bus).write_byte(0xC000, 1).unwrap();
+        assert!(bus.watchpoints_mut().take_hit().is_some());
+
+        bus.watchpoints_mut()
+            .remove(WatchOwner::Debugger, &watchpoint);
+        WatchedBus::new(&mut bus).write_byte(0xC000, 2).unwrap();
+        assert_eq!(bus.watchpoints_mut().take_hit(), None);
+    }
+
+    #[test]
+    fn dma_oam_write() {
+        let mut bus = bus();
+        bus.write_byte(0xC000, 0x42).unwrap();
+        bus.watchpoints_mut().add(
+            WatchOwner::Debugger,
+            Watchpoint {
+                start: 0xFE00,
+                end: 0xFE9F,
+                kind: WatchKind::Access,
+                value: Some(0x42),
+            },
+        );
+
+        bus.write_byte(0xFF46, 0xC0).unwrap();
+        bus.step(4).unwrap();
+
+        let hit = bus.watchpoints_mut().take_hit().unwrap();
+        assert_eq!((hit.addr, hit.access), (0xFE00, MemAccess::Write));
+    }
+}
@@ -11,20 +11,176 @@ mod debugger;
 mod gbr;
 
 use debugger::debugger_app::DebuggerApp;
+use debugger::disassembler::Disassembly;
+use debugger::symbols::Symbols;
 use gbr::game_boy::GameBoy;
+use gbr::gdb::GdbStub;
+use gbr::link::{LoopbackPeer, SerialPeer, TcpPeer};
+use gbr::movie::Movie;
+use gbr::printer::Printer;
+use gbr::trace::{TraceFilter, Tracer};
+
+// Config file holding the keyboard and gamepad bindings and the user colour palettes
+const CONFIG_ENV: &str = "GBR_CONFIG";
+const DEFAULT_CONFIG: &str = "gbr.toml";
+
+// Link cable configuration: one instance listens, the other connects to it.
+// With loopback the serial output is wired back to the input.
+const LINK_LISTEN_ENV: &str = "GBR_LINK_LISTEN";
+const LINK_CONNECT_ENV: &str = "GBR_LINK_CONNECT";
+const LINK_LOOPBACK_ENV: &str = "GBR_LINK_LOOPBACK";
+// Game Boy Printer plugged in the link port, saving images to the given directory
+const PRINTER_ENV: &str = "GBR_PRINTER";
+
+// GDB remote serial protocol server address, e.g. 127.0.0.1:2345
+const GDB_ENV: &str = "GBR_GDB";
+
+// Input movie recorded from power on to a file, or played back from one
+const MOVIE_RECORD_ENV: &str = "GBR_MOVIE_RECORD";
+const MOVIE_PLAY_ENV: &str = "GBR_MOVIE_PLAY";
+
+// Execution trace file, optionally rotated past a size in bytes and filtered
+// by a hex PC range ("0100-3FFF") and ROM bank
+const TRACE_ENV: &str = "GBR_TRACE";
+const TRACE_MAX_SIZE_ENV: &str = "GBR_TRACE_MAX_SIZE";
+const TRACE_PC_ENV: &str = "GBR_TRACE_PC";
+const TRACE_BANK_ENV: &str = "GBR_TRACE_BANK";
+
+fn tracer() -> Option<Tracer> {
+    let path = std::env::var(TRACE_ENV).ok()?;
+    let parse_hex = |text: &str| u16::from_str_radix(text.trim(), 16).ok();
+
+    let max_size = std::env::var(TRACE_MAX_SIZE_ENV)
+        .ok()
+        .and_then(|size| size.parse().ok());
+
+    let pc = std::env::var(TRACE_PC_ENV).ok().and_then(|range| {
+        let (start, end) = range.split_once('-')?;
+        Some(parse_hex(start)?..=parse_hex(end)?)
+    });
+
+    let bank = std::env::var(TRACE_BANK_ENV)
+        .ok()
+        .and_then(|bank| parse_hex(&bank));
+
+    log::info!("Tracing execution to {}", path);
+    Tracer::new(PathBuf::from(path), max_size, TraceFilter { pc, bank })
+        .inspect_err(|e| log::error!("{}", e))
+        .ok()
+}
+
+fn start_movie(gb: &mut GameBoy) {
+    let result = if let Ok(path) = std::env::var(MOVIE_PLAY_ENV) {
+        log::info!("Playing back movie {}", path);
+        Movie::load(&PathBuf::from(path)).and_then(|movie| gb.play_movie(movie))
+    } else if let Ok(path) = std::env::var(MOVIE_RECORD_ENV) {
+        log::info!("Recording movie to {}", path);
+        gb.record_movie(PathBuf::from(path))
+    } else {
+        return;
+    };
+
+    if let Err(e) = result {
+        log::error!("{}", e);
+    }
+}
+
+// Symbol file next to the cartridge ROM, e.g. game.sym for game.gb
+fn symbols(cart_rom_filename: Option<&PathBuf>) -> Symbols {
+    let Some(path) = cart_rom_filename.map(|rom| rom.with_extension("sym")) else {
+        return Symbols::default();
+    };
+
+    if !path.exists() {
+        return Symbols::default();
+    }
+
+    match Symbols::load(&path) {
+        Ok(symbols) => {
+            log::info!("Loaded {} symbols from {:?}", symbols.len(), path);
+            symbols
+        }
+        Err(e) => {
+            log::error!("Failed to load {:?}: {}", path, e);
+            Symbols::default()
+        }
+    }
+}
+
+fn gdb_stub() -> Option<GdbStub> {
+    let addr = std::env::var(GDB_ENV).ok()?;
+
+    GdbStub::listen(addr)
+        .inspect(|gdb| log::info!("GDB server listening on {:?}", gdb.local_addr()))
+        .inspect_err(|e| log::error!("{}", e))
+        .ok()
+}
+
+fn link_cable_peer() -> Option<Box<dyn SerialPeer>> {
+    let peer = if let Ok(addr) = std::env::var(LINK_LISTEN_ENV) {
+        TcpPeer::listen(addr)
+            .inspect(|peer| log::info!("Link cable listening on {:?}", peer.local_addr()))
+    } else if let Ok(addr) = std::env::var(LINK_CONNECT_ENV) {
+        log::info!("Link cable connecting to {}", addr);
+        TcpPeer::connect(addr)
+    } else if std::env::var(LINK_LOOPBACK_ENV).is_ok() {
+        return Some(Box::<LoopbackPeer>::default());
+    } else if let Ok(dir) = std::env::var(PRINTER_ENV) {
+        log::info!("Game Boy Printer saving images to {}", dir);
+        return Some(Box::new(Printer::new(PathBuf::from(dir))));
+    } else {
+        return None;
+    };
+
+    match peer {
+        Ok(peer) => Some(Box::new(peer)),
+        Err(e) => {
+            log::error!("{}", e);
+            None
+        }
+    }
+}
+
+// Write the RGBDS disassembly of a ROM to a file, or to stdout
+fn disassemble(rom: PathBuf, output: Option<PathBuf>) -> std::io::Result<()> {
+    let rom = std::fs::read(rom)?;
+    let asm = Disassembly::new(rom.into()).to_rgbds();
+
+    match output {
+        Some(output) => std::fs::write(output, asm),
+        None => std::io::Write::write_all(&mut std::io::stdout(), asm.as_bytes()),
+    }
+}
 
 fn main() {
     dotenv::dotenv().ok();
 
+    if std::env::args().nth(1).as_deref() == Some("disasm") {
+        let Some(rom) = std::env::args().nth(2).map(PathBuf::from) else {
+            eprintln!("Usage: gb-r disasm <rom> [output.asm]");
+            std::process::exit(1);
+        };
+
+        if let Err(e) = disassemble(rom, std::env::args().nth(3).map(PathBuf::from)) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let boot_rom_filename = std::env::args().nth(1).and_then(|p| Some(PathBuf::from(p)));
     let cart_rom_filename = std::env::args().nth(2).and_then(|p| Some(PathBuf::from(p)));
 
-    let app = DebuggerApp::new();
+    let config = std::env::var(CONFIG_ENV).unwrap_or(DEFAULT_CONFIG.to_string());
+    let app = DebuggerApp::new(&PathBuf::from(config));
+    let symbols = symbols(cart_rom_filename.as_ref());
+
+    let mut gb = GameBoy::new(boot_rom_filename, cart_rom_filename);
+    gb.set_serial_peer(link_cable_peer());
+    gb.set_tracer(tracer());
+    start_movie(&mut gb);
 
-    let gb_emu = Arc::new(RwLock::new(GameBoy::new(
-        boot_rom_filename,
-        cart_rom_filename,
-    )));
+    let gb_emu = Arc::new(RwLock::new(gb));
 
-    app.run(gb_emu).unwrap();
+    app.run(gb_emu, symbols, gdb_stub()).unwrap();
 }